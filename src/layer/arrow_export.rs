@@ -0,0 +1,174 @@
+//! Export of layer triples to Apache Arrow record batches.
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, StringArray, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+
+use super::dictionary::{DynValue, TypedDictEntryExt};
+use super::layer::*;
+
+/// The maximum number of rows in a record batch produced by `Layer::to_arrow_batches`.
+pub const ARROW_BATCH_SIZE: usize = 8192;
+
+/// The schema of the record batches produced by `Layer::to_arrow_batches`.
+///
+/// The subject, predicate and object columns are u64 ids, or strings
+/// if `resolve` is true.
+pub fn arrow_triple_schema(resolve: bool) -> SchemaRef {
+    let datatype = if resolve {
+        DataType::Utf8
+    } else {
+        DataType::UInt64
+    };
+
+    Arc::new(Schema::new(vec![
+        Field::new("subject", datatype.clone(), false),
+        Field::new("predicate", datatype.clone(), false),
+        Field::new("object", datatype, false),
+    ]))
+}
+
+pub(crate) fn arrow_triple_batches<'a, L: Layer + ?Sized>(
+    layer: &'a L,
+    resolve: bool,
+) -> Box<dyn Iterator<Item = RecordBatch> + 'a> {
+    let schema = arrow_triple_schema(resolve);
+    let mut triples = layer.triples();
+
+    Box::new(std::iter::from_fn(move || {
+        let chunk: Vec<IdTriple> = triples.by_ref().take(ARROW_BATCH_SIZE).collect();
+        if chunk.is_empty() {
+            return None;
+        }
+
+        let columns: Vec<ArrayRef> = if resolve {
+            vec![
+                Arc::new(StringArray::from(
+                    chunk
+                        .iter()
+                        .map(|t| layer.id_subject(t.subject).unwrap())
+                        .collect::<Vec<_>>(),
+                )),
+                Arc::new(StringArray::from(
+                    chunk
+                        .iter()
+                        .map(|t| layer.id_predicate(t.predicate).unwrap())
+                        .collect::<Vec<_>>(),
+                )),
+                Arc::new(StringArray::from(
+                    chunk
+                        .iter()
+                        .map(|t| object_to_string(layer.id_object(t.object).unwrap()))
+                        .collect::<Vec<_>>(),
+                )),
+            ]
+        } else {
+            vec![
+                Arc::new(UInt64Array::from(
+                    chunk.iter().map(|t| t.subject).collect::<Vec<_>>(),
+                )),
+                Arc::new(UInt64Array::from(
+                    chunk.iter().map(|t| t.predicate).collect::<Vec<_>>(),
+                )),
+                Arc::new(UInt64Array::from(
+                    chunk.iter().map(|t| t.object).collect::<Vec<_>>(),
+                )),
+            ]
+        };
+
+        Some(
+            RecordBatch::try_new(schema.clone(), columns)
+                .expect("columns should match the triple schema"),
+        )
+    }))
+}
+
+/// Renders an object as a string. Values are rendered in their lexical
+/// form, or as hex for datatypes stored in a binary encoding.
+fn object_to_string(object: ObjectType) -> String {
+    match object {
+        ObjectType::Node(node) => node,
+        ObjectType::Value(entry) => match entry.to_dyn() {
+            DynValue::String(s) => s,
+            DynValue::UInt32(v) => v.to_string(),
+            DynValue::Int32(v) => v.to_string(),
+            DynValue::UInt64(v) => v.to_string(),
+            DynValue::Int64(v) => v.to_string(),
+            DynValue::Float32(v) => xsd_float_lexical(v),
+            DynValue::Float64(v) => xsd_float_lexical(v),
+            DynValue::Boolean(v) => v.to_string(),
+            DynValue::Decimal(s) | DynValue::BigInt(s) | DynValue::Lexical(_, s) => s,
+            DynValue::Other(_, bytes) => hex::encode(bytes),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::*;
+    use crate::storage::memory::*;
+    use tdb_succinct::TdbDataType;
+
+    #[tokio::test]
+    async fn export_layer_to_arrow_batches() {
+        let files = base_layer_memory_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+        for i in 0..10_000 {
+            builder.add_value_triple(ValueTriple::new_node(
+                &format!("s{}", i / 3),
+                "p",
+                &format!("o{}", i),
+            ));
+        }
+        builder.add_value_triple(ValueTriple::new_string_value("s0", "name", "hello"));
+        builder.commit().await.unwrap();
+
+        let layer = BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+            .await
+            .unwrap();
+
+        let batches: Vec<_> = layer.to_arrow_batches(false).collect();
+        assert_eq!(2, batches.len());
+        assert!(batches
+            .iter()
+            .all(|b| b.schema() == arrow_triple_schema(false)));
+        assert_eq!(
+            layer.triple_count(),
+            batches.iter().map(|b| b.num_rows()).sum::<usize>()
+        );
+
+        let resolved: Vec<_> = layer.to_arrow_batches(true).collect();
+        assert!(resolved
+            .iter()
+            .all(|b| b.schema() == arrow_triple_schema(true)));
+        assert_eq!(
+            layer.triple_count(),
+            resolved.iter().map(|b| b.num_rows()).sum::<usize>()
+        );
+
+        let objects = resolved[0]
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert!(objects.iter().any(|o| o == Some("hello")));
+    }
+
+    #[test]
+    fn special_floats_use_lexical_form() {
+        assert_eq!(
+            "NaN",
+            object_to_string(ObjectType::Value(f64::make_entry(&f64::NAN)))
+        );
+        assert_eq!(
+            "-INF",
+            object_to_string(ObjectType::Value(f32::make_entry(&f32::NEG_INFINITY)))
+        );
+        assert_eq!(
+            "1.5",
+            object_to_string(ObjectType::Value(f64::make_entry(&1.5)))
+        );
+    }
+}
@@ -8,6 +8,37 @@ use super::layer::*;
 use crate::{chrono_log, storage::*};
 use tdb_succinct::util::{heap_sorted_iter, stream_iter_ok};
 use tdb_succinct::*;
+use thiserror::Error;
+
+/// Policy for handling non-finite floating point values when building a value dictionary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FloatPolicy {
+    /// Store NaN and infinite floats like any other value.
+    #[default]
+    Permissive,
+    /// Reject NaN and infinite floats.
+    Strict,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum BuilderError {
+    #[error("non-finite float value rejected by strict float policy")]
+    NonFinite,
+}
+
+impl From<BuilderError> for io::Error {
+    fn from(err: BuilderError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+fn value_is_finite(value: &TypedDictEntry) -> bool {
+    match value.datatype() {
+        Datatype::Float32 => value.as_val::<f32, f32>().is_finite(),
+        Datatype::Float64 => value.as_val::<f64, f64>().is_finite(),
+        _ => true,
+    }
+}
 
 pub struct DictionarySetFileBuilder<F: 'static + FileLoad + FileStore> {
     node_files: DictionaryFiles<F>,
@@ -16,6 +47,8 @@ pub struct DictionarySetFileBuilder<F: 'static + FileLoad + FileStore> {
     node_dictionary_builder: StringDictBufBuilder<BytesMut, BytesMut>,
     predicate_dictionary_builder: StringDictBufBuilder<BytesMut, BytesMut>,
     value_dictionary_builder: TypedDictBufBuilder<BytesMut, BytesMut, BytesMut, BytesMut>,
+    float_policy: FloatPolicy,
+    last_node: Option<(Bytes, u64)>,
 }
 
 impl<F: 'static + FileLoad + FileStore> DictionarySetFileBuilder<F> {
@@ -41,26 +74,44 @@ impl<F: 'static + FileLoad + FileStore> DictionarySetFileBuilder<F> {
             node_dictionary_builder,
             predicate_dictionary_builder,
             value_dictionary_builder,
+            float_policy: FloatPolicy::default(),
+            last_node: None,
         })
     }
 
+    /// Set the policy for handling non-finite float values.
+    pub fn set_float_policy(&mut self, policy: FloatPolicy) {
+        self.float_policy = policy;
+    }
+
     /// Add a node string.
     ///
     /// Panics if the given node string is not a lexical successor of the previous node string.
     pub fn add_node(&mut self, node: &str) -> u64 {
-        let id = self
-            .node_dictionary_builder
-            .add(Bytes::copy_from_slice(node.as_bytes()));
-
-        id
+        self.add_node_bytes(Bytes::copy_from_slice(node.as_bytes()))
     }
 
     pub fn add_node_bytes(&mut self, node: Bytes) -> u64 {
-        let id = self.node_dictionary_builder.add(node);
+        let id = self.node_dictionary_builder.add(node.clone());
+        self.last_node = Some((node, id));
 
         id
     }
 
+    /// Add a node string, or return the existing id if it was the last node added.
+    ///
+    /// Returns the id along with whether a new entry was inserted.
+    /// Panics if the given node string is neither equal to nor a lexical successor of the previous node string.
+    pub fn intern_node(&mut self, node: &str) -> (u64, bool) {
+        if let Some((last, id)) = self.last_node.as_ref() {
+            if last.as_ref() == node.as_bytes() {
+                return (*id, false);
+            }
+        }
+
+        (self.add_node(node), true)
+    }
+
     /// Add a predicate string.
     ///
     /// Panics if the given predicate string is not a lexical successor of the previous node string.
@@ -80,11 +131,23 @@ impl<F: 'static + FileLoad + FileStore> DictionarySetFileBuilder<F> {
 
     /// Add a value string.
     ///
-    /// Panics if the given value string is not a lexical successor of the previous value string.
+    /// Panics if the given value string is not a lexical successor of the previous value string, or if the value is rejected by the float policy.
     pub fn add_value(&mut self, value: TypedDictEntry) -> u64 {
+        self.try_add_value(value)
+            .expect("value rejected by float policy")
+    }
+
+    /// Add a value string, checking it against the float policy.
+    ///
+    /// Panics if the given value string is not a lexical successor of the previous value string.
+    pub fn try_add_value(&mut self, value: TypedDictEntry) -> Result<u64, BuilderError> {
+        if self.float_policy == FloatPolicy::Strict && !value_is_finite(&value) {
+            return Err(BuilderError::NonFinite);
+        }
+
         let id = self.value_dictionary_builder.add(value);
 
-        id
+        Ok(id)
     }
 
     /// Add nodes from an iterable.
@@ -176,16 +239,45 @@ impl<F: 'static + FileLoad + FileStore> DictionarySetFileBuilder<F> {
         ids
     }
 
+    /// Add values from an iterator of datatypes and their lexical bytes.
+    ///
+    /// This is useful when the values were read from another store
+    /// and are already in their lexical form. Panics if the values
+    /// are not in order, or if a value is rejected by the float policy.
+    pub fn add_values_raw<I: Iterator<Item = (Datatype, Bytes)>>(&mut self, values: I) -> Vec<u64> {
+        values
+            .map(|(datatype, value)| {
+                self.add_value(TypedDictEntry::new(
+                    datatype,
+                    SizedDictEntry::new(vec![value]),
+                ))
+            })
+            .collect()
+    }
+
     pub async fn finalize(self) -> io::Result<()> {
-        let (mut node_offsets_buf, mut node_data_buf) = self.node_dictionary_builder.finalize();
-        let (mut predicate_offsets_buf, mut predicate_data_buf) =
-            self.predicate_dictionary_builder.finalize();
+        let node_dictionary_builder = self.node_dictionary_builder;
+        let predicate_dictionary_builder = self.predicate_dictionary_builder;
+        let value_dictionary_builder = self.value_dictionary_builder;
+
+        // building the dictionaries is cpu-bound, so we keep it off the executor threads
+        let (node_bufs, predicate_bufs, value_bufs) = tokio::task::spawn_blocking(move || {
+            (
+                node_dictionary_builder.finalize(),
+                predicate_dictionary_builder.finalize(),
+                value_dictionary_builder.finalize(),
+            )
+        })
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let (mut node_offsets_buf, mut node_data_buf) = node_bufs;
+        let (mut predicate_offsets_buf, mut predicate_data_buf) = predicate_bufs;
         let (
             mut value_types_present_buf,
             mut value_type_offsets_buf,
             mut value_offsets_buf,
             mut value_data_buf,
-        ) = self.value_dictionary_builder.finalize();
+        ) = value_bufs;
 
         self.node_files
             .write_all_from_bufs(&mut node_data_buf, &mut node_offsets_buf)
@@ -363,6 +455,28 @@ impl<F: 'static + FileLoad + FileStore> TripleFileBuilder<F> {
     }
 }
 
+/// Check that the given triples are in strictly ascending order.
+///
+/// `TripleFileBuilder` panics on out-of-order input, so this can be
+/// used to check untrusted input beforehand. On the first violation,
+/// the offending adjacent pair is returned.
+pub fn validate_triple_order<I: Iterator<Item = IdTriple>>(
+    mut iter: I,
+) -> Result<(), (IdTriple, IdTriple)> {
+    let mut last = match iter.next() {
+        Some(triple) => triple,
+        None => return Ok(()),
+    };
+    for triple in iter {
+        if triple <= last {
+            return Err((last, triple));
+        }
+        last = triple;
+    }
+
+    Ok(())
+}
+
 const SINGLE_SORT_LIMIT: u64 = 0x8000_0000;
 pub async fn build_object_index_from_direct_files<
     FLoad: 'static + FileLoad,
@@ -552,3 +666,88 @@ pub async fn build_indexes<FLoad: 'static + FileLoad, F: 'static + FileLoad + Fi
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::*;
+
+    #[test]
+    fn validate_ordered_triples() {
+        let triples = vec![
+            IdTriple::new(1, 1, 1),
+            IdTriple::new(1, 1, 2),
+            IdTriple::new(1, 2, 1),
+            IdTriple::new(2, 1, 1),
+        ];
+
+        assert_eq!(Ok(()), validate_triple_order(triples.into_iter()));
+        assert_eq!(Ok(()), validate_triple_order(std::iter::empty()));
+    }
+
+    #[test]
+    fn validate_out_of_order_triples() {
+        let triples = vec![
+            IdTriple::new(1, 1, 1),
+            IdTriple::new(2, 1, 1),
+            IdTriple::new(1, 3, 1),
+            IdTriple::new(3, 1, 1),
+        ];
+
+        assert_eq!(
+            Err((IdTriple::new(2, 1, 1), IdTriple::new(1, 3, 1))),
+            validate_triple_order(triples.into_iter())
+        );
+    }
+
+    async fn build_value_dictionary(
+        add: impl FnOnce(&mut DictionarySetFileBuilder<MemoryBackedStore>),
+    ) -> Vec<Bytes> {
+        let files = base_layer_memory_files();
+        let mut builder = DictionarySetFileBuilder::from_files(
+            files.node_dictionary_files,
+            files.predicate_dictionary_files,
+            files.value_dictionary_files.clone(),
+        )
+        .await
+        .unwrap();
+        add(&mut builder);
+        builder.finalize().await.unwrap();
+
+        let value_files = files.value_dictionary_files;
+        vec![
+            value_files.types_present_file.map().await.unwrap(),
+            value_files.type_offsets_file.map().await.unwrap(),
+            value_files.blocks_file.map().await.unwrap(),
+            value_files.offsets_file.map().await.unwrap(),
+        ]
+    }
+
+    #[tokio::test]
+    async fn add_values_raw_matches_add_values() {
+        let mut values = vec![
+            String::make_entry(&"bar"),
+            String::make_entry(&"foo"),
+            u32::make_entry(&42),
+            i64::make_entry(&-7),
+            f64::make_entry(&1.5),
+        ];
+        values.sort();
+        let raw: Vec<_> = values
+            .iter()
+            .map(|v| (v.datatype(), v.to_bytes()))
+            .collect();
+
+        let expected_ids: Vec<u64> = (1..=values.len() as u64).collect();
+        let from_entries = build_value_dictionary(|builder| {
+            assert_eq!(expected_ids, builder.add_values(values));
+        })
+        .await;
+        let from_raw = build_value_dictionary(|builder| {
+            assert_eq!(expected_ids, builder.add_values_raw(raw.into_iter()));
+        })
+        .await;
+
+        assert_eq!(from_entries, from_raw);
+    }
+}
@@ -0,0 +1,1462 @@
+//! Inspection helpers for the dictionaries a layer is built from.
+//!
+//! These only rely on the public lookup methods of the dictionaries,
+//! so they work on any dictionary regardless of how it was loaded.
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::stream::{self, Stream, StreamExt};
+use itertools::Itertools;
+use rug::Integer;
+use std::io;
+use std::string::FromUtf8Error;
+use tdb_succinct::tfc::block::IdLookupResult;
+use tdb_succinct::*;
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+
+use crate::storage::FileLoad;
+
+/// How string lookups treat entries that are not valid UTF-8.
+///
+/// A layer is given its mode when it is loaded. Neither mode panics
+/// on a corrupt dictionary.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Utf8Mode {
+    /// Entries that are not valid UTF-8 are treated as missing.
+    #[default]
+    Strict,
+    /// Invalid UTF-8 sequences are replaced with the replacement character.
+    Lenient,
+}
+
+/// Extra lookup methods for string dictionaries.
+pub trait StringDictExt {
+    /// Returns the string with the given id, or an error if it is not valid UTF-8.
+    ///
+    /// Unlike `get`, this does not panic on a corrupt dictionary.
+    fn try_get(&self, id: usize) -> Option<Result<String, FromUtf8Error>>;
+
+    /// Returns the string with the given id, replacing invalid UTF-8 sequences.
+    fn get_lossy(&self, id: usize) -> Option<String>;
+
+    /// Returns the string with the given id, handling invalid UTF-8 according to `mode`.
+    fn get_with_mode(&self, id: usize, mode: Utf8Mode) -> Option<String>;
+
+    /// Returns the first entry of this dictionary, reading only the first block.
+    fn first(&self) -> Option<SizedDictEntry>;
+
+    /// Returns the last entry of this dictionary, reading only the last block.
+    fn last(&self) -> Option<SizedDictEntry>;
+
+    /// Returns the ids of the entries surrounding the given string.
+    ///
+    /// The first id is the largest id whose entry is below the
+    /// string, and the second is the smallest id whose entry is above
+    /// it. If the string is in the dictionary, both are its id.
+    fn id_bracket(&self, s: &str) -> (Option<u64>, Option<u64>);
+
+    /// Parses a dictionary from a single buffer as produced by `to_combined_bytes`.
+    ///
+    /// The buffer starts with the length of the offsets part as a
+    /// big-endian u64, followed by the offsets and then the data.
+    fn parse_combined(bytes: Bytes) -> io::Result<Self>
+    where
+        Self: Sized;
+
+    /// Encodes this dictionary as a single buffer that can be read back with `parse_combined`.
+    fn to_combined_bytes(&self) -> Bytes;
+}
+
+impl StringDictExt for StringDict {
+    fn try_get(&self, id: usize) -> Option<Result<String, FromUtf8Error>> {
+        self.entry(id)
+            .map(|entry| String::from_utf8(entry.to_bytes().to_vec()))
+    }
+
+    fn get_lossy(&self, id: usize) -> Option<String> {
+        self.entry(id)
+            .map(|entry| String::from_utf8_lossy(&entry.to_bytes()).into_owned())
+    }
+
+    fn get_with_mode(&self, id: usize, mode: Utf8Mode) -> Option<String> {
+        match mode {
+            Utf8Mode::Strict => self.try_get(id).and_then(Result::ok),
+            Utf8Mode::Lenient => self.get_lossy(id),
+        }
+    }
+
+    fn first(&self) -> Option<SizedDictEntry> {
+        match self.num_entries() {
+            0 => None,
+            _ => self.entry(1),
+        }
+    }
+
+    fn last(&self) -> Option<SizedDictEntry> {
+        match self.num_entries() {
+            0 => None,
+            num_entries => self.entry(num_entries),
+        }
+    }
+
+    fn id_bracket(&self, s: &str) -> (Option<u64>, Option<u64>) {
+        let result = self.id(s);
+
+        (result.floor(), result.ceil(self.num_entries()))
+    }
+
+    fn parse_combined(mut bytes: Bytes) -> io::Result<Self> {
+        if bytes.len() < 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "combined dictionary is missing its header",
+            ));
+        }
+        let offsets_len = bytes.get_u64() as usize;
+        if offsets_len > bytes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "combined dictionary header points past the end of the buffer",
+            ));
+        }
+        let offsets = bytes.split_to(offsets_len);
+
+        Ok(StringDict::parse(offsets, bytes))
+    }
+
+    fn to_combined_bytes(&self) -> Bytes {
+        // re-encoding the entries in order reproduces the original layout
+        let mut builder = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new());
+        for id in 1..=self.num_entries() {
+            builder.add(self.entry(id).unwrap().to_bytes());
+        }
+        let (offsets, data) = builder.finalize();
+
+        let mut result = BytesMut::with_capacity(8 + offsets.len() + data.len());
+        result.put_u64(offsets.len() as u64);
+        result.put(offsets);
+        result.put(data);
+
+        result.freeze()
+    }
+}
+
+/// Range helpers for dictionary id lookups.
+///
+/// A lookup either finds the entry, finds the closest entry before
+/// where it would have been, or finds nothing because it would come
+/// before the first entry.
+pub trait IdLookupResultExt {
+    /// Returns the largest id whose entry is less than or equal to the looked up entry.
+    fn floor(&self) -> Option<u64>;
+
+    /// Returns the smallest id whose entry is greater than or equal to the looked up entry.
+    ///
+    /// As the lookup result does not know the dictionary size, this
+    /// needs the amount of entries in the dictionary that was searched.
+    fn ceil(&self, num_entries: usize) -> Option<u64>;
+}
+
+impl IdLookupResultExt for IdLookupResult {
+    fn floor(&self) -> Option<u64> {
+        match self {
+            IdLookupResult::Found(id) => Some(*id),
+            IdLookupResult::Closest(id) => Some(*id),
+            IdLookupResult::NotFound => None,
+        }
+    }
+
+    fn ceil(&self, num_entries: usize) -> Option<u64> {
+        let id = match self {
+            IdLookupResult::Found(id) => *id,
+            IdLookupResult::Closest(id) => *id + 1,
+            IdLookupResult::NotFound => 1,
+        };
+
+        if id > num_entries as u64 {
+            None
+        } else {
+            Some(id)
+        }
+    }
+}
+
+/// Extra memory layout control for dictionary entries.
+pub trait SizedDictEntryExt {
+    /// Copies the parts of this entry into a single buffer if that is cheaper, or if there are more than `max_parts` parts.
+    ///
+    /// `optimize` only looks at whether the overhead of keeping the
+    /// parts apart outweighs their size. Bounding the amount of parts
+    /// as well bounds the work of walking the entry.
+    fn optimize_with_threshold(&mut self, max_parts: usize);
+}
+
+impl SizedDictEntryExt for SizedDictEntry {
+    fn optimize_with_threshold(&mut self, max_parts: usize) {
+        if self.0.len() <= max_parts {
+            self.optimize();
+            return;
+        }
+
+        let mut buf = BytesMut::with_capacity(self.0.iter().map(|part| part.len()).sum());
+        for part in self.0.iter() {
+            buf.extend_from_slice(part);
+        }
+        self.0 = vec![buf.freeze()];
+    }
+}
+
+/// The bytes given for a typed dictionary entry do not fit its datatype.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("expected {expected} bytes for {datatype:?} but got {actual}")]
+pub struct TypeMismatch {
+    pub datatype: Datatype,
+    pub expected: usize,
+    pub actual: usize,
+}
+
+/// Returns the size in bytes of every entry of the given datatype, if it has a fixed size.
+pub fn fixed_record_size(datatype: Datatype) -> Option<usize> {
+    datatype.record_size().map(|size| size as usize)
+}
+
+/// Returns whether entries of the given datatype are stored as their UTF-8 lexical form.
+fn is_string_like(datatype: Datatype) -> bool {
+    matches!(
+        datatype,
+        Datatype::LangString
+            | Datatype::AnyURI
+            | Datatype::Language
+            | Datatype::NormalizedString
+            | Datatype::Token
+            | Datatype::NMToken
+            | Datatype::Name
+            | Datatype::NCName
+            | Datatype::Notation
+            | Datatype::QName
+            | Datatype::ID
+            | Datatype::IDRef
+            | Datatype::Entity
+            | Datatype::AnySimpleType
+    )
+}
+
+/// Streams the blocks of a dictionary, reading one block at a time from the data file.
+///
+/// Only the offsets file is mapped. Each block is read by seeking to
+/// its start in the data file, so the data file is never loaded as a
+/// whole.
+pub async fn stream_sized_dict_blocks<F: 'static + FileLoad + Clone>(
+    offsets_file: F,
+    data_file: F,
+) -> io::Result<impl Stream<Item = io::Result<SizedDictBlock>> + Send> {
+    let offsets = MonotonicLogArray::from_logarray(LogArray::parse(offsets_file.map().await?)?);
+    let data_size = data_file.size().await?;
+    let num_blocks = if data_size == 0 { 0 } else { offsets.len() + 1 };
+
+    Ok(stream::iter(0..num_blocks).then(move |block_index| {
+        let start = if block_index == 0 {
+            0
+        } else {
+            offsets.entry(block_index - 1) as usize
+        };
+        // the last block runs up to the end of the data file
+        let end = if block_index == num_blocks - 1 {
+            data_size
+        } else {
+            offsets.entry(block_index) as usize
+        };
+        let data_file = data_file.clone();
+
+        async move {
+            let mut reader = data_file.open_read_from(start).await?;
+            let mut bytes = BytesMut::new();
+            bytes.resize(end - start, 0);
+            reader.read_exact(&mut bytes[..]).await?;
+
+            Ok(SizedDictBlock::parse(bytes.freeze()))
+        }
+    }))
+}
+
+/// Reads the trailing u64 of a dictionary data buffer.
+///
+/// Dictionary builders end the data buffer with the amount of
+/// entries as a big-endian u64. This is also the id offset that a
+/// dictionary chained after this one starts from. Returns None if the
+/// buffer is too short to contain the trailer.
+pub fn read_dict_id_offset(data: &Bytes) -> Option<u64> {
+    if data.len() < 8 {
+        return None;
+    }
+
+    Some((&data[data.len() - 8..]).get_u64())
+}
+
+/// Returns how many entries of a possibly truncated string dictionary can be read safely.
+///
+/// `offsets` and `data` are the buffers a `StringDict` is parsed
+/// from. Blocks are read in order, and each block's declared length
+/// is checked against the data that is left, so this does not panic
+/// on truncated data. See `complete_blocks` for when a block counts
+/// as complete.
+pub fn recoverable_dict_entries(offsets: Bytes, data: &Bytes) -> io::Result<usize> {
+    let offsets = MonotonicLogArray::from_logarray(LogArray::parse(offsets)?);
+
+    Ok(complete_blocks(&offsets, data)
+        .iter()
+        .map(|block| block.num_entries)
+        .sum())
+}
+
+/// The shape of a block as declared by its header.
+struct BlockExtent {
+    num_entries: usize,
+    record_size: Option<u8>,
+    len: usize,
+}
+
+/// Parses the header of the tfc block at the start of `data`.
+///
+/// Returns None if the header is malformed, or if the header or the
+/// entries it declares run past the end of `data`.
+fn block_extent(data: &[u8]) -> Option<BlockExtent> {
+    let control_word = *data.first()?;
+    let num_entries = (control_word & 0b111) as usize + 1;
+    let record_size = match control_word >> 3 {
+        0 => None,
+        3 => Some(4),
+        4 => Some(8),
+        5 => Some(16),
+        _ => return None,
+    };
+
+    let mut pos = 1;
+    let (head_len, len) = vbyte::decode(data.get(pos..)?).ok()?;
+    pos = pos.checked_add(len)?.checked_add(head_len as usize)?;
+    for _ in 1..num_entries {
+        let (shared, len) = vbyte::decode(data.get(pos..)?).ok()?;
+        pos += len;
+        let suffix_len = match record_size {
+            Some(record_size) => (record_size as u64).checked_sub(shared)?,
+            None => {
+                let (suffix_len, len) = vbyte::decode(data.get(pos..)?).ok()?;
+                pos += len;
+                suffix_len
+            }
+        };
+        pos = pos.checked_add(suffix_len as usize)?;
+    }
+
+    if pos > data.len() {
+        None
+    } else {
+        Some(BlockExtent {
+            num_entries,
+            record_size,
+            len: pos,
+        })
+    }
+}
+
+/// Returns the blocks at the start of a dictionary data buffer that are complete.
+///
+/// `block_ends` holds the end offset of every block but the last. A
+/// block is complete if its declared length fits in the remaining
+/// data and ends exactly at its recorded offset. The last block has
+/// no recorded offset, so it is only complete if it is directly
+/// followed by the entry count trailer, and that trailer agrees with
+/// the entries read. Reading stops at the first incomplete block.
+fn complete_blocks(block_ends: &MonotonicLogArray, data: &[u8]) -> Vec<BlockExtent> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut count = 0;
+    for block_index in 0..=block_ends.len() {
+        let block = match data.get(start..).and_then(block_extent) {
+            Some(block) => block,
+            None => break,
+        };
+        let end = start + block.len;
+        let complete = if block_index < block_ends.len() {
+            block_ends.entry(block_index) as usize == end
+        } else {
+            data.len() == end + 8 && (&data[end..]).get_u64() == (count + block.num_entries) as u64
+        };
+        if !complete {
+            break;
+        }
+
+        start = end;
+        count += block.num_entries;
+        result.push(block);
+    }
+
+    result
+}
+
+/// Returns an upper bound on the amount of entries of the merge of the given dictionaries.
+///
+/// This is the sum of their entry counts, which is exact when the
+/// dictionaries do not overlap. It does not look at any entries.
+pub fn estimate_merged_dict_entries(dicts: &[StringDict]) -> usize {
+    dicts.iter().map(|d| d.num_entries()).sum()
+}
+
+/// Returns the exact amount of distinct entries in the merge of the given dictionaries.
+///
+/// Unlike `estimate_merged_dict_entries`, this scans every entry of
+/// every dictionary.
+pub fn count_merged_dict_entries(dicts: &[StringDict]) -> usize {
+    dicts.iter().map(|d| d.iter()).kmerge().dedup().count()
+}
+
+/// Returns the overlap between two dictionaries, as the number of
+/// shared entries divided by the number of distinct entries in both.
+///
+/// This is computed in a single merged scan over both
+/// dictionaries. Two empty dictionaries have an overlap of 0.
+pub fn dictionary_overlap(a: &StringDict, b: &StringDict) -> f64 {
+    let mut shared = 0;
+    let mut total = 0;
+    for entry in a.iter().merge_join_by(b.iter(), |x, y| x.cmp(y)) {
+        if entry.is_both() {
+            shared += 1;
+        }
+        total += 1;
+    }
+
+    if total == 0 {
+        0.0
+    } else {
+        shared as f64 / total as f64
+    }
+}
+
+/// Returns a string dictionary without any entries.
+pub fn empty_string_dict() -> StringDict {
+    let (offsets, data) = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new()).finalize();
+
+    StringDict::parse(offsets.freeze(), data.freeze())
+}
+
+/// Returns a typed dictionary without any entries.
+pub fn empty_typed_dict() -> TypedDict {
+    let (types_present, type_offsets, offsets, data) = TypedDictBufBuilder::new(
+        BytesMut::new(),
+        BytesMut::new(),
+        BytesMut::new(),
+        BytesMut::new(),
+    )
+    .finalize();
+
+    TypedDict::from_parts(
+        types_present.freeze(),
+        type_offsets.freeze(),
+        offsets.freeze(),
+        data.freeze(),
+    )
+}
+
+/// A value of a typed dictionary entry, decoded according to its datatype.
+///
+/// Strings, numbers and booleans are decoded into a native variant.
+/// Decimals and big integers are given in their lexical form, since
+/// they don't fit a fixed-size number. The string-like xsd types,
+/// such as `AnyURI` or `Token`, are given as `Lexical` along with
+/// their datatype. Only datatypes stored in a binary encoding, such as
+/// dates and durations, are returned as `Other` with the entry's
+/// stored bytes, which can be decoded with `TypedDictEntry::as_val`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynValue {
+    String(String),
+    UInt32(u32),
+    Int32(i32),
+    UInt64(u64),
+    Int64(i64),
+    Float32(f32),
+    Float64(f64),
+    Boolean(bool),
+    /// A decimal in its lexical form.
+    Decimal(String),
+    /// An arbitrary-size integer in its lexical form.
+    BigInt(String),
+    /// A value of a string-like datatype.
+    Lexical(Datatype, String),
+    /// A value of a datatype stored in a binary encoding, as its raw bytes.
+    Other(Datatype, Bytes),
+}
+
+/// Extra constructors and conversions for typed dictionary entries.
+pub trait TypedDictEntryExt: Sized {
+    /// Construct an entry, checking that fixed-size datatypes get the right amount of bytes.
+    fn new_checked(datatype: Datatype, bytes: Bytes) -> Result<Self, TypeMismatch>;
+
+    /// Decode this entry into a value of the type matching its datatype.
+    ///
+    /// See `DynValue` for which datatypes are decoded.
+    fn to_dyn(&self) -> DynValue;
+}
+
+impl TypedDictEntryExt for TypedDictEntry {
+    fn new_checked(datatype: Datatype, bytes: Bytes) -> Result<Self, TypeMismatch> {
+        if let Some(expected) = fixed_record_size(datatype) {
+            if bytes.len() != expected {
+                return Err(TypeMismatch {
+                    datatype,
+                    expected,
+                    actual: bytes.len(),
+                });
+            }
+        }
+
+        Ok(TypedDictEntry::new(
+            datatype,
+            SizedDictEntry::new(vec![bytes]),
+        ))
+    }
+
+    fn to_dyn(&self) -> DynValue {
+        match self.datatype() {
+            Datatype::String => DynValue::String(self.as_val::<String, String>()),
+            Datatype::UInt32 => DynValue::UInt32(self.as_val::<u32, u32>()),
+            Datatype::Int32 => DynValue::Int32(self.as_val::<i32, i32>()),
+            Datatype::UInt64 => DynValue::UInt64(self.as_val::<u64, u64>()),
+            Datatype::Int64 => DynValue::Int64(self.as_val::<i64, i64>()),
+            Datatype::Float32 => DynValue::Float32(self.as_val::<f32, f32>()),
+            Datatype::Float64 => DynValue::Float64(self.as_val::<f64, f64>()),
+            Datatype::Boolean => DynValue::Boolean(self.as_val::<bool, bool>()),
+            Datatype::Decimal => DynValue::Decimal(self.as_val::<Decimal, Decimal>().0),
+            Datatype::BigInt => DynValue::BigInt(self.as_val::<Integer, Integer>().to_string()),
+            datatype if is_string_like(datatype) => DynValue::Lexical(
+                datatype,
+                String::from_utf8_lossy(&self.to_bytes()).into_owned(),
+            ),
+            datatype => DynValue::Other(datatype, self.to_bytes()),
+        }
+    }
+}
+
+/// Extra inspection methods for typed (value) dictionaries.
+pub trait TypedDictExt {
+    /// Returns the datatypes present in this dictionary, in dictionary order.
+    fn types_present(&self) -> Vec<Datatype>;
+
+    /// Returns, for every datatype but the first, the amount of entries that precede it.
+    ///
+    /// Together with `types_present`, this delimits the id range of
+    /// each datatype. Datatype `i` (for `i > 0`) starts at id
+    /// `type_id_offsets()[i-1] + 1`.
+    fn type_id_offsets(&self) -> Vec<u64>;
+
+    /// Returns an iterator over the id and decoded value of every entry of `T`'s datatype.
+    fn typed_entries_with_ids<'a, T: TdbDataType + FromLexical<T> + 'a>(
+        &'a self,
+    ) -> Box<dyn Iterator<Item = (u64, T)> + 'a>;
+
+    /// Returns the first entry of this dictionary, reading only the first block.
+    fn first(&self) -> Option<TypedDictEntry>;
+
+    /// Encodes the entries in id range `[lo, hi)` as a standalone dictionary.
+    ///
+    /// The entries are re-based to start at id 1. The returned
+    /// buffers are the types present, type offsets, offsets and data
+    /// parts, as expected by `TypedDict::from_parts`. The range is
+    /// clamped to the entries in this dictionary.
+    fn slice_ids(&self, lo: u64, hi: u64) -> (Bytes, Bytes, Bytes, Bytes);
+
+    /// Returns the last entry of this dictionary, reading only the last block.
+    fn last(&self) -> Option<TypedDictEntry>;
+
+    /// Returns the amount of entries in each block, grouped by datatype.
+    ///
+    /// Every block should hold `BLOCK_SIZE` entries, except for the
+    /// last block of each datatype.
+    fn block_fill_ratios(&self) -> Vec<(Datatype, Vec<u8>)>;
+
+    /// Returns how many entries of a possibly truncated typed dictionary can be read safely.
+    ///
+    /// The arguments are the buffers a `TypedDict` is parsed from,
+    /// which would panic on truncated data. Each datatype's segment of
+    /// blocks is walked in order, checking every block's declared
+    /// length against the data that is left and stopping at the first
+    /// block that is incomplete or doesn't fit its segment.
+    fn entry_count_recoverable(
+        types_present: Bytes,
+        type_offsets: Bytes,
+        offsets: Bytes,
+        data: &Bytes,
+    ) -> io::Result<usize>
+    where
+        Self: Sized;
+}
+
+impl TypedDictExt for TypedDict {
+    fn types_present(&self) -> Vec<Datatype> {
+        type_boundaries(self)
+            .into_iter()
+            .map(|(datatype, _)| datatype)
+            .collect()
+    }
+
+    fn type_id_offsets(&self) -> Vec<u64> {
+        type_boundaries(self)
+            .into_iter()
+            .skip(1)
+            .map(|(_, first_id)| first_id as u64 - 1)
+            .collect()
+    }
+
+    fn typed_entries_with_ids<'a, T: TdbDataType + FromLexical<T> + 'a>(
+        &'a self,
+    ) -> Box<dyn Iterator<Item = (u64, T)> + 'a> {
+        let boundaries = type_boundaries(self);
+        let position = boundaries
+            .iter()
+            .position(|(datatype, _)| *datatype == T::datatype());
+        let (start, end) = match position {
+            Some(position) => (
+                boundaries[position].1,
+                boundaries
+                    .get(position + 1)
+                    .map(|(_, first_id)| *first_id)
+                    .unwrap_or(self.num_entries() + 1),
+            ),
+            None => return Box::new(std::iter::empty()),
+        };
+
+        Box::new((start..end).map(move |id| (id as u64, self.entry(id).unwrap().as_val::<T, T>())))
+    }
+
+    fn first(&self) -> Option<TypedDictEntry> {
+        match self.num_entries() {
+            0 => None,
+            _ => self.entry(1),
+        }
+    }
+
+    fn slice_ids(&self, lo: u64, hi: u64) -> (Bytes, Bytes, Bytes, Bytes) {
+        let lo = std::cmp::max(lo, 1) as usize;
+        let hi = std::cmp::min(hi, self.num_entries() as u64 + 1) as usize;
+
+        let mut builder = TypedDictBufBuilder::new(
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+        );
+        for id in lo..hi {
+            builder.add(self.entry(id).unwrap());
+        }
+        let (types_present, type_offsets, offsets, data) = builder.finalize();
+
+        (
+            types_present.freeze(),
+            type_offsets.freeze(),
+            offsets.freeze(),
+            data.freeze(),
+        )
+    }
+
+    fn last(&self) -> Option<TypedDictEntry> {
+        match self.num_entries() {
+            0 => None,
+            num_entries => self.entry(num_entries),
+        }
+    }
+
+    fn block_fill_ratios(&self) -> Vec<(Datatype, Vec<u8>)> {
+        let mut result: Vec<(Datatype, Vec<u8>)> = Vec::new();
+        for (datatype, block) in self.block_iter() {
+            match result.last_mut() {
+                Some((last, counts)) if *last == datatype => counts.push(block.num_entries()),
+                _ => result.push((datatype, vec![block.num_entries()])),
+            }
+        }
+
+        result
+    }
+
+    fn entry_count_recoverable(
+        types_present: Bytes,
+        type_offsets: Bytes,
+        offsets: Bytes,
+        data: &Bytes,
+    ) -> io::Result<usize> {
+        let types_present = MonotonicLogArray::from_logarray(LogArray::parse(types_present)?);
+        let type_offsets = MonotonicLogArray::from_logarray(LogArray::parse(type_offsets)?);
+        let offsets = MonotonicLogArray::from_logarray(LogArray::parse(offsets)?);
+        if types_present.len() > 1 && type_offsets.len() != types_present.len() - 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "type offsets do not match the types present",
+            ));
+        }
+
+        let blocks = complete_blocks(&offsets, data);
+        let mut count = 0;
+        let mut block_index = 0;
+        for segment in 0..types_present.len() {
+            // the type offsets hold the index of the last block of every datatype but the last
+            let segment_end = if segment < type_offsets.len() {
+                type_offsets.entry(segment) as usize + 1
+            } else {
+                offsets.len() + 1
+            };
+
+            let record_size = match blocks.get(block_index) {
+                Some(block) => block.record_size,
+                None => break,
+            };
+            while block_index < segment_end {
+                match blocks.get(block_index) {
+                    // all blocks of a segment hold entries of the same datatype
+                    Some(block) if block.record_size == record_size => {
+                        count += block.num_entries;
+                        block_index += 1;
+                    }
+                    _ => return Ok(count),
+                }
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+/// Returns every datatype in the dictionary along with the first id that has it.
+///
+/// Since entries are sorted by datatype first, the boundaries can be
+/// found with a binary search per datatype.
+fn type_boundaries(dict: &TypedDict) -> Vec<(Datatype, usize)> {
+    let num_entries = dict.num_entries();
+    let mut result = Vec::new();
+    let mut start = 1;
+    while start <= num_entries {
+        let datatype = dict.entry(start).unwrap().datatype();
+        result.push((datatype, start));
+
+        let mut low = start + 1;
+        let mut high = num_entries + 1;
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if dict.entry(mid).unwrap().datatype() == datatype {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        start = low;
+    }
+
+    result
+}
+
+/// A typed dictionary builder that takes its values one datatype at a time.
+///
+/// Values within a group still have to be sorted, but the caller
+/// no longer needs to merge the groups into a single sorted stream.
+pub struct GroupedTypedDictBufBuilder {
+    inner: TypedDictBufBuilder<BytesMut, BytesMut, BytesMut, BytesMut>,
+    last_datatype: Option<Datatype>,
+}
+
+impl GroupedTypedDictBufBuilder {
+    pub fn new() -> Self {
+        Self {
+            inner: TypedDictBufBuilder::new(
+                BytesMut::new(),
+                BytesMut::new(),
+                BytesMut::new(),
+                BytesMut::new(),
+            ),
+            last_datatype: None,
+        }
+    }
+
+    /// Add a sorted group of values of the given datatype, returning their ids.
+    ///
+    /// This panics if the datatype isn't greater than the datatype of
+    /// any previously added group.
+    pub fn add_group<I: Iterator<Item = Bytes>>(
+        &mut self,
+        datatype: Datatype,
+        values: I,
+    ) -> Vec<u64> {
+        if let Some(last_datatype) = self.last_datatype {
+            assert!(
+                datatype > last_datatype,
+                "group of datatype {:?} added after group of datatype {:?}",
+                datatype,
+                last_datatype
+            );
+        }
+        self.last_datatype = Some(datatype);
+
+        values
+            .map(|value| {
+                self.inner.add(TypedDictEntry::new(
+                    datatype,
+                    SizedDictEntry::new(vec![value]),
+                ))
+            })
+            .collect()
+    }
+
+    pub fn finalize(self) -> (BytesMut, BytesMut, BytesMut, BytesMut) {
+        self.inner.finalize()
+    }
+}
+
+impl Default for GroupedTypedDictBufBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryBackedStore;
+    use crate::storage::{FileStore, SyncableFile};
+    use tdb_succinct::tfc::block::BLOCK_SIZE;
+    use tokio::io::AsyncWriteExt;
+
+    fn string_dict(entries: &[&'static [u8]]) -> StringDict {
+        let mut builder = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new());
+        for entry in entries {
+            builder.add(Bytes::from_static(entry));
+        }
+        let (offsets, data) = builder.finalize();
+
+        StringDict::parse(offsets.freeze(), data.freeze())
+    }
+
+    #[tokio::test]
+    async fn streamed_blocks_match_in_memory_blocks() {
+        let entries: Vec<String> = (0..20).map(|i| format!("entry{:02}", i)).collect();
+        let mut builder = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new());
+        for entry in entries.iter() {
+            builder.add(Bytes::copy_from_slice(entry.as_bytes()));
+        }
+        let (offsets, data) = builder.finalize();
+        let dict = StringDict::parse(offsets.clone().freeze(), data.clone().freeze());
+
+        let offsets_file = MemoryBackedStore::new();
+        let mut w = offsets_file.open_write().await.unwrap();
+        w.write_all(&offsets).await.unwrap();
+        w.sync_all().await.unwrap();
+        let data_file = MemoryBackedStore::new();
+        let mut w = data_file.open_write().await.unwrap();
+        w.write_all(&data).await.unwrap();
+        w.sync_all().await.unwrap();
+
+        let streamed: Vec<SizedDictBlock> = stream_sized_dict_blocks(offsets_file, data_file)
+            .await
+            .unwrap()
+            .map(|block| block.unwrap())
+            .collect()
+            .await;
+        let in_memory: Vec<SizedDictBlock> = dict.block_iter().collect();
+
+        assert_eq!(3, streamed.len());
+        assert_eq!(in_memory.len(), streamed.len());
+        for (streamed, in_memory) in streamed.iter().zip(in_memory.iter()) {
+            assert_eq!(in_memory.num_entries(), streamed.num_entries());
+            for i in 0..in_memory.num_entries() as usize {
+                assert_eq!(in_memory.entry(i).to_bytes(), streamed.entry(i).to_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn dict_trailer_is_id_offset() {
+        let mut builder = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new());
+        for i in 0..20 {
+            builder.add(Bytes::from(format!("entry{:02}", i)));
+        }
+        let (offsets, data) = builder.finalize();
+        let data = data.freeze();
+        let dict = StringDict::parse(offsets.freeze(), data.clone());
+
+        assert_eq!(Some(dict.num_entries() as u64), read_dict_id_offset(&data));
+        assert_eq!(None, read_dict_id_offset(&Bytes::from_static(b"short")));
+    }
+
+    #[test]
+    fn recoverable_entries_of_truncated_dict() {
+        let mut builder = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new());
+        for i in 0..20 {
+            builder.add(Bytes::from(format!("entry{:02}", i)));
+        }
+        let (offsets, data) = builder.finalize();
+        let offsets = offsets.freeze();
+        let data = data.freeze();
+        let dict = StringDict::parse(offsets.clone(), data.clone());
+        let blocks: Vec<_> = dict
+            .block_iter()
+            .map(|b| b.num_entries() as usize)
+            .collect();
+        assert_eq!(3, blocks.len());
+
+        assert_eq!(
+            20,
+            recoverable_dict_entries(offsets.clone(), &data).unwrap()
+        );
+
+        // cut off the trailer and part of the final block
+        let truncated = data.slice(..data.len() - 12);
+        assert_eq!(
+            blocks[0] + blocks[1],
+            recoverable_dict_entries(offsets.clone(), &truncated).unwrap()
+        );
+
+        // cut into the second block
+        let second_block_end = LogArray::parse(offsets.clone()).unwrap().entry(1) as usize;
+        let truncated = data.slice(..second_block_end - 1);
+        assert_eq!(
+            blocks[0],
+            recoverable_dict_entries(offsets, &truncated).unwrap()
+        );
+    }
+
+    #[test]
+    fn merged_dict_entries_of_overlapping_dicts() {
+        let dict1 = string_dict(&[b"aaa", b"bbb", b"ccc", b"ddd"]);
+        let dict2 = string_dict(&[b"bbb", b"ccc", b"eee"]);
+        let dicts = [dict1, dict2];
+
+        assert_eq!(7, estimate_merged_dict_entries(&dicts));
+        assert_eq!(5, count_merged_dict_entries(&dicts));
+        assert_eq!(0, count_merged_dict_entries(&[]));
+    }
+
+    #[test]
+    fn overlap_of_dictionaries() {
+        let dict1 = string_dict(&[b"aaa", b"bbb", b"ccc", b"ddd"]);
+        let dict2 = string_dict(&[b"bbb", b"ccc", b"eee"]);
+
+        assert_eq!(0.4, dictionary_overlap(&dict1, &dict2));
+        assert_eq!(0.4, dictionary_overlap(&dict2, &dict1));
+        assert_eq!(1.0, dictionary_overlap(&dict1, &dict1));
+        assert_eq!(0.0, dictionary_overlap(&dict1, &empty_string_dict()));
+        assert_eq!(
+            0.0,
+            dictionary_overlap(&empty_string_dict(), &empty_string_dict())
+        );
+    }
+
+    #[test]
+    fn first_and_last_of_empty_dictionaries() {
+        assert_eq!(None, empty_string_dict().first());
+        assert_eq!(None, empty_string_dict().last());
+        assert_eq!(None, empty_typed_dict().first());
+        assert_eq!(None, empty_typed_dict().last());
+    }
+
+    #[test]
+    fn try_get_valid_utf8() {
+        let dict = string_dict(&[b"aaa", b"bbb"]);
+
+        assert_eq!("bbb", dict.try_get(2).unwrap().unwrap());
+        assert!(dict.try_get(3).is_none());
+    }
+
+    #[test]
+    fn try_get_invalid_utf8_does_not_panic() {
+        let dict = string_dict(&[b"aaa", b"b\xff\xfeb"]);
+
+        assert!(dict.try_get(1).unwrap().is_ok());
+        assert!(dict.try_get(2).unwrap().is_err());
+        assert_eq!("b\u{fffd}\u{fffd}b", dict.get_lossy(2).unwrap());
+    }
+
+    #[test]
+    fn string_dict_boundaries() {
+        let dict = string_dict(&[
+            b"aaa", b"bbb", b"ccc", b"ddd", b"eee", b"fff", b"ggg", b"hhh", b"iii", b"jjj",
+        ]);
+
+        assert_eq!(&b"aaa"[..], &dict.first().unwrap().to_bytes()[..]);
+        assert_eq!(&b"jjj"[..], &dict.last().unwrap().to_bytes()[..]);
+
+        let empty = string_dict(&[]);
+        assert!(empty.first().is_none());
+        assert!(empty.last().is_none());
+    }
+
+    #[test]
+    fn new_checked_rejects_wrong_record_size() {
+        let result = TypedDictEntry::new_checked(Datatype::UInt32, Bytes::from_static(&[0, 0, 1]));
+
+        assert_eq!(
+            Err(TypeMismatch {
+                datatype: Datatype::UInt32,
+                expected: 4,
+                actual: 3
+            }),
+            result.map(|_| ())
+        );
+    }
+
+    #[test]
+    fn new_checked_accepts_right_record_size() {
+        let entry = u32::make_entry(&42);
+        let checked = TypedDictEntry::new_checked(Datatype::UInt32, entry.to_bytes()).unwrap();
+        assert_eq!(entry, checked);
+
+        let string = TypedDictEntry::new_checked(Datatype::String, Bytes::from_static(b"abc"));
+        assert!(string.is_ok());
+    }
+
+    #[test]
+    fn optimize_entry_with_part_threshold() {
+        let parts: Vec<_> = [b"a", b"b", b"c", b"d", b"e"]
+            .iter()
+            .map(|part| Bytes::from_static(*part))
+            .collect();
+        let mut entry = SizedDictEntry::new(parts);
+        entry.optimize_with_threshold(2);
+
+        assert_eq!(1, entry.0.len());
+        assert_eq!(&b"abcde"[..], &entry.0[0][..]);
+
+        let parts = vec![Bytes::from(vec![1; 100]), Bytes::from(vec![2; 100])];
+        let mut entry = SizedDictEntry::new(parts);
+        entry.optimize_with_threshold(2);
+
+        assert_eq!(2, entry.0.len());
+    }
+
+    #[test]
+    fn lookup_floor_and_ceil() {
+        let dict = string_dict(&[b"bbb", b"ddd", b"fff"]);
+        let len = dict.num_entries();
+
+        let found = dict.id("ddd");
+        assert_eq!(IdLookupResult::Found(2), found);
+        assert_eq!(Some(2), found.floor());
+        assert_eq!(Some(2), found.ceil(len));
+
+        let between = dict.id("ccc");
+        assert_eq!(IdLookupResult::Closest(1), between);
+        assert_eq!(Some(1), between.floor());
+        assert_eq!(Some(2), between.ceil(len));
+
+        let after = dict.id("ggg");
+        assert_eq!(IdLookupResult::Closest(3), after);
+        assert_eq!(Some(3), after.floor());
+        assert_eq!(None, after.ceil(len));
+
+        let before = dict.id("aaa");
+        assert_eq!(IdLookupResult::NotFound, before);
+        assert_eq!(None, before.floor());
+        assert_eq!(Some(1), before.ceil(len));
+
+        assert_eq!(None, IdLookupResult::NotFound.ceil(0));
+    }
+
+    #[test]
+    fn id_bracket_around_string() {
+        let dict = string_dict(&[b"bbb", b"ddd", b"fff"]);
+
+        assert_eq!((Some(1), Some(2)), dict.id_bracket("ccc"));
+        assert_eq!((Some(2), Some(3)), dict.id_bracket("eee"));
+        assert_eq!((None, Some(1)), dict.id_bracket("aaa"));
+        assert_eq!((Some(3), None), dict.id_bracket("ggg"));
+    }
+
+    #[test]
+    fn id_bracket_of_exact_match() {
+        let dict = string_dict(&[b"bbb", b"ddd", b"fff"]);
+
+        assert_eq!((Some(1), Some(1)), dict.id_bracket("bbb"));
+        assert_eq!((Some(2), Some(2)), dict.id_bracket("ddd"));
+        assert_eq!((Some(3), Some(3)), dict.id_bracket("fff"));
+    }
+
+    #[test]
+    fn combined_bytes_round_trip() {
+        let entries: Vec<String> = (0..20).map(|i| format!("entry{:02}", i)).collect();
+        let mut builder = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new());
+        for entry in entries.iter() {
+            builder.add(Bytes::copy_from_slice(entry.as_bytes()));
+        }
+        let (offsets, data) = builder.finalize();
+        let dict = StringDict::parse(offsets.freeze(), data.freeze());
+
+        let combined = dict.to_combined_bytes();
+        let parsed = StringDict::parse_combined(combined).unwrap();
+
+        assert_eq!(entries.len(), parsed.num_entries());
+        for (ix, entry) in entries.iter().enumerate() {
+            assert_eq!(Some(entry.clone()), parsed.get(ix + 1));
+            assert_eq!(Some(ix as u64 + 1), parsed.id(entry).into_option());
+        }
+    }
+
+    #[test]
+    fn combined_bytes_without_header_is_an_error() {
+        assert!(StringDict::parse_combined(Bytes::from_static(&[0, 0, 1])).is_err());
+    }
+
+    fn test_full_blocks() -> TypedDict {
+        let (types_present, type_offsets, offsets, data) = test_full_blocks_parts();
+
+        TypedDict::from_parts(types_present, type_offsets, offsets, data)
+    }
+
+    fn test_full_blocks_parts() -> (Bytes, Bytes, Bytes, Bytes) {
+        let mut builder = TypedDictBufBuilder::new(
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+        );
+
+        let mut strings: Vec<_> = (0..10).map(|i| format!("string{:02}", i)).collect();
+        strings.sort();
+        for s in strings {
+            builder.add(String::make_entry(&s));
+        }
+        for i in 0..24_u32 {
+            builder.add(u32::make_entry(&i));
+        }
+        for i in 0..5_i64 {
+            builder.add(i64::make_entry(&i));
+        }
+
+        let (types_present, type_offsets, offsets, data) = builder.finalize();
+
+        (
+            types_present.freeze(),
+            type_offsets.freeze(),
+            offsets.freeze(),
+            data.freeze(),
+        )
+    }
+
+    #[test]
+    fn recoverable_entries_of_truncated_typed_dict() {
+        let (types_present, type_offsets, offsets, data) = test_full_blocks_parts();
+        let recoverable = |data: &Bytes| {
+            TypedDict::entry_count_recoverable(
+                types_present.clone(),
+                type_offsets.clone(),
+                offsets.clone(),
+                data,
+            )
+            .unwrap()
+        };
+        assert_eq!(39, recoverable(&data));
+
+        // cut into the final block, which holds the five Int64 entries
+        let block_ends = LogArray::parse(offsets.clone()).unwrap();
+        let last_block_start = block_ends.entry(block_ends.len() - 1) as usize;
+        assert_eq!(34, recoverable(&data.slice(..last_block_start + 3)));
+        assert_eq!(34, recoverable(&data.slice(..data.len() - 12)));
+        // losing only the trailer also makes the final block untrustworthy
+        assert_eq!(34, recoverable(&data.slice(..data.len() - 8)));
+
+        // cut into the second UInt32 block
+        let truncated = data.slice(..block_ends.entry(3) as usize - 1);
+        assert_eq!(18, recoverable(&truncated));
+    }
+
+    #[test]
+    fn block_fill_ratios_of_full_blocks() {
+        let dict = test_full_blocks();
+        let ratios = dict.block_fill_ratios();
+
+        assert_eq!(
+            vec![Datatype::String, Datatype::UInt32, Datatype::Int64],
+            ratios
+                .iter()
+                .map(|(datatype, _)| *datatype)
+                .collect::<Vec<_>>()
+        );
+        for (_, counts) in ratios.iter() {
+            let (last, rest) = counts.split_last().unwrap();
+            assert!(rest.iter().all(|count| *count as usize == BLOCK_SIZE));
+            assert!(*last as usize <= BLOCK_SIZE);
+        }
+        assert_eq!(
+            dict.num_entries(),
+            ratios
+                .iter()
+                .flat_map(|(_, counts)| counts.iter())
+                .map(|count| *count as usize)
+                .sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn entries_to_dyn_values() {
+        let dict = test_full_blocks();
+
+        assert_eq!(
+            DynValue::String("string00".to_string()),
+            dict.entry(1).unwrap().to_dyn()
+        );
+        assert_eq!(DynValue::UInt32(0), dict.entry(11).unwrap().to_dyn());
+        assert_eq!(DynValue::UInt32(23), dict.entry(34).unwrap().to_dyn());
+        assert_eq!(DynValue::Int64(4), dict.entry(39).unwrap().to_dyn());
+        assert_eq!(DynValue::Float64(1.5), f64::make_entry(&1.5_f64).to_dyn());
+    }
+
+    #[test]
+    fn every_fixture_entry_to_dyn_value() {
+        let dict = test_full_blocks();
+
+        for id in 1..=dict.num_entries() as u64 {
+            let entry = dict.entry(id as usize).unwrap();
+            let expected = match entry.datatype() {
+                Datatype::String => DynValue::String(entry.as_val::<String, String>()),
+                Datatype::UInt32 => DynValue::UInt32(entry.as_val::<u32, u32>()),
+                Datatype::Int64 => DynValue::Int64(entry.as_val::<i64, i64>()),
+                datatype => panic!("unexpected datatype {:?} in fixture", datatype),
+            };
+            assert_eq!(expected, entry.to_dyn());
+        }
+    }
+
+    #[test]
+    fn remaining_datatypes_to_dyn_values() {
+        assert_eq!(DynValue::Int32(-3), i32::make_entry(&-3_i32).to_dyn());
+        assert_eq!(DynValue::UInt64(7), u64::make_entry(&7_u64).to_dyn());
+        assert_eq!(DynValue::Float32(0.5), f32::make_entry(&0.5_f32).to_dyn());
+        assert_eq!(DynValue::Boolean(true), bool::make_entry(&true).to_dyn());
+        assert_eq!(DynValue::Boolean(false), bool::make_entry(&false).to_dyn());
+        assert_eq!(
+            DynValue::Decimal("12.5".to_string()),
+            Decimal::make_entry(&Decimal("12.5".to_string())).to_dyn()
+        );
+        let big: Integer = "123456789012345678901234567890".parse().unwrap();
+        assert_eq!(
+            DynValue::BigInt("123456789012345678901234567890".to_string()),
+            Integer::make_entry(&big).to_dyn()
+        );
+        assert_eq!(
+            DynValue::Lexical(Datatype::AnyURI, "http://example.com/".to_string()),
+            TypedDictEntry::new_checked(
+                Datatype::AnyURI,
+                Bytes::from_static(b"http://example.com/")
+            )
+            .unwrap()
+            .to_dyn()
+        );
+    }
+
+    #[test]
+    fn types_present_in_dictionary_order() {
+        let dict = test_full_blocks();
+
+        assert_eq!(
+            vec![Datatype::String, Datatype::UInt32, Datatype::Int64],
+            dict.types_present()
+        );
+    }
+
+    #[test]
+    fn typed_dict_boundaries() {
+        let dict = test_full_blocks();
+
+        let first = dict.first().unwrap();
+        assert_eq!(Datatype::String, first.datatype());
+        assert_eq!("string00", first.as_val::<String, String>());
+
+        let last = dict.last().unwrap();
+        assert_eq!(Datatype::Int64, last.datatype());
+        assert_eq!(4, last.as_val::<i64, i64>());
+    }
+
+    #[test]
+    fn type_id_offsets_delimit_datatypes() {
+        let dict = test_full_blocks();
+        let types = dict.types_present();
+        let offsets = dict.type_id_offsets();
+
+        assert_eq!(vec![10, 34], offsets);
+
+        let mut boundaries = vec![0];
+        boundaries.extend(offsets.iter().cloned());
+        boundaries.push(dict.num_entries() as u64);
+        for (i, datatype) in types.into_iter().enumerate() {
+            for id in boundaries[i] + 1..=boundaries[i + 1] {
+                assert_eq!(datatype, dict.entry(id as usize).unwrap().datatype());
+            }
+        }
+    }
+
+    #[test]
+    fn grouped_builder_matches_flat_builder() {
+        let strings: Vec<String> = (0..10).map(|i| format!("string{:02}", i)).collect();
+
+        let mut flat = TypedDictBufBuilder::new(
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+        );
+        let flat_ids = flat.add_all(
+            strings
+                .iter()
+                .map(|s| String::make_entry(s))
+                .chain((0..24_u32).map(|i| u32::make_entry(&i)))
+                .chain((0..5_i64).map(|i| i64::make_entry(&i))),
+        );
+
+        let mut grouped = GroupedTypedDictBufBuilder::new();
+        let mut grouped_ids = Vec::new();
+        for (datatype, values) in vec![
+            (
+                Datatype::String,
+                strings
+                    .iter()
+                    .map(|s| String::make_entry(s).to_bytes())
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                Datatype::UInt32,
+                (0..24_u32)
+                    .map(|i| u32::make_entry(&i).to_bytes())
+                    .collect(),
+            ),
+            (
+                Datatype::Int64,
+                (0..5_i64).map(|i| i64::make_entry(&i).to_bytes()).collect(),
+            ),
+        ] {
+            grouped_ids.extend(grouped.add_group(datatype, values.into_iter()));
+        }
+
+        assert_eq!(flat_ids, grouped_ids);
+        assert_eq!(flat.finalize(), grouped.finalize());
+    }
+
+    #[test]
+    #[should_panic]
+    fn grouped_builder_rejects_smaller_datatype() {
+        let mut builder = GroupedTypedDictBufBuilder::new();
+        builder.add_group(
+            Datatype::UInt32,
+            std::iter::once(u32::make_entry(&1).to_bytes()),
+        );
+        builder.add_group(
+            Datatype::String,
+            std::iter::once(String::make_entry(&"a").to_bytes()),
+        );
+    }
+
+    #[test]
+    fn slice_datatype_range_into_dictionary() {
+        let dict = test_full_blocks();
+
+        let (types_present, type_offsets, offsets, data) = dict.slice_ids(11, 35);
+        let slice = TypedDict::from_parts(types_present, type_offsets, offsets, data);
+
+        assert_eq!(24, slice.num_entries());
+        assert_eq!(vec![Datatype::UInt32], slice.types_present());
+        for i in 0..24_u32 {
+            let id = i as usize + 1;
+            assert_eq!(i, slice.entry(id).unwrap().as_val::<u32, u32>());
+            assert_eq!(
+                IdLookupResult::Found(id as u64),
+                slice.id_entry(&u32::make_entry(&i))
+            );
+        }
+    }
+
+    #[test]
+    fn typed_entries_with_ids_align_with_entry() {
+        let dict = test_full_blocks();
+
+        let u32s: Vec<(u64, u32)> = dict.typed_entries_with_ids::<u32>().collect();
+        assert_eq!(24, u32s.len());
+        for (ix, (id, value)) in u32s.into_iter().enumerate() {
+            assert_eq!(11 + ix as u64, id);
+            assert_eq!(ix as u32, value);
+            assert_eq!(u32::make_entry(&value), dict.entry(id as usize).unwrap());
+        }
+
+        let i64s: Vec<(u64, i64)> = dict.typed_entries_with_ids::<i64>().collect();
+        assert_eq!((35..40).zip(0..5).collect::<Vec<_>>(), i64s);
+
+        assert_eq!(0, dict.typed_entries_with_ids::<f64>().count());
+    }
+
+    fn single_type_dict() -> TypedDict {
+        let mut builder = TypedDictBufBuilder::new(
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+        );
+        for i in 0..20_u32 {
+            builder.add(u32::make_entry(&(i * 3)));
+        }
+        let (types_present, type_offsets, offsets, data) = builder.finalize();
+
+        TypedDict::from_parts(
+            types_present.freeze(),
+            type_offsets.freeze(),
+            offsets.freeze(),
+            data.freeze(),
+        )
+    }
+
+    #[test]
+    fn single_type_dictionary_lookups() {
+        let dict = single_type_dict();
+
+        assert_eq!(20, dict.num_entries());
+        assert_eq!(vec![Datatype::UInt32], dict.types_present());
+        assert!(dict.type_id_offsets().is_empty());
+
+        for i in 0..20_u32 {
+            let id = i as usize + 1;
+            assert_eq!(u32::make_entry(&(i * 3)), dict.entry(id).unwrap());
+            assert_eq!(Some(i * 3), dict.get::<u32>(id));
+            assert_eq!(
+                Some(id as u64),
+                dict.id_entry(&u32::make_entry(&(i * 3))).into_option()
+            );
+        }
+
+        assert!(dict.entry(21).is_none());
+        assert!(dict.id_entry(&u32::make_entry(&1)).into_option().is_none());
+        assert!(dict
+            .id_entry(&u32::make_entry(&100))
+            .into_option()
+            .is_none());
+    }
+
+    #[test]
+    fn single_type_dictionary_iter() {
+        let dict = single_type_dict();
+
+        let entries: Vec<_> = dict.iter().collect();
+        let expected: Vec<_> = (0..20_u32).map(|i| u32::make_entry(&(i * 3))).collect();
+
+        assert_eq!(expected, entries);
+    }
+
+    #[test]
+    fn empty_dictionary_has_no_types() {
+        let builder = TypedDictBufBuilder::new(
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+            BytesMut::new(),
+        );
+        let (types_present, type_offsets, offsets, data) = builder.finalize();
+        let dict = TypedDict::from_parts(
+            types_present.freeze(),
+            type_offsets.freeze(),
+            offsets.freeze(),
+            data.freeze(),
+        );
+
+        assert!(dict.types_present().is_empty());
+        assert!(dict.type_id_offsets().is_empty());
+    }
+}
@@ -1,13 +1,19 @@
 use super::*;
 use crate::storage::{BitIndexMaps, FileLoad, FileStore, IdMapFiles};
+use std::cmp::Ordering;
 use std::convert::TryInto;
 use std::io;
+use std::sync::Arc;
 use tdb_succinct::util::sorted_iterator;
 use tdb_succinct::*;
 
+/// The amount of ids an idmap needs to have for `IdMap::with_cache` to materialize it.
+pub const IDMAP_CACHE_THRESHOLD: usize = 1 << 16;
+
 #[derive(Clone)]
 pub struct IdMap {
     pub id_wtree: Option<WaveletTree>,
+    inner_to_outer_cache: Option<Arc<Vec<u64>>>,
 }
 
 impl Default for IdMap {
@@ -25,7 +31,39 @@ impl IdMap {
     }
 
     pub fn from_parts(id_wtree: Option<WaveletTree>) -> Self {
-        IdMap { id_wtree }
+        IdMap {
+            id_wtree,
+            inner_to_outer_cache: None,
+        }
+    }
+
+    /// Materialize the inner to outer mapping if this idmap has at least `IDMAP_CACHE_THRESHOLD` ids.
+    ///
+    /// This trades memory for speed in `inner_to_outer`, which
+    /// otherwise decodes the wavelet tree on every call.
+    pub fn with_cache(self) -> Self {
+        self.with_cache_threshold(IDMAP_CACHE_THRESHOLD)
+    }
+
+    /// Materialize the inner to outer mapping if this idmap has at least `threshold` ids.
+    pub fn with_cache_threshold(mut self, threshold: usize) -> Self {
+        if self.is_cached() {
+            return self;
+        }
+
+        if let Some(wtree) = self.id_wtree.as_ref() {
+            if wtree.len() >= threshold {
+                let cache = (0..wtree.len()).map(|i| wtree.decode_one(i) + 1).collect();
+                self.inner_to_outer_cache = Some(Arc::new(cache));
+            }
+        }
+
+        self
+    }
+
+    /// Returns true if the inner to outer mapping has been materialized.
+    pub fn is_cached(&self) -> bool {
+        self.inner_to_outer_cache.is_some()
     }
 
     pub fn outer_to_inner(&self, id: u64) -> u64 {
@@ -42,6 +80,13 @@ impl IdMap {
     }
 
     pub fn inner_to_outer(&self, id: u64) -> u64 {
+        if let Some(cache) = self.inner_to_outer_cache.as_ref() {
+            return match id {
+                0 => id,
+                _ => cache.get(id as usize - 1).cloned().unwrap_or(id),
+            };
+        }
+
         self.id_wtree
             .as_ref()
             .and_then(|wtree| {
@@ -54,6 +99,25 @@ impl IdMap {
             })
             .unwrap_or(id)
     }
+
+    /// Returns the full outer to inner mapping for the ids `1..=len`.
+    ///
+    /// The element at index `i` is the inner id for outer id `i+1`.
+    pub fn to_vec(&self, len: usize) -> Vec<u64> {
+        match self.id_wtree.as_ref() {
+            None => (1..=len as u64).collect(),
+            Some(_) => (1..=len as u64).map(|i| self.outer_to_inner(i)).collect(),
+        }
+    }
+}
+
+/// Compare two typed dictionary entries in the physical order of a value dictionary.
+///
+/// Entries are ordered by datatype first, then by their lexical
+/// bytes. This is the `Ord` of `TypedDictEntry`, which compares the
+/// entry buffers in place without copying them.
+pub fn typed_dict_entry_cmp(a: &TypedDictEntry, b: &TypedDictEntry) -> Ordering {
+    a.cmp(b)
 }
 
 pub async fn memory_construct_idmaps<F: 'static + FileLoad + FileStore>(
@@ -89,6 +153,19 @@ pub async fn construct_idmaps_from_structures<F: 'static + FileLoad + FileStore>
     debug_assert!(node_dicts.len() == predicate_idmaps.len());
     let len = node_dicts.len();
 
+    // every id of every layer gets looked up here, so large idmaps are
+    // materialized once rather than decoded again for each lookup.
+    let node_value_idmaps: Vec<_> = node_value_idmaps
+        .iter()
+        .cloned()
+        .map(IdMap::with_cache)
+        .collect();
+    let predicate_idmaps: Vec<_> = predicate_idmaps
+        .iter()
+        .cloned()
+        .map(IdMap::with_cache)
+        .collect();
+
     let mut node_iters = Vec::with_capacity(len);
     let mut node_offset = 0;
     let node_entries_len: Vec<_> = node_dicts.iter().map(|d| d.num_entries()).collect();
@@ -220,3 +297,93 @@ async fn construct_idmaps_from_layers<F: 'static + FileLoad + FileStore>(
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryBackedStore;
+    use crate::storage::BitIndexFiles;
+
+    #[tokio::test]
+    async fn idmap_to_vec_matches_outer_to_inner() {
+        let files = BitIndexFiles {
+            bits_file: MemoryBackedStore::new(),
+            blocks_file: MemoryBackedStore::new(),
+            sblocks_file: MemoryBackedStore::new(),
+        };
+        build_wavelet_tree_from_iter(
+            2,
+            vec![2, 0, 3, 1].into_iter(),
+            files.bits_file.clone(),
+            files.blocks_file.clone(),
+            files.sblocks_file.clone(),
+        )
+        .await
+        .unwrap();
+
+        let idmap = IdMap::from_maps(files.map_all().await.unwrap(), 2);
+        let expected: Vec<_> = (1..=4).map(|i| idmap.outer_to_inner(i)).collect();
+
+        assert_eq!(expected, idmap.to_vec(4));
+        assert_ne!(vec![1, 2, 3, 4], idmap.to_vec(4));
+    }
+
+    #[tokio::test]
+    async fn cached_idmap_matches_uncached() {
+        let files = BitIndexFiles {
+            bits_file: MemoryBackedStore::new(),
+            blocks_file: MemoryBackedStore::new(),
+            sblocks_file: MemoryBackedStore::new(),
+        };
+        let inner: Vec<u64> = (0..100).map(|i| (i * 37) % 100).collect();
+        build_wavelet_tree_from_iter(
+            7,
+            inner.into_iter(),
+            files.bits_file.clone(),
+            files.blocks_file.clone(),
+            files.sblocks_file.clone(),
+        )
+        .await
+        .unwrap();
+
+        let idmap = IdMap::from_maps(files.map_all().await.unwrap(), 7);
+        assert!(!idmap.clone().with_cache().is_cached());
+
+        let cached = idmap.clone().with_cache_threshold(0);
+        assert!(cached.is_cached());
+        for id in 1..=110 {
+            assert_eq!(idmap.inner_to_outer(id), cached.inner_to_outer(id));
+        }
+    }
+
+    #[test]
+    fn identity_idmap_to_vec() {
+        let idmap = IdMap::default();
+
+        assert_eq!(vec![1, 2, 3], idmap.to_vec(3));
+    }
+
+    #[test]
+    fn typed_dict_entry_cmp_orders_by_datatype_first() {
+        let string_entry = String::make_entry(&"zzz");
+        let u32_entry = u32::make_entry(&0);
+
+        assert_eq!(
+            Ordering::Less,
+            typed_dict_entry_cmp(&string_entry, &u32_entry)
+        );
+        assert_eq!(
+            Ordering::Greater,
+            typed_dict_entry_cmp(&u32_entry, &string_entry)
+        );
+    }
+
+    #[test]
+    fn typed_dict_entry_cmp_orders_same_datatype_by_value() {
+        let small = u32::make_entry(&2);
+        let large = u32::make_entry(&300);
+
+        assert_eq!(Ordering::Less, typed_dict_entry_cmp(&small, &large));
+        assert_eq!(Ordering::Equal, typed_dict_entry_cmp(&small, &small));
+    }
+}
@@ -6,9 +6,10 @@ use futures::stream::{Peekable, Stream, StreamExt};
 use futures::task::{Context, Poll};
 
 use super::super::builder::*;
+use super::super::dictionary::Utf8Mode;
 use super::super::id_map::*;
 use super::super::layer::*;
-use crate::layer::InternalLayer;
+use crate::layer::{InternalLayer, LayerSizeBreakdown, MappedRegions, WaveletLookupCache};
 use crate::{chrono_log, storage::*};
 use tdb_succinct::*;
 
@@ -38,6 +39,18 @@ pub struct BaseLayer {
     pub(super) o_ps_adjacency_list: AdjacencyList,
 
     pub(super) predicate_wavelet_tree: WaveletTree,
+
+    pub(super) metadata: Option<Bytes>,
+
+    pub(super) wavelet_lookup_cache: WaveletLookupCache,
+    pub(super) utf8_mode: Utf8Mode,
+
+    pub(super) size_breakdown: LayerSizeBreakdown,
+    pub(super) mapped_regions: MappedRegions,
+}
+
+fn logarray_parses(map: &Bytes) -> bool {
+    LogArray::parse(map.clone()).is_ok()
 }
 
 impl BaseLayer {
@@ -49,7 +62,66 @@ impl BaseLayer {
         Ok(Self::load(name, maps))
     }
 
+    /// Load a possibly partially written base layer, for recovery purposes.
+    ///
+    /// Incomplete id maps are treated as the identity mapping, and
+    /// missing or unreadable subjects and objects files are treated
+    /// as if the layer was dense. The other files still have to be
+    /// present.
+    pub async fn load_lenient<F: FileLoad + FileStore>(
+        name: [u32; 5],
+        files: &BaseLayerFiles<F>,
+    ) -> io::Result<InternalLayer> {
+        let id_map_maps = match files.id_map_files.map_all().await {
+            Ok(id_map_maps) => id_map_maps,
+            Err(_) => {
+                chrono_log!("lenient load: treating incomplete id maps as identity");
+                files.id_map_files.map_all_lenient().await?
+            }
+        };
+
+        let mut maps = BaseLayerMaps {
+            node_dictionary_maps: files.node_dictionary_files.map_all().await?,
+            predicate_dictionary_maps: files.predicate_dictionary_files.map_all().await?,
+            value_dictionary_maps: files.value_dictionary_files.map_all().await?,
+
+            id_map_maps,
+
+            subjects_map: files.subjects_file.map_if_exists().await?,
+            objects_map: files.objects_file.map_if_exists().await?,
+
+            s_p_adjacency_list_maps: files.s_p_adjacency_list_files.map_all().await?,
+            sp_o_adjacency_list_maps: files.sp_o_adjacency_list_files.map_all().await?,
+            o_ps_adjacency_list_maps: files.o_ps_adjacency_list_files.map_all().await?,
+
+            predicate_wavelet_tree_maps: files.predicate_wavelet_tree_files.map_all().await?,
+
+            metadata_map: files.metadata_file.map_if_exists().await?,
+        };
+        if !maps.subjects_map.as_ref().map_or(true, logarray_parses) {
+            chrono_log!("lenient load: ignoring unreadable subjects file");
+            maps.subjects_map = None;
+        }
+        if !maps.objects_map.as_ref().map_or(true, logarray_parses) {
+            chrono_log!("lenient load: ignoring unreadable objects file");
+            maps.objects_map = None;
+        }
+
+        Ok(Self::load(name, maps))
+    }
+
     pub fn load(name: [u32; 5], maps: BaseLayerMaps) -> InternalLayer {
+        Self::load_with_utf8_mode(name, maps, Utf8Mode::default())
+    }
+
+    /// Load a base layer whose string lookups handle invalid UTF-8 according to `utf8_mode`.
+    pub fn load_with_utf8_mode(
+        name: [u32; 5],
+        maps: BaseLayerMaps,
+        utf8_mode: Utf8Mode,
+    ) -> InternalLayer {
+        let size_breakdown = LayerSizeBreakdown::from_base_maps(&maps);
+        let mapped_regions = MappedRegions::from_base_maps(&maps);
         let node_dictionary = StringDict::parse(
             maps.node_dictionary_maps.offsets_map,
             maps.node_dictionary_maps.blocks_map,
@@ -137,6 +209,14 @@ impl BaseLayer {
             o_ps_adjacency_list,
 
             predicate_wavelet_tree,
+
+            metadata: maps.metadata_map,
+
+            wavelet_lookup_cache: WaveletLookupCache::default(),
+            utf8_mode,
+
+            size_breakdown,
+            mapped_regions,
         })
     }
 }
@@ -182,6 +262,14 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
         self.builder.add_node_bytes(node)
     }
 
+    /// Add a node string, or return the existing id if it was the last node added.
+    ///
+    /// Returns the id along with whether a new entry was inserted.
+    /// Panics if the given node string is neither equal to nor a lexical successor of the previous node string.
+    pub fn intern_node(&mut self, node: &str) -> (u64, bool) {
+        self.builder.intern_node(node)
+    }
+
     /// Add a predicate string.
     ///
     /// Panics if the given predicate string is not a lexical successor of the previous node string.
@@ -204,6 +292,18 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
         id
     }
 
+    /// Add a value string, checking it against the float policy.
+    ///
+    /// Panics if the given value string is not a lexical successor of the previous value string.
+    pub fn try_add_value(&mut self, value: TypedDictEntry) -> Result<u64, BuilderError> {
+        self.builder.try_add_value(value)
+    }
+
+    /// Set the policy for handling non-finite float values.
+    pub fn set_float_policy(&mut self, policy: FloatPolicy) {
+        self.builder.set_float_policy(policy)
+    }
+
     /// Add nodes from an iterable.
     ///
     /// Panics if the nodes are not in lexical order, or if previous added nodes are a lexical succesor of any of these nodes.
@@ -325,6 +425,11 @@ pub struct BaseLayerFileBuilderPhase2<F: 'static + FileLoad + FileStore> {
     files: BaseLayerFiles<F>,
 
     builder: TripleFileBuilder<F>,
+
+    allow_duplicates: bool,
+    last_triple: Option<(u64, u64, u64)>,
+
+    metadata: Option<Bytes>,
 }
 
 impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
@@ -345,7 +450,32 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
         )
         .await?;
 
-        Ok(BaseLayerFileBuilderPhase2 { files, builder })
+        Ok(BaseLayerFileBuilderPhase2 {
+            files,
+            builder,
+
+            allow_duplicates: false,
+            last_triple: None,
+
+            metadata: None,
+        })
+    }
+
+    /// Set whether duplicate triples are allowed.
+    ///
+    /// When allowed, a triple equal to the previously added triple is
+    /// silently dropped, and a triple smaller than the previously
+    /// added triple results in an error rather than a panic.
+    pub fn allow_duplicates(&mut self, allow: bool) {
+        self.allow_duplicates = allow;
+    }
+
+    /// Set a metadata blob to be stored alongside the layer.
+    ///
+    /// The metadata is written when the layer is finalized, and can be
+    /// retrieved from the loaded layer through `Layer::metadata`.
+    pub fn set_metadata(&mut self, metadata: Bytes) {
+        self.metadata = Some(metadata);
     }
 
     /// Add the given subject, predicate and object.
@@ -357,6 +487,20 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
         predicate: u64,
         object: u64,
     ) -> io::Result<()> {
+        if self.allow_duplicates {
+            let triple = (subject, predicate, object);
+            match self.last_triple {
+                Some(last) if last == triple => return Ok(()),
+                Some(last) if last > triple => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "layer builder got addition in wrong order",
+                    ))
+                }
+                _ => self.last_triple = Some(triple),
+            }
+        }
+
         self.builder.add_triple(subject, predicate, object).await
     }
 
@@ -370,6 +514,15 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
     where
         <I as std::iter::IntoIterator>::IntoIter: Unpin + Send,
     {
+        if self.allow_duplicates {
+            for triple in triples {
+                self.add_triple(triple.subject, triple.predicate, triple.object)
+                    .await?;
+            }
+
+            return Ok(());
+        }
+
         self.builder.add_id_triples(triples).await
     }
 
@@ -396,6 +549,10 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
         )
         .await?;
 
+        if let Some(metadata) = self.metadata {
+            write_metadata_file(&self.files.metadata_file, &metadata).await?;
+        }
+
         chrono_log!("finalized base builder");
 
         Ok(())
@@ -479,12 +636,18 @@ pub async fn open_base_triple_stream<F: 'static + FileLoad + FileStore>(
     s_p_files: AdjacencyListFiles<F>,
     sp_o_files: AdjacencyListFiles<F>,
 ) -> io::Result<impl Stream<Item = io::Result<(u64, u64, u64)>> + Unpin + Send> {
-    let s_p_stream =
-        adjacency_list_stream_pairs(s_p_files.bitindex_files.bits_file, s_p_files.nums_file)
-            .await?;
-    let sp_o_stream =
-        adjacency_list_stream_pairs(sp_o_files.bitindex_files.bits_file, sp_o_files.nums_file)
-            .await?;
+    let s_p_stream = adjacency_list_stream_pairs_with_read_ahead(
+        s_p_files.bitindex_files.bits_file,
+        s_p_files.nums_file,
+        TRIPLE_STREAM_READ_AHEAD,
+    )
+    .await?;
+    let sp_o_stream = adjacency_list_stream_pairs_with_read_ahead(
+        sp_o_files.bitindex_files.bits_file,
+        sp_o_files.nums_file,
+        TRIPLE_STREAM_READ_AHEAD,
+    )
+    .await?;
 
     Ok(BaseTripleStream::new(s_p_stream, sp_o_stream))
 }
@@ -493,7 +656,9 @@ pub async fn open_base_triple_stream<F: 'static + FileLoad + FileStore>(
 pub mod base_tests {
     use super::*;
     use crate::storage::memory::*;
+    use bytes::BytesMut;
     use futures::stream::TryStreamExt;
+    use tokio::io::AsyncWriteExt;
 
     pub fn base_layer_files() -> BaseLayerFiles<MemoryBackedStore> {
         // TODO inline
@@ -536,6 +701,86 @@ pub mod base_tests {
             .unwrap()
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dictionary_finalize_on_multi_threaded_runtime() {
+        let files = base_layer_files();
+        let mut builder = BaseLayerFileBuilder::from_files(&files).await.unwrap();
+        let nodes: Vec<String> = (0..1000).map(|i| format!("node{:04}", i)).collect();
+        builder.add_nodes(nodes.clone());
+        let builder = builder.into_phase2().await.unwrap();
+        builder.finalize().await.unwrap();
+
+        let mut expected = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new());
+        for node in nodes {
+            expected.add(Bytes::copy_from_slice(node.as_bytes()));
+        }
+        let (offsets, data) = expected.finalize();
+
+        let node_files = &files.node_dictionary_files;
+        assert_eq!(data.freeze(), node_files.blocks_file.map().await.unwrap());
+        assert_eq!(
+            offsets.freeze(),
+            node_files.offsets_file.map().await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_in_node_dictionary_does_not_panic() {
+        let mut files = example_base_layer_files().await.unwrap();
+
+        // replace the node dictionary with one where "bbbbb" is corrupt
+        let mut dict = StringDictBufBuilder::new(BytesMut::new(), BytesMut::new());
+        for node in [&b"aaaaa"[..], b"baa", b"bb\xffbb", b"ccccc", b"mooo"] {
+            dict.add(Bytes::copy_from_slice(node));
+        }
+        let (offsets, data) = dict.finalize();
+        files.node_dictionary_files = DictionaryFiles {
+            blocks_file: MemoryBackedStore::new(),
+            offsets_file: MemoryBackedStore::new(),
+        };
+        for (file, bytes) in [
+            (&files.node_dictionary_files.blocks_file, data),
+            (&files.node_dictionary_files.offsets_file, offsets),
+        ] {
+            let mut w = file.open_write().await.unwrap();
+            w.write_all(&bytes).await.unwrap();
+            w.sync_all().await.unwrap();
+        }
+
+        let strict = BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+            .await
+            .unwrap();
+        assert_eq!(Some("aaaaa".to_string()), strict.id_subject(1));
+        assert_eq!(None, strict.id_subject(3));
+        assert_eq!(None, strict.id_object(3));
+        assert_eq!(None, strict.id_triple_to_string(&IdTriple::new(3, 2, 5)));
+
+        let lenient = BaseLayer::load_with_utf8_mode(
+            [1, 2, 3, 4, 5],
+            files.map_all().await.unwrap(),
+            Utf8Mode::Lenient,
+        );
+        assert_eq!(Some("bb\u{fffd}bb".to_string()), lenient.id_subject(3));
+        assert_eq!(
+            Some(ObjectType::Node("bb\u{fffd}bb".to_string())),
+            lenient.id_object(3)
+        );
+    }
+
+    #[tokio::test]
+    async fn intern_same_node_twice() {
+        let files = base_layer_files();
+        let mut builder = BaseLayerFileBuilder::from_files(&files).await.unwrap();
+
+        let (id, inserted) = builder.intern_node("aaaaa");
+        assert!(inserted);
+        assert_eq!((id, false), builder.intern_node("aaaaa"));
+
+        let (next_id, inserted) = builder.intern_node("bbbbb");
+        assert!(inserted);
+        assert_eq!(id + 1, next_id);
+    }
+
     #[tokio::test]
     async fn build_and_query_base_layer() {
         let layer = example_base_layer().await;
@@ -656,6 +901,307 @@ pub mod base_tests {
         assert!(layer.triples_p(4).next().is_none());
     }
 
+    #[tokio::test]
+    async fn lookup_by_predicate_set() {
+        let layer = example_base_layer().await;
+
+        let triples: Vec<_> = layer
+            .triples_with_predicates(&[3, 1])
+            .map(|t| (t.subject, t.predicate, t.object))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (1, 1, 1),
+                (2, 1, 1),
+                (2, 1, 3),
+                (2, 3, 6),
+                (3, 3, 6),
+                (4, 3, 6)
+            ],
+            triples
+        );
+
+        assert!(layer.triples_with_predicates(&[]).next().is_none());
+    }
+
+    #[tokio::test]
+    async fn objects_for_subject_predicate() {
+        let layer = example_base_layer().await;
+
+        let objects: Vec<_> = layer.objects_sp(2, 1).collect();
+        assert_eq!(vec![1, 3], objects);
+        assert_eq!(2, layer.count_sp(2, 1));
+
+        assert!(layer.objects_sp(2, 2).next().is_none());
+        assert_eq!(0, layer.count_sp(2, 2));
+    }
+
+    #[tokio::test]
+    async fn base_layer_builder_drops_duplicates() {
+        let files = base_layer_files();
+        let mut builder = BaseLayerFileBuilder::from_files(&files).await.unwrap();
+        builder.add_nodes(vec!["a".to_string(), "b".to_string()]);
+        builder.add_predicates(vec!["p".to_string()]);
+        let mut builder = builder.into_phase2().await.unwrap();
+        builder.allow_duplicates(true);
+
+        builder.add_triple(1, 1, 1).await.unwrap();
+        builder.add_triple(1, 1, 2).await.unwrap();
+        builder.add_triple(1, 1, 2).await.unwrap();
+        builder.add_triple(2, 1, 1).await.unwrap();
+        assert!(builder.add_triple(1, 1, 2).await.is_err());
+        builder.finalize().await.unwrap();
+
+        let layer = BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+            .await
+            .unwrap();
+        let triples: Vec<_> = layer
+            .triples()
+            .map(|t| (t.subject, t.predicate, t.object))
+            .collect();
+
+        assert_eq!(vec![(1, 1, 1), (1, 1, 2), (2, 1, 1)], triples);
+    }
+
+    #[tokio::test]
+    async fn lenient_load_without_id_map_files() {
+        let mut files = example_base_layer_files().await.unwrap();
+        // a crash during the build may leave only some id map files behind
+        let bits_file = MemoryBackedStore::new();
+        let mut w = bits_file.open_write().await.unwrap();
+        w.write_all(&[0, 0, 0, 0, 0, 0, 0, 0]).await.unwrap();
+        w.sync_all().await.unwrap();
+        files.id_map_files.node_value_idmap_files = BitIndexFiles {
+            bits_file,
+            blocks_file: MemoryBackedStore::new(),
+            sblocks_file: MemoryBackedStore::new(),
+        };
+        files.id_map_files.predicate_idmap_files = BitIndexFiles {
+            bits_file: MemoryBackedStore::new(),
+            blocks_file: MemoryBackedStore::new(),
+            sblocks_file: MemoryBackedStore::new(),
+        };
+
+        let layer = BaseLayer::load_lenient([1, 2, 3, 4, 5], &files)
+            .await
+            .unwrap();
+
+        assert_eq!(Some(2), layer.subject_id("baa"));
+        assert_eq!(Some("abcde".to_string()), layer.id_predicate(1));
+        assert!(layer.triple_exists(2, 1, 3));
+        assert!(layer.triple_exists(4, 3, 6));
+        assert_eq!(7, layer.triples().count());
+    }
+
+    #[tokio::test]
+    async fn value_triples_to_ids_in_bulk() {
+        let layer = example_base_layer().await;
+
+        let triples = vec![
+            ValueTriple::new_node("baa", "abcde", "bbbbb"),
+            ValueTriple::new_node("aaaaa", "abcde", "aaaaa"),
+            ValueTriple::new_node("baa", "abcde", "unknown"),
+            ValueTriple::new_string_value("ccccc", "lll", "chicken"),
+            ValueTriple::new_node("baa", "abcde", "bbbbb"),
+            ValueTriple::new_string_value("unknown", "abcde", "chicken"),
+        ];
+
+        let expected: Vec<_> = triples
+            .iter()
+            .map(|t| layer.value_triple_to_id(t))
+            .collect();
+        let ids = layer.value_triples_to_ids(&triples);
+
+        assert_eq!(expected, ids);
+        assert_eq!(Some(IdTriple::new(2, 1, 3)), ids[0]);
+        assert_eq!(None, ids[2]);
+    }
+
+    #[tokio::test]
+    async fn fanout_of_predicate() {
+        let layer = example_base_layer().await;
+
+        assert_eq!(
+            Fanout {
+                subject_count: 2,
+                min: 1,
+                max: 2,
+                mean: 1.5
+            },
+            layer.predicate_fanout(1)
+        );
+        assert_eq!(Fanout::default(), layer.predicate_fanout(4));
+    }
+
+    #[tokio::test]
+    async fn datatype_of_object() {
+        let layer = example_base_layer().await;
+
+        let chicken = layer
+            .object_value_id(&String::make_entry(&"chicken"))
+            .unwrap();
+        assert_eq!(Some(Datatype::String), layer.object_datatype(chicken));
+        assert_eq!(None, layer.object_datatype(1));
+        assert_eq!(None, layer.object_datatype(100));
+    }
+
+    #[tokio::test]
+    async fn value_dictionary_handle_shared_across_threads() {
+        let layer = example_base_layer().await;
+        let dict = layer.value_dictionary_handle();
+
+        let handles: Vec<_> = vec!["chicken", "zebra"]
+            .into_iter()
+            .map(|value| {
+                let dict = dict.clone();
+                std::thread::spawn(move || {
+                    dict.id_entry(&String::make_entry(&value))
+                        .into_option()
+                        .and_then(|id| dict.get::<String>(id as usize))
+                })
+            })
+            .collect();
+
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(
+            vec![Some("chicken".to_string()), Some("zebra".to_string())],
+            results
+        );
+    }
+
+    #[tokio::test]
+    async fn used_ids_of_base_layer() {
+        let layer = example_base_layer().await;
+
+        let subjects: Vec<_> = layer.used_subject_ids().collect();
+        let objects: Vec<_> = layer.used_object_ids().collect();
+
+        assert_eq!(vec![1, 2, 3, 4], subjects);
+        assert_eq!(vec![1, 3, 5, 6], objects);
+        assert!(layer.id_object_is_value(6).unwrap());
+    }
+
+    #[tokio::test]
+    async fn triples_exist_in_bulk() {
+        let layer = example_base_layer().await;
+
+        let triples = vec![
+            IdTriple::new(3, 3, 6),
+            IdTriple::new(1, 1, 2),
+            IdTriple::new(1, 1, 1),
+            IdTriple::new(5, 1, 1),
+            IdTriple::new(2, 1, 3),
+            IdTriple::new(3, 3, 6),
+            IdTriple::new(0, 0, 0),
+        ];
+
+        let expected: Vec<_> = triples
+            .iter()
+            .map(|t| layer.triple_exists(t.subject, t.predicate, t.object))
+            .collect();
+
+        assert_eq!(vec![true, false, true, false, true, true, false], expected);
+        assert_eq!(expected, layer.triples_exist(&triples));
+    }
+
+    #[tokio::test]
+    async fn triples_filtered_by_object_kind() {
+        let layer = example_base_layer().await;
+
+        let nodes: Vec<_> = layer.triples_filtered(ObjectKindFilter::Nodes).collect();
+        let values: Vec<_> = layer.triples_filtered(ObjectKindFilter::Values).collect();
+        let any: Vec<_> = layer.triples_filtered(ObjectKindFilter::Any).collect();
+
+        assert_eq!(
+            vec![
+                IdTriple::new(1, 1, 1),
+                IdTriple::new(2, 1, 1),
+                IdTriple::new(2, 1, 3),
+                IdTriple::new(3, 2, 5)
+            ],
+            nodes
+        );
+        assert_eq!(
+            vec![
+                IdTriple::new(2, 3, 6),
+                IdTriple::new(3, 3, 6),
+                IdTriple::new(4, 3, 6)
+            ],
+            values
+        );
+
+        let mut combined: Vec<_> = nodes.into_iter().chain(values).collect();
+        combined.sort();
+        assert_eq!(layer.triples().collect::<Vec<_>>(), any);
+        assert_eq!(any, combined);
+    }
+
+    #[tokio::test]
+    async fn distinct_subject_predicate_pairs() {
+        let layer = example_base_layer().await;
+
+        let pairs: Vec<_> = layer.sp_pairs().collect();
+
+        assert_eq!(vec![(1, 1), (2, 1), (2, 3), (3, 2), (3, 3), (4, 3)], pairs);
+    }
+
+    #[tokio::test]
+    async fn triples_grouped_by_predicate() {
+        let layer = example_base_layer().await;
+
+        let groups: Vec<_> = layer.triples_grouped_by_predicate().collect();
+
+        assert_eq!(
+            vec![1, 2, 3],
+            groups.iter().map(|(p, _)| *p).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                IdTriple::new(2, 3, 6),
+                IdTriple::new(3, 3, 6),
+                IdTriple::new(4, 3, 6)
+            ],
+            groups[2].1
+        );
+    }
+
+    #[tokio::test]
+    async fn predicates_with_ids() {
+        let layer = example_base_layer().await;
+
+        let predicates: Vec<_> = layer.predicates().collect();
+
+        assert_eq!(
+            vec![
+                (1, "abcde".to_string()),
+                (2, "fghij".to_string()),
+                (3, "klmno".to_string()),
+                (4, "lll".to_string()),
+            ],
+            predicates
+        );
+        for (id, predicate) in predicates {
+            assert_eq!(Some(id), layer.predicate_id(&predicate));
+        }
+    }
+
+    #[tokio::test]
+    async fn value_objects_have_object_ids() {
+        let layer = example_base_layer().await;
+
+        let values: Vec<_> = layer.value_objects().collect();
+        assert_eq!(5, values.len());
+
+        let chicken = layer
+            .object_value_id(&String::make_entry(&"chicken"))
+            .unwrap();
+        let (id, datatype, entry) = &values[0];
+        assert_eq!(chicken, *id);
+        assert_eq!(Datatype::String, *datatype);
+        assert_eq!(&b"chicken"[..], &entry.to_bytes()[..]);
+    }
+
     #[tokio::test]
     async fn create_empty_base_layer() {
         let base_layer_files = base_layer_files();
@@ -735,4 +1281,168 @@ pub mod base_tests {
         assert_eq!(0, layer.triple_addition_count());
         assert_eq!(0, layer.triple_removal_count());
     }
+
+    #[tokio::test]
+    async fn permissive_float_policy_accepts_nan() {
+        let layer_files = base_layer_files();
+        let mut builder = BaseLayerFileBuilder::from_files(&layer_files)
+            .await
+            .unwrap();
+
+        let id = builder.try_add_value(f64::make_entry(&f64::NAN)).unwrap();
+        assert_eq!(1, id);
+    }
+
+    #[tokio::test]
+    async fn strict_float_policy_rejects_nan() {
+        let layer_files = base_layer_files();
+        let mut builder = BaseLayerFileBuilder::from_files(&layer_files)
+            .await
+            .unwrap();
+        builder.set_float_policy(FloatPolicy::Strict);
+
+        assert_eq!(
+            Err(BuilderError::NonFinite),
+            builder.try_add_value(f64::make_entry(&f64::NAN))
+        );
+        assert_eq!(
+            Err(BuilderError::NonFinite),
+            builder.try_add_value(f32::make_entry(&f32::INFINITY))
+        );
+        assert_eq!(Ok(1), builder.try_add_value(f64::make_entry(&1.5_f64)));
+    }
+
+    #[tokio::test]
+    async fn empty_string_as_first_dictionary_entry() {
+        let layer_files = base_layer_files();
+        let mut builder = BaseLayerFileBuilder::from_files(&layer_files)
+            .await
+            .unwrap();
+
+        builder.add_nodes(vec!["", "a", "aa", "b"].into_iter().map(|s| s.to_string()));
+        builder.add_predicates(vec!["", "p"].into_iter().map(|s| s.to_string()));
+        builder.add_values(vec!["", "x"].into_iter().map(|s| String::make_entry(&s)));
+
+        let mut builder = builder.into_phase2().await.unwrap();
+        builder.add_triple(1, 1, 2).await.unwrap();
+        builder.add_triple(2, 2, 5).await.unwrap();
+        builder.finalize().await.unwrap();
+
+        let layer = BaseLayer::load_from_files([1, 2, 3, 4, 5], &layer_files)
+            .await
+            .unwrap();
+
+        assert_eq!(Some(1), layer.subject_id(""));
+        assert_eq!(Some("".to_string()), layer.id_subject(1));
+        assert_eq!(Some(3), layer.object_node_id("aa"));
+        assert_eq!(Some(4), layer.subject_id("b"));
+        assert_eq!(Some(1), layer.predicate_id(""));
+        assert_eq!(Some("".to_string()), layer.id_predicate(1));
+        assert_eq!(Some(2), layer.predicate_id("p"));
+        assert_eq!(Some(5), layer.object_value_id(&String::make_entry(&"")));
+        assert_eq!(
+            Some(ObjectType::Value(String::make_entry(&""))),
+            layer.id_object(5)
+        );
+
+        assert!(layer.triple_exists(1, 1, 2));
+        assert!(layer.triple_exists(2, 2, 5));
+    }
+
+    async fn file_size(file: &MemoryBackedStore) -> usize {
+        if file.exists().await.unwrap() {
+            file.size().await.unwrap()
+        } else {
+            0
+        }
+    }
+
+    async fn bitindex_files_size(files: &BitIndexFiles<MemoryBackedStore>) -> usize {
+        file_size(&files.bits_file).await
+            + file_size(&files.blocks_file).await
+            + file_size(&files.sblocks_file).await
+    }
+
+    async fn adjacency_list_files_size(files: &AdjacencyListFiles<MemoryBackedStore>) -> usize {
+        bitindex_files_size(&files.bitindex_files).await + file_size(&files.nums_file).await
+    }
+
+    #[tokio::test]
+    async fn base_layer_size_breakdown() {
+        let files = example_base_layer_files().await.unwrap();
+        let layer = BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+            .await
+            .unwrap();
+        let breakdown = layer.size_breakdown();
+
+        let node_dictionary = file_size(&files.node_dictionary_files.blocks_file).await
+            + file_size(&files.node_dictionary_files.offsets_file).await;
+        let predicate_dictionary = file_size(&files.predicate_dictionary_files.blocks_file).await
+            + file_size(&files.predicate_dictionary_files.offsets_file).await;
+        let value_dictionary = file_size(&files.value_dictionary_files.types_present_file).await
+            + file_size(&files.value_dictionary_files.type_offsets_file).await
+            + file_size(&files.value_dictionary_files.offsets_file).await
+            + file_size(&files.value_dictionary_files.blocks_file).await;
+        let idmaps = bitindex_files_size(&files.id_map_files.node_value_idmap_files).await
+            + bitindex_files_size(&files.id_map_files.predicate_idmap_files).await;
+        let s_p_adjacency_list = adjacency_list_files_size(&files.s_p_adjacency_list_files).await;
+        let sp_o_adjacency_list = adjacency_list_files_size(&files.sp_o_adjacency_list_files).await;
+        let o_ps_adjacency_list = adjacency_list_files_size(&files.o_ps_adjacency_list_files).await;
+        let predicate_wavelet_tree = bitindex_files_size(&files.predicate_wavelet_tree_files).await;
+
+        assert!(node_dictionary > 0);
+        assert_eq!(node_dictionary, breakdown.node_dictionary);
+        assert_eq!(predicate_dictionary, breakdown.predicate_dictionary);
+        assert_eq!(value_dictionary, breakdown.value_dictionary);
+        assert_eq!(
+            idmaps,
+            breakdown.node_value_idmap + breakdown.predicate_idmap
+        );
+        assert_eq!(s_p_adjacency_list, breakdown.pos_s_p_adjacency_list);
+        assert_eq!(sp_o_adjacency_list, breakdown.pos_sp_o_adjacency_list);
+        assert_eq!(o_ps_adjacency_list, breakdown.pos_o_ps_adjacency_list);
+        assert_eq!(predicate_wavelet_tree, breakdown.pos_predicate_wavelet_tree);
+        assert_eq!(0, breakdown.neg_s_p_adjacency_list);
+        assert_eq!(0, breakdown.neg_predicate_wavelet_tree);
+
+        let mapped_size = node_dictionary
+            + predicate_dictionary
+            + value_dictionary
+            + idmaps
+            + file_size(&files.subjects_file).await
+            + file_size(&files.objects_file).await
+            + s_p_adjacency_list
+            + sp_o_adjacency_list
+            + o_ps_adjacency_list
+            + predicate_wavelet_tree;
+        assert_eq!(mapped_size, layer.mapped_size());
+    }
+
+    #[tokio::test]
+    async fn prefault_loaded_base_layer() {
+        let layer = example_base_layer().await;
+        let regions = layer.mapped_regions().regions();
+        assert!(!regions.is_empty());
+        assert!(regions.iter().any(|region| !region.is_empty()));
+
+        layer.prefault();
+
+        assert!(layer.triple_exists(1, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn prefault_skips_memory_store_layers() {
+        let store = MemoryLayerStore::new();
+        let mut builder = store.create_base_layer().await.unwrap();
+        let name = builder.name();
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.commit_boxed().await.unwrap();
+
+        let layer = store.get_layer(name).await.unwrap().unwrap();
+        assert!(layer.mapped_regions().regions().is_empty());
+
+        layer.prefault();
+
+        assert!(layer.value_triple_exists(&ValueTriple::new_string_value("cow", "says", "moo")));
+    }
 }
@@ -3,18 +3,23 @@
 //! A child layer stores a reference to a base layer, as well as
 //! triple additions and removals, and any new dictionary entries that
 //! this layer needs for its additions.
+use bytes::Bytes;
+
 use super::super::builder::*;
+use super::super::dictionary::{empty_string_dict, empty_typed_dict, Utf8Mode};
 use super::super::id_map::*;
 use crate::layer::*;
+use crate::storage::memory::child_layer_memory_files;
 use crate::storage::*;
 use rayon::prelude::*;
+use tdb_succinct::util::heap_sorted_stream;
 use tdb_succinct::*;
 
 use std::io;
 use std::pin::Pin;
 use std::sync::Arc;
 
-use futures::stream::{self, Stream, StreamExt};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use futures::task::{Context, Poll};
 
 /// A child layer.
@@ -49,6 +54,14 @@ pub struct ChildLayer {
 
     pub(super) pos_predicate_wavelet_tree: WaveletTree,
     pub(super) neg_predicate_wavelet_tree: WaveletTree,
+
+    pub(super) metadata: Option<Bytes>,
+
+    pub(super) wavelet_lookup_cache: WaveletLookupCache,
+    pub(super) utf8_mode: Utf8Mode,
+
+    pub(super) size_breakdown: LayerSizeBreakdown,
+    pub(super) mapped_regions: MappedRegions,
 }
 
 impl ChildLayer {
@@ -61,21 +74,80 @@ impl ChildLayer {
         Ok(Self::load(name, parent, maps))
     }
 
+    /// Build a child layer in memory from the given additions and removals.
+    ///
+    /// The layer is given a random name.
+    pub async fn from_changes(
+        parent: Arc<InternalLayer>,
+        additions: Vec<ValueTriple>,
+        removals: Vec<ValueTriple>,
+    ) -> io::Result<InternalLayer> {
+        let name = rand::random();
+        let files = child_layer_memory_files();
+
+        let mut builder = SimpleLayerBuilder::from_parent(name, parent.clone(), files.clone());
+        for triple in additions {
+            builder.add_value_triple(triple);
+        }
+        for triple in removals {
+            builder.remove_value_triple(triple);
+        }
+        builder.commit().await?;
+
+        Self::load_from_files(name, parent, &files).await
+    }
+
+    /// Check that every removal in this layer refers to a triple that exists in the parent stack.
+    ///
+    /// Returns the removals that do not, in ascending order.
+    pub fn validate_removals(&self) -> Result<(), Vec<IdTriple>> {
+        let invalid: Vec<_> = InternalLayerTripleSubjectIterator::new(
+            Some(self.neg_subjects.clone()),
+            self.neg_s_p_adjacency_list.clone(),
+            self.neg_sp_o_adjacency_list.clone(),
+        )
+        .filter(|t| !self.parent.triple_exists(t.subject, t.predicate, t.object))
+        .collect();
+
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid)
+        }
+    }
+
     pub fn load(name: [u32; 5], parent: Arc<InternalLayer>, maps: ChildLayerMaps) -> InternalLayer {
-        let node_dictionary = StringDict::parse(
-            maps.node_dictionary_maps.offsets_map,
-            maps.node_dictionary_maps.blocks_map,
-        );
-        let predicate_dictionary = StringDict::parse(
-            maps.predicate_dictionary_maps.offsets_map,
-            maps.predicate_dictionary_maps.blocks_map,
-        );
-        let value_dictionary = TypedDict::from_parts(
-            maps.value_dictionary_maps.types_present_map,
-            maps.value_dictionary_maps.type_offsets_map,
-            maps.value_dictionary_maps.offsets_map,
-            maps.value_dictionary_maps.blocks_map,
-        );
+        Self::load_with_utf8_mode(name, parent, maps, Utf8Mode::default())
+    }
+
+    /// Load a child layer whose string lookups handle invalid UTF-8 according to `utf8_mode`.
+    pub fn load_with_utf8_mode(
+        name: [u32; 5],
+        parent: Arc<InternalLayer>,
+        maps: ChildLayerMaps,
+        utf8_mode: Utf8Mode,
+    ) -> InternalLayer {
+        let size_breakdown = LayerSizeBreakdown::from_child_maps(&maps);
+        let mapped_regions = MappedRegions::from_child_maps(&maps);
+        // a child without dictionaries of its own only refers to
+        // entries of its parent, so all lookups fall through to there.
+        let node_dictionary = match maps.own_node_dictionary_maps() {
+            None => empty_string_dict(),
+            Some(maps) => StringDict::parse(maps.offsets_map.clone(), maps.blocks_map.clone()),
+        };
+        let predicate_dictionary = match maps.own_predicate_dictionary_maps() {
+            None => empty_string_dict(),
+            Some(maps) => StringDict::parse(maps.offsets_map.clone(), maps.blocks_map.clone()),
+        };
+        let value_dictionary = match maps.own_value_dictionary_maps() {
+            None => empty_typed_dict(),
+            Some(maps) => TypedDict::from_parts(
+                maps.types_present_map.clone(),
+                maps.type_offsets_map.clone(),
+                maps.offsets_map.clone(),
+                maps.blocks_map.clone(),
+            ),
+        };
 
         let parent_node_value_count = parent.node_and_value_count();
         let parent_predicate_count = parent.predicate_count();
@@ -193,6 +265,14 @@ impl ChildLayer {
 
             pos_predicate_wavelet_tree,
             neg_predicate_wavelet_tree,
+
+            metadata: maps.metadata_map,
+
+            wavelet_lookup_cache: WaveletLookupCache::default(),
+            utf8_mode,
+
+            size_breakdown,
+            mapped_regions,
         })
     }
 }
@@ -241,6 +321,19 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
         }
     }
 
+    /// Add a node string, reporting whether a new entry was inserted.
+    ///
+    /// No entry is inserted if the node already exists in the parent
+    /// or was the last node added to this builder. Panics if the
+    /// given node string is neither equal to nor a lexical successor
+    /// of the previous node string.
+    pub fn intern_node(&mut self, node: &str) -> (u64, bool) {
+        match self.parent.subject_id(node) {
+            None => self.builder.intern_node(node),
+            Some(id) => (id, false),
+        }
+    }
+
     /// Add a predicate string.
     ///
     /// Does nothing if the predicate already exists in the paretn, and
@@ -265,6 +358,23 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
         }
     }
 
+    /// Add a value string, checking it against the float policy.
+    ///
+    /// Values that already exist in the parent are not checked.
+    /// Panics if the given value string is not a lexical successor of
+    /// the previous value string.
+    pub fn try_add_value(&mut self, value: TypedDictEntry) -> Result<u64, BuilderError> {
+        match self.parent.object_value_id(&value) {
+            None => self.builder.try_add_value(value),
+            Some(id) => Ok(id),
+        }
+    }
+
+    /// Set the policy for handling non-finite float values.
+    pub fn set_float_policy(&mut self, policy: FloatPolicy) {
+        self.builder.set_float_policy(policy)
+    }
+
     /// Add nodes from an iterable.
     ///
     /// Panics if the nodes are not in lexical order, or if previous
@@ -388,9 +498,29 @@ pub struct ChildLayerFileBuilderPhase2<F: 'static + FileLoad + FileStore + Clone
 
     pos_builder: TripleFileBuilder<F>,
     neg_builder: TripleFileBuilder<F>,
+
+    metadata: Option<Bytes>,
+    shared_dictionaries: bool,
 }
 
 impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuilderPhase2<F> {
+    /// Create a builder for a child layer without dictionaries of its own.
+    ///
+    /// No dictionary files are written for the resulting layer, so
+    /// all triples added to it have to use ids that are already
+    /// known to the parent. This is useful for layers that only
+    /// remove or re-add existing triples. Instead, the layer is marked
+    /// as sharing its parent's dictionaries.
+    pub async fn from_parent_dictionaries(
+        parent: Arc<dyn Layer>,
+        files: &ChildLayerFiles<F>,
+    ) -> io::Result<Self> {
+        let mut builder = Self::new(parent, files.clone(), 0, 0, 0).await?;
+        builder.shared_dictionaries = true;
+
+        Ok(builder)
+    }
+
     pub(crate) async fn new(
         parent: Arc<dyn Layer>,
         files: ChildLayerFiles<F>,
@@ -426,9 +556,20 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
 
             pos_builder,
             neg_builder,
+
+            metadata: None,
+            shared_dictionaries: false,
         })
     }
 
+    /// Set a metadata blob to be stored alongside the layer.
+    ///
+    /// The metadata is written when the layer is finalized, and can be
+    /// retrieved from the loaded layer through `Layer::metadata`.
+    pub fn set_metadata(&mut self, metadata: Bytes) {
+        self.metadata = Some(metadata);
+    }
+
     pub(crate) async fn add_triple_unchecked(
         &mut self,
         subject: u64,
@@ -529,6 +670,9 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
     }
 
     /// Write the layer data to storage.
+    ///
+    /// If no triples were added or removed, this results in a layer
+    /// that passes through to its parent unchanged.
     pub async fn finalize(self) -> io::Result<()> {
         let pos_task = tokio::spawn(self.pos_builder.finalize());
         let neg_task = tokio::spawn(self.neg_builder.finalize());
@@ -554,6 +698,13 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
         pos_indexes_task.await??;
         neg_indexes_task.await??;
 
+        if let Some(metadata) = self.metadata {
+            write_metadata_file(&self.files.metadata_file, &metadata).await?;
+        }
+        if self.shared_dictionaries {
+            write_marker_file(&self.files.shared_dictionaries_file).await?;
+        }
+
         Ok(())
     }
 }
@@ -686,13 +837,21 @@ pub async fn open_child_triple_stream<F: 'static + FileLoad + FileStore>(
     s_p_files: AdjacencyListFiles<F>,
     sp_o_files: AdjacencyListFiles<F>,
 ) -> io::Result<impl Stream<Item = io::Result<(u64, u64, u64)>> + Unpin + Send> {
-    let subjects_stream = logarray_stream_entries(subjects_file).await?;
-    let s_p_stream =
-        adjacency_list_stream_pairs(s_p_files.bitindex_files.bits_file, s_p_files.nums_file)
-            .await?;
-    let sp_o_stream =
-        adjacency_list_stream_pairs(sp_o_files.bitindex_files.bits_file, sp_o_files.nums_file)
+    let subjects_stream =
+        logarray_stream_entries(ReadAheadFile::new(subjects_file, TRIPLE_STREAM_READ_AHEAD))
             .await?;
+    let s_p_stream = adjacency_list_stream_pairs_with_read_ahead(
+        s_p_files.bitindex_files.bits_file,
+        s_p_files.nums_file,
+        TRIPLE_STREAM_READ_AHEAD,
+    )
+    .await?;
+    let sp_o_stream = adjacency_list_stream_pairs_with_read_ahead(
+        sp_o_files.bitindex_files.bits_file,
+        sp_o_files.nums_file,
+        TRIPLE_STREAM_READ_AHEAD,
+    )
+    .await?;
 
     Ok(ChildTripleStream::new(
         subjects_stream,
@@ -701,6 +860,39 @@ pub async fn open_child_triple_stream<F: 'static + FileLoad + FileStore>(
     ))
 }
 
+/// Opens a stream over the additions and removals of a child layer, in ascending triple order.
+pub async fn open_child_triple_change_stream<
+    F: 'static + FileLoad + FileStore + Clone + Send + Sync,
+>(
+    files: &ChildLayerFiles<F>,
+) -> io::Result<impl Stream<Item = io::Result<(TripleChange, (u64, u64, u64))>> + Send> {
+    let parts = vec![
+        (
+            TripleChange::Addition,
+            files.pos_subjects_file.clone(),
+            files.pos_s_p_adjacency_list_files.clone(),
+            files.pos_sp_o_adjacency_list_files.clone(),
+        ),
+        (
+            TripleChange::Removal,
+            files.neg_subjects_file.clone(),
+            files.neg_s_p_adjacency_list_files.clone(),
+            files.neg_sp_o_adjacency_list_files.clone(),
+        ),
+    ];
+
+    let mut streams = Vec::with_capacity(2);
+    for (change, subjects_file, s_p_files, sp_o_files) in parts {
+        let stream = open_child_triple_stream(subjects_file, s_p_files, sp_o_files).await?;
+        // the change goes second so that the merge orders by triple
+        streams.push(stream.map_ok(move |triple| (triple, change)));
+    }
+
+    let merged = heap_sorted_stream(streams).await?;
+
+    Ok(merged.map_ok(|(triple, change)| (change, triple)))
+}
+
 #[cfg(test)]
 pub mod child_tests {
     use super::*;
@@ -713,6 +905,23 @@ pub mod child_tests {
         child_layer_memory_files()
     }
 
+    #[tokio::test]
+    async fn intern_node_reports_new_entries() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+        let child_files = child_layer_files();
+
+        let mut child_builder = ChildLayerFileBuilder::from_files(parent.clone(), &child_files)
+            .await
+            .unwrap();
+
+        assert_eq!((1, false), child_builder.intern_node("aaaaa"));
+
+        let (id, inserted) = child_builder.intern_node("zzzzz");
+        assert!(inserted);
+        assert_eq!((id, false), child_builder.intern_node("zzzzz"));
+    }
+
     #[tokio::test]
     async fn empty_child_layer_equivalent_to_parent() {
         let base_layer = example_base_layer().await;
@@ -742,6 +951,42 @@ pub mod child_tests {
         assert!(!child_layer.triple_exists(2, 2, 0));
     }
 
+    #[tokio::test]
+    async fn empty_child_layer_passes_through_parent_triples() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+
+        let child_files = child_layer_memory_files();
+        let builder = ChildLayerFileBuilder::from_files(parent.clone(), &child_files)
+            .await
+            .unwrap()
+            .into_phase2()
+            .await
+            .unwrap();
+        builder.finalize().await.unwrap();
+
+        let child_layer =
+            ChildLayer::load_from_files([5, 4, 3, 2, 1], parent.clone(), &child_files)
+                .await
+                .unwrap();
+
+        assert_eq!(0, child_layer.internal_triple_additions().count());
+        assert_eq!(0, child_layer.internal_triple_removals().count());
+        assert_eq!(
+            parent.triples().collect::<Vec<_>>(),
+            child_layer.triples().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            parent.triples_p(3).collect::<Vec<_>>(),
+            child_layer.triples_p(3).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            parent.triples_o(6).collect::<Vec<_>>(),
+            child_layer.triples_o(6).collect::<Vec<_>>()
+        );
+        assert_eq!(parent.triple_count(), child_layer.triple_count());
+    }
+
     #[tokio::test]
     async fn child_layer_can_have_inserts() {
         let base_layer = example_base_layer().await;
@@ -806,6 +1051,240 @@ pub mod child_tests {
         assert!(!child_layer.triple_exists(2, 2, 0));
     }
 
+    #[tokio::test]
+    async fn lookup_by_object_across_stack() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+        let child_files = child_layer_files();
+
+        let child_builder = ChildLayerFileBuilder::from_files(parent.clone(), &child_files)
+            .await
+            .unwrap();
+        let mut b = child_builder.into_phase2().await.unwrap();
+        b.add_triple(1, 2, 6).await.unwrap();
+        b.remove_triple(3, 3, 6).await.unwrap();
+        b.finalize().await.unwrap();
+
+        let child_layer = ChildLayer::load_from_files([5, 4, 3, 2, 1], parent, &child_files)
+            .await
+            .unwrap();
+
+        let triples: Vec<_> = child_layer
+            .triples_o(6)
+            .map(|t| (t.subject, t.predicate, t.object))
+            .collect();
+        assert_eq!(vec![(1, 2, 6), (2, 3, 6), (4, 3, 6)], triples);
+    }
+
+    #[tokio::test]
+    async fn child_layer_from_changes() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+
+        let child_layer = ChildLayer::from_changes(
+            parent.clone(),
+            vec![
+                ValueTriple::new_node("ccccc", "abcde", "mooo"),
+                ValueTriple::new_string_value("duck", "abcde", "quack"),
+            ],
+            vec![ValueTriple::new_node("baa", "abcde", "aaaaa")],
+        )
+        .await
+        .unwrap();
+
+        assert!(child_layer.value_triple_exists(&ValueTriple::new_node("ccccc", "abcde", "mooo")));
+        assert!(child_layer
+            .value_triple_exists(&ValueTriple::new_string_value("duck", "abcde", "quack")));
+        assert!(!child_layer.value_triple_exists(&ValueTriple::new_node("baa", "abcde", "aaaaa")));
+        assert!(child_layer.value_triple_exists(&ValueTriple::new_node("aaaaa", "abcde", "aaaaa")));
+        assert_eq!(parent.triple_count() + 1, child_layer.triple_count());
+    }
+
+    #[tokio::test]
+    async fn child_removing_everything_is_empty() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+        let removals = parent
+            .triples()
+            .map(|t| parent.id_triple_to_string(&t).unwrap())
+            .collect();
+
+        let child_layer = ChildLayer::from_changes(parent.clone(), Vec::new(), removals)
+            .await
+            .unwrap();
+
+        assert!(!parent.is_empty());
+        assert!(child_layer.is_empty());
+        assert_eq!(0, child_layer.triples().count());
+    }
+
+    #[tokio::test]
+    async fn compaction_map_of_child_with_gaps() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+        let removals = vec![
+            parent.id_triple_to_string(&IdTriple::new(1, 1, 1)).unwrap(),
+            parent.id_triple_to_string(&IdTriple::new(3, 2, 5)).unwrap(),
+        ];
+
+        let child_layer = ChildLayer::from_changes(parent, Vec::new(), removals)
+            .await
+            .unwrap();
+
+        // node 3 is used both as a subject and as an object, and
+        // gets a single new id
+        let (nodes, values) = child_layer.compaction_map();
+        assert_eq!(vec![1, 2, 3, 4], nodes);
+        assert_eq!(vec![6], values);
+        assert!(nodes.windows(2).all(|w| w[0] < w[1]));
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[tokio::test]
+    async fn subject_positions_of_sparse_child() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+        let child_files = child_layer_files();
+
+        let child_builder = ChildLayerFileBuilder::from_files(parent.clone(), &child_files)
+            .await
+            .unwrap();
+        let mut b = child_builder.into_phase2().await.unwrap();
+        b.add_triple(2, 1, 2).await.unwrap();
+        b.add_triple(4, 3, 3).await.unwrap();
+        b.finalize().await.unwrap();
+
+        let child_layer =
+            ChildLayer::load_from_files([5, 4, 3, 2, 1], parent.clone(), &child_files)
+                .await
+                .unwrap();
+
+        assert_eq!(Some(0), child_layer.subject_position(2));
+        assert_eq!(Some(1), child_layer.subject_position(4));
+        assert_eq!(None, child_layer.subject_position(1));
+        assert_eq!(None, child_layer.subject_position(3));
+        assert_eq!(None, child_layer.subject_position(0));
+    }
+
+    #[tokio::test]
+    async fn validate_removals_flags_missing_triples() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+        let child_files = child_layer_files();
+
+        let mut b =
+            ChildLayerFileBuilderPhase2::from_parent_dictionaries(parent.clone(), &child_files)
+                .await
+                .unwrap();
+        b.remove_triple_unchecked(2, 1, 1).await.unwrap();
+        b.remove_triple_unchecked(2, 1, 2).await.unwrap();
+        b.remove_triple_unchecked(3, 2, 5).await.unwrap();
+        b.finalize().await.unwrap();
+
+        let child_layer = ChildLayer::load_from_files([5, 4, 3, 2, 1], parent, &child_files)
+            .await
+            .unwrap();
+        let child_layer = match child_layer {
+            InternalLayer::Child(child_layer) => child_layer,
+            _ => panic!("expected a child layer"),
+        };
+
+        assert_eq!(
+            Err(vec![IdTriple::new(2, 1, 2)]),
+            child_layer.validate_removals()
+        );
+    }
+
+    #[tokio::test]
+    async fn child_layer_with_missing_dictionary_fails_to_load() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+        let child_files = child_layer_files();
+
+        let mut b = ChildLayerFileBuilder::from_files(parent.clone(), &child_files)
+            .await
+            .unwrap();
+        b.add_node("foo");
+        let mut b = b.into_phase2().await.unwrap();
+        b.remove_triple(2, 1, 1).await.unwrap();
+        b.finalize().await.unwrap();
+
+        // simulate a lost node dictionary
+        let mut damaged_files = child_files.clone();
+        damaged_files.node_dictionary_files = DictionaryFiles {
+            blocks_file: MemoryBackedStore::new(),
+            offsets_file: MemoryBackedStore::new(),
+        };
+
+        assert!(
+            ChildLayer::load_from_files([5, 4, 3, 2, 1], parent, &damaged_files)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn child_layer_without_dictionaries() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+        let child_files = child_layer_files();
+
+        let mut b =
+            ChildLayerFileBuilderPhase2::from_parent_dictionaries(parent.clone(), &child_files)
+                .await
+                .unwrap();
+        b.remove_triple(2, 1, 1).await.unwrap();
+        b.remove_triple(3, 2, 5).await.unwrap();
+        b.finalize().await.unwrap();
+
+        assert!(!child_files
+            .node_dictionary_files
+            .blocks_file
+            .exists()
+            .await
+            .unwrap());
+        assert!(!child_files
+            .value_dictionary_files
+            .blocks_file
+            .exists()
+            .await
+            .unwrap());
+
+        assert!(child_files.shared_dictionaries_file.exists().await.unwrap());
+
+        let maps = child_files.map_all().await.unwrap();
+        assert!(maps.own_node_dictionary_maps().is_none());
+        assert!(maps.own_predicate_dictionary_maps().is_none());
+        assert!(maps.own_value_dictionary_maps().is_none());
+
+        let child_layer =
+            ChildLayer::load_from_files([5, 4, 3, 2, 1], parent.clone(), &child_files)
+                .await
+                .unwrap();
+
+        assert_eq!(
+            parent.node_and_value_count(),
+            child_layer.node_and_value_count()
+        );
+        assert_eq!(parent.predicate_count(), child_layer.predicate_count());
+
+        assert!(!child_layer.triple_exists(2, 1, 1));
+        assert!(child_layer.triple_exists(2, 1, 3));
+        assert!(!child_layer.triple_exists(3, 2, 5));
+
+        assert_eq!(Some("baa".to_string()), child_layer.id_subject(2));
+        assert_eq!(Some(4), child_layer.subject_id("ccccc"));
+        assert_eq!(Some("abcde".to_string()), child_layer.id_predicate(1));
+        assert_eq!(
+            Some(ObjectType::Node("bbbbb".to_string())),
+            child_layer.id_object(3)
+        );
+        assert_eq!(
+            Some(6),
+            child_layer.object_value_id(&String::make_entry(&"chicken"))
+        );
+    }
+
     #[tokio::test]
     async fn child_layer_can_have_inserts_and_deletes() {
         let base_layer = example_base_layer().await;
@@ -1174,6 +1653,52 @@ pub mod child_tests {
         assert_eq!(vec![(2, 1, 1), (2, 3, 6), (4, 3, 6)], removal_triples);
     }
 
+    #[tokio::test]
+    async fn stream_child_triple_changes() {
+        let base_layer = example_base_layer().await;
+        let parent: Arc<InternalLayer> = Arc::new(base_layer.into());
+
+        let child_files = child_layer_files();
+        let builder = ChildLayerFileBuilder::from_files(parent.clone(), &child_files)
+            .await
+            .unwrap();
+
+        let mut b = builder.into_phase2().await.unwrap();
+        b.add_triple(1, 2, 1).await.unwrap();
+        b.add_triple(3, 1, 5).await.unwrap();
+        b.add_triple(5, 2, 3).await.unwrap();
+        b.remove_triple(2, 1, 1).await.unwrap();
+        b.remove_triple(2, 3, 6).await.unwrap();
+        b.remove_triple(4, 3, 6).await.unwrap();
+        b.finalize().await.unwrap();
+
+        let child_layer =
+            ChildLayer::load_from_files([5, 4, 3, 2, 1], parent.clone(), &child_files)
+                .await
+                .unwrap();
+
+        let stream = open_child_triple_change_stream(&child_files).await.unwrap();
+        let changes: Vec<_> = stream.try_collect().await.unwrap();
+
+        let expected: Vec<_> = child_layer
+            .local_changes()
+            .map(|(change, t)| (change, (t.subject, t.predicate, t.object)))
+            .collect();
+
+        assert_eq!(expected, changes);
+        assert_eq!(
+            vec![
+                (TripleChange::Addition, (1, 2, 1)),
+                (TripleChange::Removal, (2, 1, 1)),
+                (TripleChange::Removal, (2, 3, 6)),
+                (TripleChange::Addition, (3, 1, 5)),
+                (TripleChange::Removal, (4, 3, 6)),
+                (TripleChange::Addition, (5, 2, 3)),
+            ],
+            changes
+        );
+    }
+
     #[tokio::test]
     async fn count_triples() {
         let base_layer = example_base_layer().await;
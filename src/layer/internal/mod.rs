@@ -3,11 +3,17 @@ pub mod base_merge;
 pub mod child;
 mod object_iterator;
 mod predicate_iterator;
+mod prefault;
 pub mod rollup;
+mod size;
 mod subject_iterator;
+mod wavelet_cache;
 
+use super::dictionary::{StringDictExt, TypedDictExt, Utf8Mode};
 use super::id_map::*;
 use super::layer::*;
+use bytes::Bytes;
+use itertools::Itertools;
 use tdb_succinct::*;
 
 use std::collections::HashSet;
@@ -17,8 +23,11 @@ pub use base::*;
 pub use child::*;
 pub use object_iterator::*;
 pub use predicate_iterator::*;
+pub use prefault::*;
 pub use rollup::*;
+pub use size::*;
 pub use subject_iterator::*;
+pub use wavelet_cache::*;
 
 #[derive(Clone)]
 pub enum InternalLayer {
@@ -66,6 +75,55 @@ impl InternalLayer {
         count
     }
 
+    /// Returns the size in bytes of each of the structures in this layer.
+    ///
+    /// This only covers this layer itself, not its ancestors.
+    pub fn size_breakdown(&self) -> LayerSizeBreakdown {
+        match self {
+            Base(base) => base.size_breakdown,
+            Child(child) => child.size_breakdown,
+            Rollup(rollup) => rollup.internal.size_breakdown(),
+        }
+    }
+
+    /// Returns the total size in bytes of the files this layer's structures were loaded from.
+    ///
+    /// Depending on the store, this memory is memory mapped rather
+    /// than allocated on the heap.
+    pub fn mapped_size(&self) -> usize {
+        self.size_breakdown().total()
+    }
+
+    /// Touches every page of this layer and its ancestors to pull them into the page cache.
+    ///
+    /// This blocks until done, so it is best called on a background
+    /// thread after loading. Layers loaded from a store that keeps its
+    /// files on the heap are skipped.
+    pub fn prefault(&self) {
+        let mut layer = Some(self);
+        while let Some(l) = layer {
+            l.mapped_regions().prefault();
+            layer = l.immediate_parent();
+        }
+    }
+
+    /// Forget the regions this layer was loaded from, so that `prefault` skips it.
+    pub(crate) fn forget_mapped_regions(&mut self) {
+        match self {
+            Base(base) => base.mapped_regions = MappedRegions::default(),
+            Child(child) => child.mapped_regions = MappedRegions::default(),
+            Rollup(_) => {}
+        }
+    }
+
+    fn mapped_regions(&self) -> &MappedRegions {
+        match self {
+            Base(base) => &base.mapped_regions,
+            Child(child) => &child.mapped_regions,
+            Rollup(rollup) => rollup.internal.mapped_regions(),
+        }
+    }
+
     pub fn node_dictionary(&self) -> &StringDict {
         match self {
             Base(base) => &base.node_dictionary,
@@ -186,6 +244,24 @@ impl InternalLayer {
         }
     }
 
+    /// The cache of predicate wavelet lookups for this layer, which is disabled by default.
+    pub fn wavelet_lookup_cache(&self) -> &WaveletLookupCache {
+        match self {
+            Base(base) => &base.wavelet_lookup_cache,
+            Child(child) => &child.wavelet_lookup_cache,
+            Rollup(rollup) => rollup.internal.wavelet_lookup_cache(),
+        }
+    }
+
+    /// How string lookups in this layer handle invalid UTF-8.
+    pub fn utf8_mode(&self) -> Utf8Mode {
+        match self {
+            Base(base) => base.utf8_mode,
+            Child(child) => child.utf8_mode,
+            Rollup(rollup) => rollup.internal.utf8_mode(),
+        }
+    }
+
     pub fn pos_subjects(&self) -> Option<&MonotonicLogArray> {
         match self {
             Base(base) => base.subjects.as_ref(),
@@ -219,7 +295,8 @@ impl InternalLayer {
     }
 
     pub fn predicate_dict_get(&self, id: usize) -> Option<String> {
-        self.predicate_dictionary().get(id)
+        self.predicate_dictionary()
+            .get_with_mode(id, self.utf8_mode())
     }
 
     pub fn predicate_dict_len(&self) -> usize {
@@ -235,7 +312,7 @@ impl InternalLayer {
     }
 
     pub fn node_dict_get(&self, id: usize) -> Option<String> {
-        self.node_dictionary().get(id)
+        self.node_dictionary().get_with_mode(id, self.utf8_mode())
     }
 
     pub fn node_dict_len(&self) -> usize {
@@ -372,7 +449,11 @@ impl InternalLayer {
         &self,
         predicate: u64,
     ) -> OptInternalLayerTriplePredicateIterator {
-        match self.pos_predicate_wavelet_tree().lookup(predicate) {
+        let tree = self.pos_predicate_wavelet_tree();
+        match self
+            .wavelet_lookup_cache()
+            .lookup(false, predicate, || tree.lookup(predicate))
+        {
             Some(lookup) => OptInternalLayerTriplePredicateIterator(Some(
                 InternalLayerTriplePredicateIterator::new(
                     lookup,
@@ -390,8 +471,10 @@ impl InternalLayer {
         predicate: u64,
     ) -> OptInternalLayerTriplePredicateIterator {
         match (
-            self.neg_predicate_wavelet_tree()
-                .and_then(|t| t.lookup(predicate)),
+            self.neg_predicate_wavelet_tree().and_then(|t| {
+                self.wavelet_lookup_cache()
+                    .lookup(true, predicate, || t.lookup(predicate))
+            }),
             self.neg_s_p_adjacency_list(),
             self.neg_sp_o_adjacency_list(),
         ) {
@@ -543,6 +626,10 @@ impl Layer for InternalLayer {
         self.parent_name()
     }
 
+    fn stack_depth(&self) -> usize {
+        self.layer_stack_size()
+    }
+
     fn node_and_value_count(&self) -> usize {
         self.parent_node_value_count()
             + self.node_dictionary().num_entries()
@@ -732,6 +819,75 @@ impl Layer for InternalLayer {
         None
     }
 
+    fn datatypes_present(&self) -> Vec<Datatype> {
+        let mut result = self.value_dictionary().types_present();
+        let mut layer = self;
+        while let Some(parent) = layer.immediate_parent() {
+            result.extend(parent.value_dictionary().types_present());
+            layer = parent;
+        }
+        result.sort();
+        result.dedup();
+
+        result
+    }
+
+    fn predicates(&self) -> Box<dyn Iterator<Item = (u64, String)> + Send> {
+        let mut layers = vec![self.clone()];
+        while let Some(parent) = layers.last().unwrap().immediate_parent() {
+            let parent = parent.clone();
+            layers.push(parent);
+        }
+
+        Box::new(layers.into_iter().rev().flat_map(|layer| {
+            let parent_count = layer.parent_predicate_count() as u64;
+            (1..=layer.predicate_dict_len()).map(move |i| {
+                let id = parent_count + layer.predicate_id_map().inner_to_outer(i as u64);
+
+                (id, layer.predicate_dict_get(i).unwrap())
+            })
+        }))
+    }
+
+    fn value_dictionary_handle(&self) -> TypedDict {
+        self.value_dictionary().clone()
+    }
+
+    fn metadata(&self) -> Option<Bytes> {
+        match self {
+            Base(base) => base.metadata.clone(),
+            Child(child) => child.metadata.clone(),
+            Rollup(rollup) => rollup.internal.metadata(),
+        }
+    }
+
+    fn value_objects(&self) -> Box<dyn Iterator<Item = (u64, Datatype, SizedDictEntry)> + Send> {
+        let mut layers = vec![self.clone()];
+        while let Some(parent) = layers.last().unwrap().immediate_parent() {
+            let parent = parent.clone();
+            layers.push(parent);
+        }
+
+        Box::new(layers.into_iter().rev().flat_map(|layer| {
+            // value ids come after the node ids in the layer's id space
+            let parent_count = layer.parent_node_value_count() as u64;
+            let node_count = layer.node_dict_len() as u64;
+            (1..=layer.value_dict_len()).map(move |i| {
+                let value = layer.value_dict_get(i).unwrap();
+                let id = parent_count
+                    + layer
+                        .node_value_id_map()
+                        .inner_to_outer(i as u64 + node_count);
+
+                (
+                    id,
+                    value.datatype(),
+                    SizedDictEntry::new(vec![value.to_bytes()]),
+                )
+            })
+        }))
+    }
+
     fn id_object_is_node(&self, id: u64) -> Option<bool> {
         if id == 0 {
             return None;
@@ -791,6 +947,19 @@ impl Layer for InternalLayer {
         additions
     }
 
+    fn stack_has_removals(&self) -> bool {
+        let mut layer = Some(self);
+        while let Some(l) = layer {
+            if l.internal_triple_layer_removal_count() > 0 {
+                return true;
+            }
+
+            layer = l.immediate_parent();
+        }
+
+        false
+    }
+
     fn triple_removal_count(&self) -> usize {
         let mut removals = self.internal_triple_layer_removal_count();
 
@@ -804,6 +973,14 @@ impl Layer for InternalLayer {
         removals
     }
 
+    fn local_changes(&self) -> Box<dyn Iterator<Item = (TripleChange, IdTriple)> + Send> {
+        // a single layer never adds and removes the same triple, so nothing cancels out here
+        Box::new(InternalTripleStackIterator::from_parts(
+            vec![self.internal_triple_additions()],
+            vec![self.internal_triple_removals()],
+        ))
+    }
+
     fn all_counts(&self) -> LayerCounts {
         let mut node_count = self.node_dict_len();
         let mut predicate_count = self.predicate_dict_len();
@@ -848,6 +1025,30 @@ impl Layer for InternalLayer {
         }
     }
 
+    fn provenance_of(&self, triple: IdTriple) -> Option<[u32; 5]> {
+        let IdTriple {
+            subject,
+            predicate,
+            object,
+        } = triple;
+        if subject == 0 || predicate == 0 || object == 0 {
+            return None;
+        }
+
+        let mut layer_opt = Some(self);
+        while let Some(layer) = layer_opt {
+            if layer.internal_triple_addition_exists(subject, predicate, object) {
+                return Some(layer.name());
+            } else if layer.internal_triple_removal_exists(subject, predicate, object) {
+                return None;
+            }
+
+            layer_opt = layer.immediate_parent();
+        }
+
+        None
+    }
+
     fn triples(&self) -> Box<dyn Iterator<Item = IdTriple> + Send> {
         Box::new(InternalTripleSubjectIterator::from_layer(self))
     }
@@ -872,6 +1073,21 @@ impl Layer for InternalLayer {
         )
     }
 
+    fn triples_sp_from(
+        &self,
+        subject: u64,
+        predicate: u64,
+        object_start: u64,
+    ) -> Box<dyn Iterator<Item = IdTriple> + Send> {
+        Box::new(
+            InternalTripleSubjectIterator::from_layer(self).seek_subject_predicate_object(
+                subject,
+                predicate,
+                object_start,
+            ),
+        )
+    }
+
     fn triples_p(&self, predicate: u64) -> Box<dyn Iterator<Item = IdTriple> + Send> {
         Box::new(InternalTriplePredicateIterator::from_layer(self, predicate))
     }
@@ -884,6 +1100,43 @@ impl Layer for InternalLayer {
         )
     }
 
+    fn triples_object_order(&self) -> Box<dyn Iterator<Item = IdTriple> + Send> {
+        // the object index orders the triples of an object by subject
+        // first, so each object's triples are reordered by predicate.
+        Box::new(
+            InternalTripleObjectIterator::from_layer(self)
+                .peekable()
+                .batching(|iter| {
+                    let first = iter.next()?;
+                    let mut triples = vec![first];
+                    while let Some(t) = iter.next_if(|t| t.object == first.object) {
+                        triples.push(t);
+                    }
+                    triples.sort_unstable_by_key(|t| (t.predicate, t.subject));
+
+                    Some(triples)
+                })
+                .flatten(),
+        )
+    }
+
+    fn subject_position(&self, subject: u64) -> Option<usize> {
+        if subject == 0 {
+            return None;
+        }
+
+        match self.pos_subjects() {
+            None => {
+                if subject > self.pos_s_p_adjacency_list().left_count() as u64 {
+                    None
+                } else {
+                    Some(subject as usize - 1)
+                }
+            }
+            Some(subjects) => subjects.index_of(subject),
+        }
+    }
+
     fn single_triple_sp(&self, subject: u64, predicate: u64) -> Option<IdTriple> {
         // is subject/predicate in the positives? we're in luck
         if let Some(pos) = sp_o_position(
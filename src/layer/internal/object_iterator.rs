@@ -268,6 +268,7 @@ mod tests {
     use crate::layer::base::base_tests::*;
     use crate::storage::memory::*;
     use crate::storage::*;
+    use std::sync::Arc;
 
     async fn example_base_layer_files() -> BaseLayerFiles<MemoryBackedStore> {
         let nodes = vec!["aaaaa", "baa", "bbbbb", "ccccc", "mooo"];
@@ -441,8 +442,7 @@ mod tests {
         assert!(triples.is_empty());
     }
 
-    #[tokio::test]
-    async fn combined_iterator_for_object() {
+    async fn example_stack_for_objects() -> Arc<InternalLayer> {
         let store = MemoryLayerStore::new();
         let mut builder = store.create_base_layer().await.unwrap();
         let base_name = builder.name();
@@ -483,7 +483,12 @@ mod tests {
         builder.add_value_triple(ValueTriple::new_node("field", "contains", "cow"));
         builder.commit_boxed().await.unwrap();
 
-        let layer = store.get_layer(child4_name).await.unwrap().unwrap();
+        store.get_layer(child4_name).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn combined_iterator_for_object() {
+        let layer = example_stack_for_objects().await;
 
         let object_id = layer.object_node_id("cow").unwrap();
         let triples: Vec<_> = layer
@@ -499,4 +504,16 @@ mod tests {
 
         assert_eq!(expected, triples);
     }
+
+    #[tokio::test]
+    async fn object_order_iterator_for_stack() {
+        let layer = example_stack_for_objects().await;
+
+        let triples: Vec<_> = layer.triples_object_order().collect();
+
+        let mut expected: Vec<_> = layer.triples().collect();
+        expected.sort_by_key(|t| (t.object, t.predicate, t.subject));
+
+        assert_eq!(expected, triples);
+    }
 }
@@ -0,0 +1,103 @@
+//! Pulling the memory regions of a layer into the page cache ahead of time.
+use bytes::Bytes;
+
+use crate::storage::*;
+
+/// Assumed page size for prefaulting. Touching a byte more often than
+/// once per actual page is harmless.
+const PAGE_SIZE: usize = 4096;
+
+/// All the memory regions a layer was loaded from.
+#[derive(Clone, Default)]
+pub struct MappedRegions {
+    regions: Vec<Bytes>,
+}
+
+impl MappedRegions {
+    pub fn from_base_maps(maps: &BaseLayerMaps) -> Self {
+        let mut regions = Vec::new();
+        push_dictionary(&mut regions, &maps.node_dictionary_maps);
+        push_dictionary(&mut regions, &maps.predicate_dictionary_maps);
+        push_typed_dictionary(&mut regions, &maps.value_dictionary_maps);
+        push_idmaps(&mut regions, &maps.id_map_maps);
+
+        regions.extend(maps.subjects_map.iter().cloned());
+        regions.extend(maps.objects_map.iter().cloned());
+        push_adjacency_list(&mut regions, &maps.s_p_adjacency_list_maps);
+        push_adjacency_list(&mut regions, &maps.sp_o_adjacency_list_maps);
+        push_adjacency_list(&mut regions, &maps.o_ps_adjacency_list_maps);
+        push_bitindex(&mut regions, &maps.predicate_wavelet_tree_maps);
+
+        Self { regions }
+    }
+
+    pub fn from_child_maps(maps: &ChildLayerMaps) -> Self {
+        let mut regions = Vec::new();
+        push_dictionary(&mut regions, &maps.node_dictionary_maps);
+        push_dictionary(&mut regions, &maps.predicate_dictionary_maps);
+        push_typed_dictionary(&mut regions, &maps.value_dictionary_maps);
+        push_idmaps(&mut regions, &maps.id_map_maps);
+
+        regions.push(maps.pos_subjects_map.clone());
+        regions.push(maps.pos_objects_map.clone());
+        regions.push(maps.neg_subjects_map.clone());
+        regions.push(maps.neg_objects_map.clone());
+        push_adjacency_list(&mut regions, &maps.pos_s_p_adjacency_list_maps);
+        push_adjacency_list(&mut regions, &maps.pos_sp_o_adjacency_list_maps);
+        push_adjacency_list(&mut regions, &maps.pos_o_ps_adjacency_list_maps);
+        push_adjacency_list(&mut regions, &maps.neg_s_p_adjacency_list_maps);
+        push_adjacency_list(&mut regions, &maps.neg_sp_o_adjacency_list_maps);
+        push_adjacency_list(&mut regions, &maps.neg_o_ps_adjacency_list_maps);
+        push_bitindex(&mut regions, &maps.pos_predicate_wavelet_tree_maps);
+        push_bitindex(&mut regions, &maps.neg_predicate_wavelet_tree_maps);
+
+        Self { regions }
+    }
+
+    /// Returns the regions in a fixed order.
+    pub fn regions(&self) -> &[Bytes] {
+        &self.regions
+    }
+
+    /// Reads one byte of every page in every region, in order.
+    pub fn prefault(&self) {
+        for region in self.regions.iter() {
+            for offset in (0..region.len()).step_by(PAGE_SIZE) {
+                // black_box makes sure the access isn't optimized away
+                std::hint::black_box(region[offset]);
+            }
+        }
+    }
+}
+
+fn push_dictionary(regions: &mut Vec<Bytes>, maps: &DictionaryMaps) {
+    regions.push(maps.offsets_map.clone());
+    regions.push(maps.blocks_map.clone());
+}
+
+fn push_typed_dictionary(regions: &mut Vec<Bytes>, maps: &TypedDictionaryMaps) {
+    regions.push(maps.types_present_map.clone());
+    regions.push(maps.type_offsets_map.clone());
+    regions.push(maps.offsets_map.clone());
+    regions.push(maps.blocks_map.clone());
+}
+
+fn push_bitindex(regions: &mut Vec<Bytes>, maps: &BitIndexMaps) {
+    regions.push(maps.bits_map.clone());
+    regions.push(maps.blocks_map.clone());
+    regions.push(maps.sblocks_map.clone());
+}
+
+fn push_idmaps(regions: &mut Vec<Bytes>, maps: &IdMapMaps) {
+    if let Some(maps) = maps.node_value_idmap_maps.as_ref() {
+        push_bitindex(regions, maps);
+    }
+    if let Some(maps) = maps.predicate_idmap_maps.as_ref() {
+        push_bitindex(regions, maps);
+    }
+}
+
+fn push_adjacency_list(regions: &mut Vec<Bytes>, maps: &AdjacencyListMaps) {
+    push_bitindex(regions, &maps.bitindex_maps);
+    regions.push(maps.nums_map.clone());
+}
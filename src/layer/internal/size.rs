@@ -0,0 +1,121 @@
+//! Storage size accounting for layers.
+use crate::storage::*;
+
+/// The size in bytes of each of the structures making up a layer.
+///
+/// For base layers, all triples are counted as additions and the
+/// `neg_` fields are zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LayerSizeBreakdown {
+    pub node_dictionary: usize,
+    pub predicate_dictionary: usize,
+    pub value_dictionary: usize,
+
+    pub node_value_idmap: usize,
+    pub predicate_idmap: usize,
+
+    pub pos_subjects: usize,
+    pub pos_objects: usize,
+    pub pos_s_p_adjacency_list: usize,
+    pub pos_sp_o_adjacency_list: usize,
+    pub pos_o_ps_adjacency_list: usize,
+    pub pos_predicate_wavelet_tree: usize,
+
+    pub neg_subjects: usize,
+    pub neg_objects: usize,
+    pub neg_s_p_adjacency_list: usize,
+    pub neg_sp_o_adjacency_list: usize,
+    pub neg_o_ps_adjacency_list: usize,
+    pub neg_predicate_wavelet_tree: usize,
+}
+
+impl LayerSizeBreakdown {
+    pub fn from_base_maps(maps: &BaseLayerMaps) -> Self {
+        LayerSizeBreakdown {
+            node_dictionary: dictionary_size(&maps.node_dictionary_maps),
+            predicate_dictionary: dictionary_size(&maps.predicate_dictionary_maps),
+            value_dictionary: typed_dictionary_size(&maps.value_dictionary_maps),
+
+            node_value_idmap: idmap_size(maps.id_map_maps.node_value_idmap_maps.as_ref()),
+            predicate_idmap: idmap_size(maps.id_map_maps.predicate_idmap_maps.as_ref()),
+
+            pos_subjects: maps.subjects_map.as_ref().map(|m| m.len()).unwrap_or(0),
+            pos_objects: maps.objects_map.as_ref().map(|m| m.len()).unwrap_or(0),
+            pos_s_p_adjacency_list: adjacency_list_size(&maps.s_p_adjacency_list_maps),
+            pos_sp_o_adjacency_list: adjacency_list_size(&maps.sp_o_adjacency_list_maps),
+            pos_o_ps_adjacency_list: adjacency_list_size(&maps.o_ps_adjacency_list_maps),
+            pos_predicate_wavelet_tree: bitindex_size(&maps.predicate_wavelet_tree_maps),
+
+            ..Default::default()
+        }
+    }
+
+    pub fn from_child_maps(maps: &ChildLayerMaps) -> Self {
+        LayerSizeBreakdown {
+            node_dictionary: dictionary_size(&maps.node_dictionary_maps),
+            predicate_dictionary: dictionary_size(&maps.predicate_dictionary_maps),
+            value_dictionary: typed_dictionary_size(&maps.value_dictionary_maps),
+
+            node_value_idmap: idmap_size(maps.id_map_maps.node_value_idmap_maps.as_ref()),
+            predicate_idmap: idmap_size(maps.id_map_maps.predicate_idmap_maps.as_ref()),
+
+            pos_subjects: maps.pos_subjects_map.len(),
+            pos_objects: maps.pos_objects_map.len(),
+            pos_s_p_adjacency_list: adjacency_list_size(&maps.pos_s_p_adjacency_list_maps),
+            pos_sp_o_adjacency_list: adjacency_list_size(&maps.pos_sp_o_adjacency_list_maps),
+            pos_o_ps_adjacency_list: adjacency_list_size(&maps.pos_o_ps_adjacency_list_maps),
+            pos_predicate_wavelet_tree: bitindex_size(&maps.pos_predicate_wavelet_tree_maps),
+
+            neg_subjects: maps.neg_subjects_map.len(),
+            neg_objects: maps.neg_objects_map.len(),
+            neg_s_p_adjacency_list: adjacency_list_size(&maps.neg_s_p_adjacency_list_maps),
+            neg_sp_o_adjacency_list: adjacency_list_size(&maps.neg_sp_o_adjacency_list_maps),
+            neg_o_ps_adjacency_list: adjacency_list_size(&maps.neg_o_ps_adjacency_list_maps),
+            neg_predicate_wavelet_tree: bitindex_size(&maps.neg_predicate_wavelet_tree_maps),
+        }
+    }
+
+    /// Returns the sum of all structure sizes.
+    pub fn total(&self) -> usize {
+        self.node_dictionary
+            + self.predicate_dictionary
+            + self.value_dictionary
+            + self.node_value_idmap
+            + self.predicate_idmap
+            + self.pos_subjects
+            + self.pos_objects
+            + self.pos_s_p_adjacency_list
+            + self.pos_sp_o_adjacency_list
+            + self.pos_o_ps_adjacency_list
+            + self.pos_predicate_wavelet_tree
+            + self.neg_subjects
+            + self.neg_objects
+            + self.neg_s_p_adjacency_list
+            + self.neg_sp_o_adjacency_list
+            + self.neg_o_ps_adjacency_list
+            + self.neg_predicate_wavelet_tree
+    }
+}
+
+fn dictionary_size(maps: &DictionaryMaps) -> usize {
+    maps.blocks_map.len() + maps.offsets_map.len()
+}
+
+fn typed_dictionary_size(maps: &TypedDictionaryMaps) -> usize {
+    maps.types_present_map.len()
+        + maps.type_offsets_map.len()
+        + maps.blocks_map.len()
+        + maps.offsets_map.len()
+}
+
+fn bitindex_size(maps: &BitIndexMaps) -> usize {
+    maps.bits_map.len() + maps.blocks_map.len() + maps.sblocks_map.len()
+}
+
+fn idmap_size(maps: Option<&BitIndexMaps>) -> usize {
+    maps.map(bitindex_size).unwrap_or(0)
+}
+
+fn adjacency_list_size(maps: &AdjacencyListMaps) -> usize {
+    bitindex_size(&maps.bitindex_maps) + maps.nums_map.len()
+}
@@ -53,6 +53,9 @@ impl InternalLayerTripleSubjectIterator {
             Some(subjects) => subjects.nearest_index_of(subject) as u64,
         };
 
+        // nearest_index_of returns the index of the first subject at
+        // or after the one we're looking for, or the length of the
+        // subjects array if there is no such subject.
         if self.s_position >= self.s_p_adjacency_list.left_count() as u64 {
             self.s_p_position = self.s_p_adjacency_list.right_count() as u64;
             self.sp_o_position = self.sp_o_adjacency_list.right_count() as u64;
@@ -109,6 +112,71 @@ impl InternalLayerTripleSubjectIterator {
         }
     }
 
+    pub fn seek_subject_predicate_object(
+        mut self,
+        subject: u64,
+        predicate: u64,
+        object: u64,
+    ) -> Self {
+        self.seek_subject_predicate_object_ref(subject, predicate, object);
+
+        self
+    }
+
+    pub fn seek_subject_predicate_object_ref(&mut self, subject: u64, predicate: u64, object: u64) {
+        self.seek_subject_predicate_ref(subject, predicate);
+        if subject == 0 || predicate == 0 || object == 0 {
+            return;
+        }
+
+        if self.s_position >= self.s_p_adjacency_list.left_count() as u64
+            || self.s_p_position >= self.s_p_adjacency_list.right_count() as u64
+        {
+            return;
+        }
+
+        let found_subject = match self.subjects.as_ref() {
+            Some(subjects) => subjects.entry(self.s_position.try_into().unwrap()),
+            None => self.s_position + 1,
+        };
+        if found_subject != subject
+            || self.s_p_adjacency_list.num_at_pos(self.s_p_position) != predicate
+        {
+            // the subject-predicate pair does not exist, so we are
+            // already positioned at the first triple after it.
+            return;
+        }
+
+        // objects are sorted within a subject-predicate pair, so we
+        // can binary search for the first object at or after the one
+        // we're looking for.
+        let mut low = self.sp_o_position;
+        let end = if self.s_p_position + 1 < self.sp_o_adjacency_list.left_count() as u64 {
+            self.sp_o_adjacency_list.offset_for(self.s_p_position + 2)
+        } else {
+            self.sp_o_adjacency_list.right_count() as u64
+        };
+        let mut high = end;
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.sp_o_adjacency_list.num_at_pos(mid) < object {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        if low == end {
+            // all objects come before the one we're looking for, so
+            // move on to the next subject-predicate pair.
+            if self.s_p_adjacency_list.bit_at_pos(self.s_p_position) {
+                self.s_position += 1;
+            }
+            self.s_p_position += 1;
+        }
+        self.sp_o_position = low;
+    }
+
     pub fn seek_s_p_pos(&mut self, pos: u64) {
         self.peeked = None;
 
@@ -202,6 +270,12 @@ impl OptInternalLayerTripleSubjectIterator {
         )
     }
 
+    pub fn seek_subject_predicate_object_ref(&mut self, subject: u64, predicate: u64, object: u64) {
+        if let Some(i) = self.0.as_mut() {
+            i.seek_subject_predicate_object_ref(subject, predicate, object)
+        };
+    }
+
     pub fn peek(&mut self) -> Option<&IdTriple> {
         self.0.as_mut().and_then(|i| i.peek())
     }
@@ -245,6 +319,16 @@ impl InternalTripleSubjectIterator {
         }
     }
 
+    pub fn from_parts(
+        positives: Vec<OptInternalLayerTripleSubjectIterator>,
+        negatives: Vec<OptInternalLayerTripleSubjectIterator>,
+    ) -> Self {
+        Self {
+            positives,
+            negatives,
+        }
+    }
+
     pub fn seek_subject(mut self, subject: u64) -> Self {
         for p in self.positives.iter_mut() {
             p.seek_subject_ref(subject);
@@ -268,6 +352,23 @@ impl InternalTripleSubjectIterator {
 
         self
     }
+
+    pub fn seek_subject_predicate_object(
+        mut self,
+        subject: u64,
+        predicate: u64,
+        object: u64,
+    ) -> Self {
+        for p in self.positives.iter_mut() {
+            p.seek_subject_predicate_object_ref(subject, predicate, object);
+        }
+
+        for n in self.negatives.iter_mut() {
+            n.seek_subject_predicate_object_ref(subject, predicate, object);
+        }
+
+        self
+    }
 }
 
 impl Iterator for InternalTripleSubjectIterator {
@@ -457,10 +558,39 @@ mod tests {
     use crate::layer::base::base_tests::*;
     use crate::layer::child::child_tests::*;
     use crate::layer::*;
-    use tdb_succinct::TdbDataType;
+    use bytes::BytesMut;
+    use tdb_succinct::{LogArray, LogArrayBufBuilder, MonotonicLogArray, TdbDataType};
 
     use std::sync::Arc;
 
+    fn monotonic_logarray(values: &[u64]) -> MonotonicLogArray {
+        let mut buf = BytesMut::new();
+        let mut builder = LogArrayBufBuilder::new(&mut buf, 8);
+        for value in values {
+            builder.push(*value);
+        }
+        builder.finalize();
+
+        MonotonicLogArray::from_logarray(LogArray::parse(buf.freeze()).unwrap())
+    }
+
+    #[test]
+    fn subject_index_lookups() {
+        let subjects = monotonic_logarray(&[3, 5, 8]);
+
+        assert_eq!(Some(1), subjects.index_of(5));
+        assert_eq!(1, subjects.nearest_index_of(5));
+
+        assert_eq!(None, subjects.index_of(1));
+        assert_eq!(0, subjects.nearest_index_of(1));
+
+        assert_eq!(None, subjects.index_of(6));
+        assert_eq!(2, subjects.nearest_index_of(6));
+
+        assert_eq!(None, subjects.index_of(10));
+        assert_eq!(3, subjects.nearest_index_of(10));
+    }
+
     #[tokio::test]
     async fn base_triple_iterator() {
         let base_layer: InternalLayer = example_base_layer().await.into();
@@ -676,6 +806,115 @@ mod tests {
         assert_eq!(expected, triples);
     }
 
+    #[tokio::test]
+    async fn base_triple_iterator_seek_to_subject_predicate_object() {
+        let layer = layer_for_seek_sp_tests().await;
+
+        let triples: Vec<_> = layer
+            .internal_triple_additions()
+            .seek_subject_predicate_object(3, 4, 4)
+            .collect();
+        assert_eq!(
+            vec![IdTriple::new(3, 4, 5), IdTriple::new(5, 3, 6)],
+            triples
+        );
+
+        let triples: Vec<_> = layer
+            .internal_triple_additions()
+            .seek_subject_predicate_object(3, 2, 6)
+            .collect();
+        assert_eq!(
+            vec![
+                IdTriple::new(3, 4, 2),
+                IdTriple::new(3, 4, 3),
+                IdTriple::new(3, 4, 5),
+                IdTriple::new(5, 3, 6)
+            ],
+            triples
+        );
+
+        let triples: Vec<_> = layer
+            .internal_triple_additions()
+            .seek_subject_predicate_object(3, 3, 1)
+            .collect();
+        assert_eq!(
+            vec![
+                IdTriple::new(3, 4, 2),
+                IdTriple::new(3, 4, 3),
+                IdTriple::new(3, 4, 5),
+                IdTriple::new(5, 3, 6)
+            ],
+            triples
+        );
+
+        let triples: Vec<_> = layer
+            .internal_triple_additions()
+            .seek_subject_predicate_object(5, 3, 7)
+            .collect();
+        assert!(triples.is_empty());
+    }
+
+    #[tokio::test]
+    async fn triples_sp_from_starts_at_object() {
+        let layer = layer_for_seek_sp_tests().await;
+
+        let triples: Vec<_> = layer.triples_sp_from(3, 4, 3).collect();
+
+        let expected = vec![
+            IdTriple::new(3, 4, 3),
+            IdTriple::new(3, 4, 5),
+            IdTriple::new(5, 3, 6),
+        ];
+
+        assert_eq!(expected, triples);
+    }
+
+    #[tokio::test]
+    async fn triples_sp_from_past_last_object() {
+        let layer = layer_for_seek_sp_tests().await;
+
+        let triples: Vec<_> = layer.triples_sp_from(3, 4, 6).collect();
+
+        assert_eq!(vec![IdTriple::new(5, 3, 6)], triples);
+    }
+
+    #[tokio::test]
+    async fn triples_after_resumes_scan() {
+        let layer = layer_for_seek_sp_tests().await;
+        let all: Vec<_> = layer.triples().collect();
+
+        for (ix, triple) in all.iter().enumerate() {
+            let mut resumed = all[..=ix].to_vec();
+            resumed.extend(layer.triples_after(*triple));
+
+            assert_eq!(all, resumed);
+        }
+
+        let triples: Vec<_> = layer.triples_after(IdTriple::new(3, 4, 4)).collect();
+        assert_eq!(
+            vec![IdTriple::new(3, 4, 5), IdTriple::new(5, 3, 6)],
+            triples
+        );
+
+        // the largest possible ids should not overflow
+        let triples: Vec<_> = layer.triples_after(IdTriple::new(3, 3, u64::MAX)).collect();
+        assert_eq!(
+            vec![
+                IdTriple::new(3, 4, 2),
+                IdTriple::new(3, 4, 3),
+                IdTriple::new(3, 4, 5),
+                IdTriple::new(5, 3, 6)
+            ],
+            triples
+        );
+        assert_eq!(
+            0,
+            layer
+                .triples_after(IdTriple::new(u64::MAX, u64::MAX, u64::MAX))
+                .count()
+        );
+    }
+
     #[tokio::test]
     async fn base_triple_iterator_seek_to_subject_predicate_nonexistent() {
         let layer = layer_for_seek_sp_tests().await;
@@ -867,8 +1106,7 @@ mod tests {
 
     use crate::storage::memory::*;
     use crate::storage::LayerStore;
-    #[tokio::test]
-    async fn combined_iterator_for_subject() {
+    async fn combined_iterator_for_subject_layer() -> Arc<InternalLayer> {
         let store = MemoryLayerStore::new();
         let mut builder = store.create_base_layer().await.unwrap();
         let base_name = builder.name();
@@ -907,7 +1145,12 @@ mod tests {
         builder.add_value_triple(ValueTriple::new_node("duck", "likes", "cow"));
         builder.commit_boxed().await.unwrap();
 
-        let layer = store.get_layer(child4_name).await.unwrap().unwrap();
+        store.get_layer(child4_name).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn combined_iterator_for_subject() {
+        let layer = combined_iterator_for_subject_layer().await;
 
         let subject_id = layer.subject_id("duck").unwrap();
         let triples: Vec<_> = layer
@@ -923,6 +1166,30 @@ mod tests {
         assert_eq!(expected, triples);
     }
 
+    #[tokio::test]
+    async fn po_pairs_for_subject_in_stack() {
+        let layer = combined_iterator_for_subject_layer().await;
+
+        let subject_id = layer.subject_id("duck").unwrap();
+        let pairs: Vec<_> = layer.po_pairs_for_subject(subject_id).collect();
+
+        let expected = vec![
+            (
+                layer.predicate_id("likes").unwrap(),
+                layer.object_node_id("cow").unwrap(),
+            ),
+            (
+                layer.predicate_id("says").unwrap(),
+                layer
+                    .object_value_id(&String::make_entry(&"quack"))
+                    .unwrap(),
+            ),
+        ];
+
+        assert_eq!(expected, pairs);
+        assert_eq!(0, layer.po_pairs_for_subject(1000).count());
+    }
+
     #[tokio::test]
     async fn combined_iterator_for_subject_predicate() {
         let store = MemoryLayerStore::new();
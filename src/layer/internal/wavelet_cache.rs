@@ -0,0 +1,101 @@
+//! An opt-in cache of predicate wavelet lookups.
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use tdb_succinct::*;
+
+type LookupKey = (bool, u64);
+
+/// A bounded cache of the wavelet lookups used for predicate queries.
+///
+/// Looking up a predicate in a wavelet tree has to reconstruct the
+/// positions of that predicate on every query. With the cache
+/// enabled, the lookup for a predicate is only constructed once and
+/// reused until it is evicted. The cache starts out disabled, in
+/// which case lookups only check an atomic flag. Clones of a layer
+/// share the same cache.
+#[derive(Clone, Default)]
+pub struct WaveletLookupCache {
+    enabled: Arc<AtomicBool>,
+    cache: Arc<Mutex<Option<LruCache<LookupKey, Option<WaveletLookup>>>>>,
+    hits: Arc<AtomicUsize>,
+}
+
+impl WaveletLookupCache {
+    /// Enable the cache, keeping at most `capacity` lookups. A capacity of 0 disables the cache.
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut cache = self.cache.lock().unwrap();
+        *cache = NonZeroUsize::new(capacity).map(LruCache::new);
+        self.enabled.store(cache.is_some(), Ordering::Release);
+    }
+
+    /// Returns whether the cache is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    /// The number of lookups that were served from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn lookup<F: FnOnce() -> Option<WaveletLookup>>(
+        &self,
+        removals: bool,
+        predicate: u64,
+        construct: F,
+    ) -> Option<WaveletLookup> {
+        if !self.is_enabled() {
+            return construct();
+        }
+
+        if let Some(cache) = self.cache.lock().unwrap().as_mut() {
+            if let Some(lookup) = cache.get(&(removals, predicate)) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return lookup.clone();
+            }
+        }
+
+        // construct the lookup without holding the lock, so lookups
+        // of other predicates are not held up.
+        let lookup = construct();
+        if let Some(cache) = self.cache.lock().unwrap().as_mut() {
+            cache.put((removals, predicate), lookup.clone());
+        }
+
+        lookup
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layer::base::base_tests::*;
+    use crate::layer::*;
+
+    #[tokio::test]
+    async fn repeated_predicate_queries_reuse_lookup() {
+        let layer = example_base_layer().await;
+
+        let uncached: Vec<_> = layer.triples_p(3).collect();
+        assert_eq!(0, layer.wavelet_lookup_cache().hits());
+
+        layer.wavelet_lookup_cache().set_capacity(2);
+        let first: Vec<_> = layer.triples_p(3).collect();
+        let second: Vec<_> = layer.triples_p(3).collect();
+
+        assert_eq!(uncached, first);
+        assert_eq!(first, second);
+        assert_eq!(1, layer.wavelet_lookup_cache().hits());
+
+        layer.triples_p(1).count();
+        layer.triples_p(2).count();
+        layer.triples_p(3).count();
+        assert_eq!(1, layer.wavelet_lookup_cache().hits());
+
+        layer.wavelet_lookup_cache().set_capacity(0);
+        assert!(!layer.wavelet_lookup_cache().is_enabled());
+        assert_eq!(first, layer.triples_p(3).collect::<Vec<_>>());
+    }
+}
@@ -1,8 +1,17 @@
 //! Common data structures and traits for all layer types.
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt::Display;
 use std::hash::Hash;
+use std::io::{self, Write};
 
-use tdb_succinct::{TdbDataType, TypedDictEntry};
+use bytes::Bytes;
+use itertools::{EitherOrBoth, Itertools};
+use num_traits::Float;
+
+use tdb_succinct::{Datatype, SizedDictEntry, TdbDataType, TypedDict, TypedDictEntry};
+
+use super::internal::TripleChange;
 
 /// A layer containing dictionary entries and triples.
 ///
@@ -14,6 +23,9 @@ pub trait Layer: Send + Sync {
     fn name(&self) -> [u32; 5];
     fn parent_name(&self) -> Option<[u32; 5]>;
 
+    /// The number of layers in this layer's stack, including this layer itself.
+    fn stack_depth(&self) -> usize;
+
     /// The amount of nodes and values known to this layer.
     /// This also counts entries in the parent.
     fn node_and_value_count(&self) -> usize;
@@ -53,6 +65,40 @@ pub trait Layer: Send + Sync {
         })
     }
 
+    /// The datatype of the object value corresponding to a numerical id.
+    ///
+    /// This will return None if the id refers to a node or doesn't refer to anything.
+    fn object_datatype(&self, object_id: u64) -> Option<Datatype> {
+        self.id_object(object_id)
+            .and_then(|o| o.value_ref().map(|v| v.datatype()))
+    }
+
+    /// Returns the datatypes of the values in this layer and its ancestors, sorted and without duplicates.
+    fn datatypes_present(&self) -> Vec<Datatype>;
+
+    /// Returns every value object in this layer and its ancestors, along with its object id and datatype.
+    fn value_objects(&self) -> Box<dyn Iterator<Item = (u64, Datatype, SizedDictEntry)> + Send>;
+
+    /// Returns every predicate in this layer and its ancestors, along with its id.
+    ///
+    /// Predicates are returned layer by layer, starting at the bottom
+    /// of the stack.
+    fn predicates(&self) -> Box<dyn Iterator<Item = (u64, String)> + Send>;
+
+    /// Returns a handle to the value dictionary of this layer, without its ancestors.
+    ///
+    /// The handle shares its buffers with the layer, so obtaining it is
+    /// an O(1) clone that neither copies nor re-parses the dictionary.
+    /// Ids in the handle are local to this layer's dictionary and are
+    /// not object ids.
+    fn value_dictionary_handle(&self) -> TypedDict;
+
+    /// Returns the metadata blob that was stored alongside this layer when it was built, if any.
+    ///
+    /// Metadata belongs to a single layer and is not inherited from
+    /// ancestors.
+    fn metadata(&self) -> Option<Bytes>;
+
     /// Check if the given id refers to a node.
     ///
     /// This will return None if the id doesn't refer to anything.
@@ -79,6 +125,14 @@ pub trait Layer: Send + Sync {
         self.triple_exists(triple.subject, triple.predicate, triple.object)
     }
 
+    /// Returns the name of the layer in this stack that added the given triple.
+    ///
+    /// This is the topmost layer that added the triple, provided that
+    /// no layer above it removed it again. If the triple does not
+    /// exist, None is returned. For a rolled-up part of the stack,
+    /// the name of the layer that was rolled up is returned.
+    fn provenance_of(&self, triple: IdTriple) -> Option<[u32; 5]>;
+
     /// Returns true if the given triple exists, and false otherwise.
     fn value_triple_exists(&self, triple: &ValueTriple) -> bool {
         self.value_triple_to_id(triple)
@@ -93,6 +147,59 @@ pub trait Layer: Send + Sync {
     fn triples_sp(&self, subject: u64, predicate: u64)
         -> Box<dyn Iterator<Item = IdTriple> + Send>;
 
+    /// Returns an iterator over all triples starting at the first triple with the given subject and predicate whose object is at or after `object_start`.
+    ///
+    /// Unlike `triples_sp`, iteration continues past the end of the
+    /// subject-predicate group into subsequent triples.
+    fn triples_sp_from(
+        &self,
+        subject: u64,
+        predicate: u64,
+        object_start: u64,
+    ) -> Box<dyn Iterator<Item = IdTriple> + Send>;
+
+    /// Returns an iterator over all triples that come after the given triple, in the order of `triples`.
+    ///
+    /// The given triple does not need to exist, which makes it usable
+    /// as a key to resume an earlier scan.
+    fn triples_after(&self, after: IdTriple) -> Box<dyn Iterator<Item = IdTriple> + Send> {
+        let next = after
+            .object
+            .checked_add(1)
+            .map(|object| (after.subject, after.predicate, object))
+            .or_else(|| {
+                after
+                    .predicate
+                    .checked_add(1)
+                    .map(|predicate| (after.subject, predicate, 0))
+            })
+            .or_else(|| after.subject.checked_add(1).map(|subject| (subject, 0, 0)));
+
+        match next {
+            Some((subject, predicate, object)) => self.triples_sp_from(subject, predicate, object),
+            None => Box::new(std::iter::empty()),
+        }
+    }
+
+    /// Returns an iterator over the objects of all triples with the given subject and predicate, in ascending order.
+    fn objects_sp(&self, subject: u64, predicate: u64) -> Box<dyn Iterator<Item = u64> + Send> {
+        Box::new(self.triples_sp(subject, predicate).map(|t| t.object))
+    }
+
+    /// Returns the number of triples with the given subject and predicate.
+    fn count_sp(&self, subject: u64, predicate: u64) -> usize {
+        self.objects_sp(subject, predicate).count()
+    }
+
+    /// Returns an iterator over the distinct predicate-object pairs of the given subject, in ascending order.
+    fn po_pairs_for_subject(&self, subject: u64) -> Box<dyn Iterator<Item = (u64, u64)> + Send> {
+        Box::new(
+            self.triples_s(subject)
+                .map(|t| (t.predicate, t.object))
+                .dedup(),
+        )
+    }
+
     /// Convert a `ValueTriple` to an `IdTriple`, returning None if any of the strings in the triple could not be resolved.
     fn value_triple_to_id(&self, triple: &ValueTriple) -> Option<IdTriple> {
         self.subject_id(&triple.subject).and_then(|subject| {
@@ -110,10 +217,205 @@ pub trait Layer: Send + Sync {
         })
     }
 
+    /// Convert a batch of `ValueTriple`s to `IdTriple`s, resolving every distinct component only once.
+    ///
+    /// The result is in the same order as the input, with None for
+    /// triples that have a component unknown to this layer.
+    fn value_triples_to_ids(&self, triples: &[ValueTriple]) -> Vec<Option<IdTriple>> {
+        let mut subjects: Vec<&str> = triples.iter().map(|t| t.subject.as_str()).collect();
+        subjects.sort_unstable();
+        subjects.dedup();
+        let mut predicates: Vec<&str> = triples.iter().map(|t| t.predicate.as_str()).collect();
+        predicates.sort_unstable();
+        predicates.dedup();
+        let mut objects: Vec<&ObjectType> = triples.iter().map(|t| &t.object).collect();
+        objects.sort_unstable();
+        objects.dedup();
+
+        let subject_ids: Vec<_> = subjects.iter().map(|s| self.subject_id(s)).collect();
+        let predicate_ids: Vec<_> = predicates.iter().map(|p| self.predicate_id(p)).collect();
+        let object_ids: Vec<_> = objects
+            .iter()
+            .map(|o| match o {
+                ObjectType::Node(node) => self.object_node_id(node),
+                ObjectType::Value(value) => self.object_value_id(value),
+            })
+            .collect();
+
+        triples
+            .iter()
+            .map(|t| {
+                let subject = subject_ids[subjects.binary_search(&t.subject.as_str()).unwrap()]?;
+                let predicate =
+                    predicate_ids[predicates.binary_search(&t.predicate.as_str()).unwrap()]?;
+                let object = object_ids[objects.binary_search(&&t.object).unwrap()]?;
+
+                Some(IdTriple::new(subject, predicate, object))
+            })
+            .collect()
+    }
+
+    /// Check for a batch of triples whether each of them exists in this layer.
+    ///
+    /// The batch is sorted and then matched against a single pass
+    /// over `triples()`. The result is in the same order as the input.
+    fn triples_exist(&self, triples: &[IdTriple]) -> Vec<bool> {
+        let mut order: Vec<usize> = (0..triples.len()).collect();
+        order.sort_unstable_by_key(|&i| triples[i]);
+
+        let mut result = vec![false; triples.len()];
+        let mut existing = self.triples().peekable();
+        for i in order {
+            let triple = triples[i];
+            while existing.next_if(|t| *t < triple).is_some() {}
+            result[i] = existing.peek() == Some(&triple);
+        }
+
+        result
+    }
+
     fn triples_p(&self, predicate: u64) -> Box<dyn Iterator<Item = IdTriple> + Send>;
 
+    /// Returns how many objects the subjects using the given predicate have for it.
+    ///
+    /// `triples_p` returns triples in subject order, so the objects
+    /// of each subject form a single run. Only the length of the
+    /// current run and the running totals are kept.
+    fn predicate_fanout(&self, predicate: u64) -> Fanout {
+        let mut fanout = Fanout::default();
+        let mut total = 0;
+        let mut current: Option<(u64, usize)> = None;
+        let mut finish_run = |count: usize| {
+            if fanout.subject_count == 0 || count < fanout.min {
+                fanout.min = count;
+            }
+            fanout.max = fanout.max.max(count);
+            fanout.subject_count += 1;
+            total += count;
+        };
+        for triple in self.triples_p(predicate) {
+            match current.as_mut() {
+                Some((subject, count)) if *subject == triple.subject => *count += 1,
+                _ => {
+                    if let Some((_, count)) = current {
+                        finish_run(count);
+                    }
+                    current = Some((triple.subject, 1));
+                }
+            }
+        }
+        if let Some((_, count)) = current {
+            finish_run(count);
+        }
+
+        if fanout.subject_count != 0 {
+            fanout.mean = total as f64 / fanout.subject_count as f64;
+        }
+
+        fanout
+    }
+
+    /// Returns the triples of this layer in batches per predicate, in ascending predicate order.
+    ///
+    /// Each batch is retrieved with `triples_p`, which uses the
+    /// predicate wavelet tree. Predicates without triples are skipped.
+    fn triples_grouped_by_predicate(&self) -> Box<dyn Iterator<Item = (u64, Vec<IdTriple>)> + '_> {
+        Box::new(
+            (1..=self.predicate_count() as u64).filter_map(move |predicate| {
+                let triples: Vec<_> = self.triples_p(predicate).collect();
+                if triples.is_empty() {
+                    None
+                } else {
+                    Some((predicate, triples))
+                }
+            }),
+        )
+    }
+
+    /// Returns an iterator over all triples with the given object, across the whole layer stack.
+    ///
+    /// This uses the object index of each layer, and leaves out
+    /// triples that were removed by a later layer.
     fn triples_o(&self, object: u64) -> Box<dyn Iterator<Item = IdTriple> + Send>;
 
+    /// Iterator over all triples known to this layer, ordered by object, predicate and subject.
+    fn triples_object_order(&self) -> Box<dyn Iterator<Item = IdTriple> + Send>;
+
+    /// Returns an iterator over the ids that are the subject of at least one triple, in ascending order.
+    fn used_subject_ids(&self) -> Box<dyn Iterator<Item = u64> + Send> {
+        Box::new(self.triples().map(|t| t.subject).dedup())
+    }
+
+    /// Returns an iterator over the ids that are the object of at least one triple, in ascending order.
+    fn used_object_ids(&self) -> Box<dyn Iterator<Item = u64> + Send> {
+        Box::new(self.triples_object_order().map(|t| t.object).dedup())
+    }
+
+    /// Returns dense remappings of the used node ids and the used value ids.
+    ///
+    /// Nodes and values share one id space, with all nodes coming
+    /// before all values. The node remapping lists every id that is
+    /// used as a subject or as a node object, in ascending order, so
+    /// old id `nodes[i]` becomes new id `i + 1`. The value remapping
+    /// lists the used value ids in ascending order, and old id
+    /// `values[i]` becomes new id `nodes.len() + i + 1`. Unused ids
+    /// are left out, and the relative order of the remaining ids is
+    /// preserved.
+    fn compaction_map(&self) -> (Vec<u64>, Vec<u64>) {
+        let (node_objects, values): (Vec<u64>, Vec<u64>) = self
+            .used_object_ids()
+            .partition(|id| self.id_object_is_node(*id) == Some(true));
+        let nodes = self
+            .used_subject_ids()
+            .merge(node_objects)
+            .dedup()
+            .collect();
+
+        (nodes, values)
+    }
+
+    /// Returns an iterator over all triples whose predicate is one of the given predicates.
+    ///
+    /// Triples are looked up per predicate and merged, so they are
+    /// returned in ascending order.
+    fn triples_with_predicates(
+        &self,
+        predicates: &[u64],
+    ) -> Box<dyn Iterator<Item = IdTriple> + Send> {
+        let mut predicates = predicates.to_vec();
+        predicates.sort_unstable();
+        predicates.dedup();
+
+        Box::new(
+            predicates
+                .into_iter()
+                .map(|predicate| self.triples_p(predicate))
+                .kmerge(),
+        )
+    }
+
+    /// Returns an iterator over all triples whose object is of the given kind, in ascending order.
+    fn triples_filtered(
+        &self,
+        object_kind: ObjectKindFilter,
+    ) -> Box<dyn Iterator<Item = IdTriple> + '_> {
+        let triples = self.triples();
+        match object_kind {
+            ObjectKindFilter::Any => triples,
+            ObjectKindFilter::Nodes => {
+                Box::new(triples.filter(move |t| self.id_object_is_node(t.object) == Some(true)))
+            }
+            ObjectKindFilter::Values => {
+                Box::new(triples.filter(move |t| self.id_object_is_value(t.object) == Some(true)))
+            }
+        }
+    }
+
+    /// Returns an iterator over all distinct subject-predicate pairs, in ascending order.
+    fn sp_pairs(&self) -> Box<dyn Iterator<Item = (u64, u64)> + Send> {
+        Box::new(self.triples().map(|t| (t.subject, t.predicate)).dedup())
+    }
+
     /// Convert all known strings in the given string triple to ids.
     fn value_triple_to_partially_resolved(&self, triple: ValueTriple) -> PartiallyResolvedTriple {
         PartiallyResolvedTriple {
@@ -151,20 +453,408 @@ pub trait Layer: Send + Sync {
         })
     }
 
+    /// Returns a page of at most `limit` triples with the given subject.
+    ///
+    /// Triples are returned in predicate and object order, starting
+    /// after the given `(predicate, object)` key if one is given. The
+    /// second element of the result is the key to continue from, or
+    /// None if there are no more triples. If the page is empty while
+    /// triples remain, as happens with a `limit` of 0, the key to
+    /// continue from is the given one, or `(0, 0)` if none was
+    /// given. As ids start at 1, `(0, 0)` comes before every triple.
+    fn triples_s_paginated(
+        &self,
+        subject: u64,
+        after: Option<(u64, u64)>,
+        limit: usize,
+    ) -> (Vec<IdTriple>, Option<(u64, u64)>) {
+        // seek straight to the key rather than scanning all earlier triples
+        let iter: Box<dyn Iterator<Item = IdTriple> + Send> = match after {
+            Some((predicate, object)) => Box::new(
+                self.triples_after(IdTriple::new(subject, predicate, object))
+                    .take_while(move |t| t.subject == subject),
+            ),
+            None => self.triples_s(subject),
+        };
+        let mut iter = iter.peekable();
+        let page: Vec<_> = iter.by_ref().take(limit).collect();
+        let next = if iter.peek().is_some() {
+            Some(
+                page.last()
+                    .map(|t| (t.predicate, t.object))
+                    .unwrap_or_else(|| after.unwrap_or((0, 0))),
+            )
+        } else {
+            None
+        };
+
+        (page, next)
+    }
+
+    /// Returns an iterator over all triples, with subject, predicate and object resolved.
+    ///
+    /// Since triples are returned in subject and predicate order,
+    /// the last resolved subject and predicate strings are reused
+    /// rather than being looked up again for every triple.
+    fn resolved_triples(
+        &self,
+    ) -> Box<dyn Iterator<Item = (String, String, ObjectType)> + Send + '_> {
+        let mut last_subject: Option<(u64, String)> = None;
+        let mut last_predicate: Option<(u64, String)> = None;
+        Box::new(self.triples().filter_map(move |t| {
+            let subject = match &last_subject {
+                Some((id, subject)) if *id == t.subject => subject.clone(),
+                _ => {
+                    let subject = self.id_subject(t.subject)?;
+                    last_subject = Some((t.subject, subject.clone()));
+                    subject
+                }
+            };
+            let predicate = match &last_predicate {
+                Some((id, predicate)) if *id == t.predicate => predicate.clone(),
+                _ => {
+                    let predicate = self.id_predicate(t.predicate)?;
+                    last_predicate = Some((t.predicate, predicate.clone()));
+                    predicate
+                }
+            };
+            let object = self.id_object(t.object)?;
+
+            Some((subject, predicate, object))
+        }))
+    }
+
+    /// Returns a hash over all triples in this layer, in resolved form.
+    ///
+    /// Since the hash only depends on the resolved triples, two layers
+    /// with the same contents get the same fingerprint regardless of
+    /// how they are stacked or whether they were rolled up.
+    fn triples_fingerprint(&self) -> [u8; 32] {
+        let mut triples: Vec<_> = self.resolved_triples().collect();
+        triples.sort();
+
+        let mut hasher = blake3::Hasher::new();
+        for (subject, predicate, object) in triples {
+            hash_length_prefixed(&mut hasher, subject.as_bytes());
+            hash_length_prefixed(&mut hasher, predicate.as_bytes());
+            match object {
+                ObjectType::Node(node) => {
+                    hasher.update(&[0]);
+                    hash_length_prefixed(&mut hasher, node.as_bytes());
+                }
+                ObjectType::Value(value) => {
+                    hasher.update(&[1, value.datatype() as u8]);
+                    hash_length_prefixed(&mut hasher, &value.to_bytes());
+                }
+            }
+        }
+
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Writes all triples in this layer to the given writer in N-Triples format.
+    ///
+    /// Nodes and predicates are written as IRIs, with characters
+    /// that N-Triples does not allow in IRIs written as `\uXXXX`
+    /// escapes. This returns an error for values of a datatype that
+    /// has no literal form yet.
+    fn write_ntriples(&self, w: &mut dyn Write) -> io::Result<()> {
+        for (subject, predicate, object) in self.resolved_triples() {
+            write_terms(w, &subject, &predicate, &object)?;
+            writeln!(w, " .")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes all triples in this layer to the given writer in N-Quads format.
+    ///
+    /// Every quad gets the given graph IRI as its fourth column. To
+    /// label quads with the layer they came from, pass the layer name
+    /// as produced by `name_to_string`.
+    fn write_nquads(&self, w: &mut dyn Write, graph: &str) -> io::Result<()> {
+        for (subject, predicate, object) in self.resolved_triples() {
+            write_terms(w, &subject, &predicate, &object)?;
+            writeln!(w, " <{}> .", escape_iri(graph))?;
+        }
+
+        Ok(())
+    }
+
     /// Returns the total amount of triple additions in this layer and all its parents.
     fn triple_addition_count(&self) -> usize;
 
     /// Returns the total amount of triple removals in this layer and all its parents.
     fn triple_removal_count(&self) -> usize;
 
+    /// Returns true if this layer or any of its parents removes triples.
+    ///
+    /// This stops at the first layer with removals, so it is cheaper
+    /// than comparing `triple_removal_count` to 0.
+    fn stack_has_removals(&self) -> bool;
+
     /// Returns the total amount of triples in this layer and all its parents.
     fn triple_count(&self) -> usize {
         self.triple_addition_count() - self.triple_removal_count()
     }
 
+    /// Returns true if no triples are visible in this layer.
+    ///
+    /// This only looks at the triple counts, so the dictionaries may
+    /// still have entries.
+    fn is_empty(&self) -> bool {
+        self.triple_count() == 0
+    }
+
+    /// Returns the triples of this layer as Arrow record batches of subject, predicate and object columns.
+    ///
+    /// The columns hold the numerical ids, or the resolved strings if
+    /// `resolve` is true. See `arrow_triple_schema` for the schema.
+    #[cfg(feature = "arrow")]
+    fn to_arrow_batches(
+        &self,
+        resolve: bool,
+    ) -> Box<dyn Iterator<Item = arrow::record_batch::RecordBatch> + '_> {
+        super::arrow_export::arrow_triple_batches(self, resolve)
+    }
+
+    /// Returns an iterator over the additions and removals made by this layer alone, in ascending triple order.
+    ///
+    /// Changes made by parent layers are not included.
+    fn local_changes(&self) -> Box<dyn Iterator<Item = (TripleChange, IdTriple)> + Send>;
+
+    /// Returns an iterator over the additions and removals made by this layer alone, resolved to strings.
+    ///
+    /// This is `local_changes` with every triple converted using
+    /// `id_triple_to_string`.
+    fn local_changes_strings(
+        &self,
+    ) -> Box<dyn Iterator<Item = (TripleChange, ValueTriple)> + Send + '_> {
+        Box::new(
+            self.local_changes()
+                .filter_map(move |(change, t)| Some((change, self.id_triple_to_string(&t)?))),
+        )
+    }
+
+    /// Returns the position of the given subject among the subjects this layer itself adds triples for.
+    ///
+    /// This is the index in the layer's subjects array. When the
+    /// layer has no subjects array, every subject up to the last one
+    /// has a position, which is the subject id minus one. None is
+    /// returned if the subject has no position in this layer.
+    fn subject_position(&self, subject: u64) -> Option<usize>;
+
     fn single_triple_sp(&self, subject: u64, predicate: u64) -> Option<IdTriple>;
 }
 
+/// Returns the triples that occur in both of the given ascending triple iterators.
+///
+/// The result is ascending as well. A triple that occurs several
+/// times in both inputs is returned as many times as it is matched.
+pub fn intersect_triples<A: IntoIterator<Item = IdTriple>, B: IntoIterator<Item = IdTriple>>(
+    a: A,
+    b: B,
+) -> impl Iterator<Item = IdTriple> {
+    a.into_iter()
+        .merge_join_by(b, |a, b| a.cmp(b))
+        .filter_map(|either| match either {
+            EitherOrBoth::Both(t, _) => Some(t),
+            _ => None,
+        })
+}
+
+/// Co-iterates two triple iterators, grouping both by subject and aligning the groups.
+///
+/// Both inputs must be sorted by subject. Every subject that occurs
+/// in either input is emitted once, in ascending order, along with its
+/// triples from `a` and from `b`. A subject that occurs in only one of
+/// the inputs gets an empty group for the other.
+pub fn subject_merge_join<A: Iterator<Item = IdTriple>, B: Iterator<Item = IdTriple>>(
+    a: A,
+    b: B,
+) -> impl Iterator<Item = (u64, Vec<IdTriple>, Vec<IdTriple>)> {
+    group_by_subject(a)
+        .merge_join_by(group_by_subject(b), |(a, _), (b, _)| a.cmp(b))
+        .map(|either| match either {
+            EitherOrBoth::Both((subject, a), (_, b)) => (subject, a, b),
+            EitherOrBoth::Left((subject, a)) => (subject, a, Vec::new()),
+            EitherOrBoth::Right((subject, b)) => (subject, Vec::new(), b),
+        })
+}
+
+fn group_by_subject<I: Iterator<Item = IdTriple>>(
+    triples: I,
+) -> impl Iterator<Item = (u64, Vec<IdTriple>)> {
+    let mut triples = triples.peekable();
+    std::iter::from_fn(move || {
+        let subject = triples.peek()?.subject;
+        let mut group = Vec::new();
+        while let Some(triple) = triples.next_if(|t| t.subject == subject) {
+            group.push(triple);
+        }
+
+        Some((subject, group))
+    })
+}
+
+/// The kind of object to return triples for in `Layer::triples_filtered`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectKindFilter {
+    Nodes,
+    Values,
+    Any,
+}
+
+/// The side of a comparison a triple was found on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    OnlyA,
+    OnlyB,
+}
+
+/// Returns the triples that occur in exactly one of the two given layers, tagged with the layer they occur in.
+///
+/// Since the layers don't need to share an id space, triples are
+/// compared in resolved form. The result is ordered by triple.
+pub fn triples_symmetric_difference(
+    a: &dyn Layer,
+    b: &dyn Layer,
+) -> impl Iterator<Item = (Side, ValueTriple)> {
+    let sorted_triples = |layer: &dyn Layer| {
+        let mut triples: Vec<_> = layer
+            .resolved_triples()
+            .map(|(subject, predicate, object)| ValueTriple {
+                subject,
+                predicate,
+                object,
+            })
+            .collect();
+        triples.sort();
+
+        triples
+    };
+
+    sorted_triples(a)
+        .into_iter()
+        .merge_join_by(sorted_triples(b), |a, b| a.cmp(b))
+        .filter_map(|either| match either {
+            EitherOrBoth::Left(t) => Some((Side::OnlyA, t)),
+            EitherOrBoth::Right(t) => Some((Side::OnlyB, t)),
+            EitherOrBoth::Both(_, _) => None,
+        })
+}
+
+/// Returns the triples added and removed in `a` relative to the baseline `b`.
+///
+/// Like `triples_symmetric_difference`, this works for layers on
+/// unrelated stacks. Both lists are ordered by triple.
+pub fn resolved_triple_diff(a: &dyn Layer, b: &dyn Layer) -> (Vec<ValueTriple>, Vec<ValueTriple>) {
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    for (side, triple) in triples_symmetric_difference(a, b) {
+        match side {
+            Side::OnlyA => added.push(triple),
+            Side::OnlyB => removed.push(triple),
+        }
+    }
+
+    (added, removed)
+}
+
+fn hash_length_prefixed(hasher: &mut blake3::Hasher, bytes: &[u8]) {
+    hasher.update(&(bytes.len() as u64).to_be_bytes());
+    hasher.update(bytes);
+}
+
+fn write_terms(
+    w: &mut dyn Write,
+    subject: &str,
+    predicate: &str,
+    object: &ObjectType,
+) -> io::Result<()> {
+    write!(w, "<{}> <{}> ", escape_iri(subject), escape_iri(predicate))?;
+    match object {
+        ObjectType::Node(node) => write!(w, "<{}>", escape_iri(node)),
+        ObjectType::Value(value) => write_literal(w, value),
+    }
+}
+
+/// Escapes the characters that may not appear in an N-Triples IRI as `\uXXXX`.
+fn escape_iri(iri: &str) -> Cow<str> {
+    let forbidden = |c: char| c <= ' ' || "<>\"{}|^`\\".contains(c);
+    if !iri.contains(forbidden) {
+        return Cow::Borrowed(iri);
+    }
+
+    let mut escaped = String::with_capacity(iri.len());
+    for c in iri.chars() {
+        if forbidden(c) {
+            escaped.push_str(&format!("\\u{:04X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+
+    Cow::Owned(escaped)
+}
+
+/// Returns the xsd lexical form of a float, which spells out the special values as `INF`, `-INF` and `NaN`.
+pub(crate) fn xsd_float_lexical<T: Float + Display>(value: T) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value.is_sign_negative() {
+            "-INF".to_string()
+        } else {
+            "INF".to_string()
+        }
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_literal(w: &mut dyn Write, value: &TypedDictEntry) -> io::Result<()> {
+    let (lexical, datatype) = match value.datatype() {
+        Datatype::String => {
+            // a corrupt value dictionary must not make the export panic
+            let escaped = String::from_utf8_lossy(&value.to_bytes())
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r");
+            return write!(w, "\"{}\"", escaped);
+        }
+        Datatype::UInt32 => (value.as_val::<u32, u32>().to_string(), "unsignedInt"),
+        Datatype::Int32 => (value.as_val::<i32, i32>().to_string(), "int"),
+        Datatype::UInt64 => (value.as_val::<u64, u64>().to_string(), "unsignedLong"),
+        Datatype::Int64 => (value.as_val::<i64, i64>().to_string(), "long"),
+        Datatype::Float32 => (xsd_float_lexical(value.as_val::<f32, f32>()), "float"),
+        Datatype::Float64 => (xsd_float_lexical(value.as_val::<f64, f64>()), "double"),
+        datatype => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no literal form for values of datatype {:?}", datatype),
+            ))
+        }
+    };
+
+    write!(
+        w,
+        "\"{}\"^^<http://www.w3.org/2001/XMLSchema#{}>",
+        lexical, datatype
+    )
+}
+
+/// The distribution of the amount of objects per subject for a predicate.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Fanout {
+    /// The amount of subjects that have the predicate.
+    pub subject_count: usize,
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+}
+
 pub struct LayerCounts {
     pub node_count: usize,
     pub predicate_count: usize,
@@ -656,4 +1346,591 @@ mod tests {
 
         assert_eq!(expected, results);
     }
+
+    #[tokio::test]
+    async fn resolved_triples_renders_nodes_and_values() {
+        let files = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+
+        builder.commit().await.unwrap();
+
+        let base: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let triples: Vec<_> = base.resolved_triples().collect();
+
+        assert_eq!(
+            vec![
+                (
+                    "cow".to_string(),
+                    "likes".to_string(),
+                    ObjectType::Node("duck".to_string())
+                ),
+                (
+                    "cow".to_string(),
+                    "says".to_string(),
+                    ObjectType::Value(String::make_entry(&"moo"))
+                ),
+            ],
+            triples
+        );
+    }
+
+    #[tokio::test]
+    async fn nquads_extend_ntriples_with_graph() {
+        let files = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "\"moo\""));
+        builder.add_value_triple(ValueTriple::new_value(
+            "cow",
+            "weight",
+            u32::make_entry(&600),
+        ));
+
+        builder.commit().await.unwrap();
+
+        let base: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let mut ntriples = Vec::new();
+        base.write_ntriples(&mut ntriples).unwrap();
+        let ntriples = String::from_utf8(ntriples).unwrap();
+
+        let mut nquads = Vec::new();
+        base.write_nquads(&mut nquads, "http://example.com/graph")
+            .unwrap();
+        let nquads = String::from_utf8(nquads).unwrap();
+
+        assert_eq!(
+            vec![
+                "<cow> <likes> <duck> .",
+                "<cow> <says> \"\\\"moo\\\"\" .",
+                "<cow> <weight> \"600\"^^<http://www.w3.org/2001/XMLSchema#unsignedInt> .",
+            ],
+            ntriples.lines().collect::<Vec<_>>()
+        );
+
+        assert_eq!(3, nquads.lines().count());
+        for (quad, triple) in nquads.lines().zip(ntriples.lines()) {
+            let quad = quad.strip_suffix(" <http://example.com/graph> .").unwrap();
+            assert_eq!(triple.strip_suffix(" .").unwrap(), quad);
+        }
+    }
+
+    #[tokio::test]
+    async fn ntriples_escape_iris_and_special_floats() {
+        let files = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+
+        builder.add_value_triple(ValueTriple::new_node("a b", "x>y", "{z}"));
+        builder.add_value_triple(ValueTriple::new_value(
+            "c",
+            "p",
+            f64::make_entry(&f64::NEG_INFINITY),
+        ));
+        builder.add_value_triple(ValueTriple::new_value(
+            "c",
+            "q",
+            f32::make_entry(&f32::INFINITY),
+        ));
+        builder.add_value_triple(ValueTriple::new_value("c", "r", f64::make_entry(&f64::NAN)));
+
+        builder.commit().await.unwrap();
+
+        let base: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let mut ntriples = Vec::new();
+        base.write_ntriples(&mut ntriples).unwrap();
+        let ntriples = String::from_utf8(ntriples).unwrap();
+
+        assert_eq!(
+            vec![
+                "<a\\u0020b> <x\\u003Ey> <\\u007Bz\\u007D> .",
+                "<c> <p> \"-INF\"^^<http://www.w3.org/2001/XMLSchema#double> .",
+                "<c> <q> \"INF\"^^<http://www.w3.org/2001/XMLSchema#float> .",
+                "<c> <r> \"NaN\"^^<http://www.w3.org/2001/XMLSchema#double> .",
+            ],
+            ntriples.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn paginate_triples_s() {
+        let files = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "pig"));
+        builder.add_value_triple(ValueTriple::new_node("cow", "hates", "horse"));
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "mooo"));
+        builder.add_value_triple(ValueTriple::new_string_value("pig", "says", "oink"));
+
+        builder.commit().await.unwrap();
+
+        let base: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let subject = base.subject_id("cow").unwrap();
+        let mut paginated = Vec::new();
+        let mut after = None;
+        let mut pages = 0;
+        loop {
+            let (page, next) = base.triples_s_paginated(subject, after, 2);
+            assert!(page.len() <= 2);
+            paginated.extend(page);
+            pages += 1;
+            match next {
+                Some(key) => after = Some(key),
+                None => break,
+            }
+        }
+
+        let expected: Vec<_> = base.triples_s(subject).collect();
+        assert_eq!(5, expected.len());
+        assert_eq!(expected, paginated);
+        assert_eq!(3, pages);
+    }
+
+    #[tokio::test]
+    async fn paginate_triples_with_zero_limit() {
+        let files = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "pig"));
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+
+        builder.commit().await.unwrap();
+
+        let base: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let subject = base.subject_id("cow").unwrap();
+        let (page, next) = base.triples_s_paginated(subject, None, 0);
+        assert!(page.is_empty());
+        let next = next.unwrap();
+        let (page, _) = base.triples_s_paginated(subject, Some(next), 3);
+        assert_eq!(base.triples_s(subject).collect::<Vec<_>>(), page);
+
+        let (first, after) = base.triples_s_paginated(subject, None, 1);
+        let (page, next) = base.triples_s_paginated(subject, after, 0);
+        assert!(page.is_empty());
+        assert_eq!(after, next);
+        let (rest, next) = base.triples_s_paginated(subject, next, 3);
+        assert_eq!(None, next);
+        assert_eq!(
+            base.triples_s(subject).collect::<Vec<_>>(),
+            first.into_iter().chain(rest).collect::<Vec<_>>()
+        );
+
+        let (_, last) = base.triples_s_paginated(subject, None, 3);
+        assert_eq!(None, last);
+    }
+
+    #[tokio::test]
+    async fn local_changes_of_child_layer() {
+        let files = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_node("pig", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+
+        builder.commit().await.unwrap();
+
+        let base: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let files = child_layer_files();
+        let mut builder =
+            SimpleLayerBuilder::from_parent([5, 4, 3, 2, 1], base.clone(), files.clone());
+        builder.remove_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "pig"));
+        builder.remove_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.add_value_triple(ValueTriple::new_string_value("pig", "says", "oink"));
+        builder.commit().await.unwrap();
+
+        let child: Arc<InternalLayer> = Arc::new(
+            ChildLayer::load_from_files([5, 4, 3, 2, 1], base.clone(), &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let changes: Vec<_> = child
+            .local_changes()
+            .map(|(change, t)| (change, child.id_triple_to_string(&t).unwrap()))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (
+                    TripleChange::Removal,
+                    ValueTriple::new_node("cow", "likes", "duck")
+                ),
+                (
+                    TripleChange::Addition,
+                    ValueTriple::new_node("cow", "likes", "pig")
+                ),
+                (
+                    TripleChange::Removal,
+                    ValueTriple::new_string_value("cow", "says", "moo")
+                ),
+                (
+                    TripleChange::Addition,
+                    ValueTriple::new_string_value("pig", "says", "oink")
+                ),
+            ],
+            changes
+        );
+        assert_eq!(changes, child.local_changes_strings().collect::<Vec<_>>());
+
+        let base_changes: Vec<_> = base.local_changes().collect();
+        assert_eq!(3, base_changes.len());
+        assert!(base_changes
+            .iter()
+            .all(|(change, _)| *change == TripleChange::Addition));
+    }
+
+    #[tokio::test]
+    async fn datatypes_present_across_stack() {
+        let files = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.commit().await.unwrap();
+        let base: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let files = child_layer_files();
+        let mut builder =
+            SimpleLayerBuilder::from_parent([5, 4, 3, 2, 1], base.clone(), files.clone());
+        builder.add_value_triple(ValueTriple::new_value("cow", "legs", u32::make_entry(&4)));
+        builder.commit().await.unwrap();
+        let child1: Arc<InternalLayer> = Arc::new(
+            ChildLayer::load_from_files([5, 4, 3, 2, 1], base.clone(), &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let files = child_layer_files();
+        let mut builder =
+            SimpleLayerBuilder::from_parent([5, 5, 5, 5, 5], child1.clone(), files.clone());
+        builder.add_value_triple(ValueTriple::new_value(
+            "cow",
+            "weight",
+            i64::make_entry(&-3),
+        ));
+        builder.add_value_triple(ValueTriple::new_string_value("pig", "says", "oink"));
+        builder.commit().await.unwrap();
+        let child2: Arc<InternalLayer> = Arc::new(
+            ChildLayer::load_from_files([5, 5, 5, 5, 5], child1.clone(), &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        assert_eq!(vec![Datatype::String], base.datatypes_present());
+
+        let mut expected = vec![Datatype::String, Datatype::UInt32];
+        expected.sort();
+        assert_eq!(expected, child1.datatypes_present());
+
+        let mut expected = vec![Datatype::String, Datatype::UInt32, Datatype::Int64];
+        expected.sort();
+        assert_eq!(expected, child2.datatypes_present());
+    }
+
+    #[test]
+    fn intersect_overlapping_triples() {
+        let a = vec![
+            IdTriple::new(1, 1, 1),
+            IdTriple::new(1, 1, 2),
+            IdTriple::new(1, 2, 1),
+            IdTriple::new(3, 1, 1),
+        ];
+        let b = vec![
+            IdTriple::new(1, 1, 2),
+            IdTriple::new(1, 2, 1),
+            IdTriple::new(2, 1, 1),
+            IdTriple::new(3, 1, 1),
+            IdTriple::new(4, 1, 1),
+        ];
+
+        let result: Vec<_> = intersect_triples(a, b).collect();
+        assert_eq!(
+            vec![
+                IdTriple::new(1, 1, 2),
+                IdTriple::new(1, 2, 1),
+                IdTriple::new(3, 1, 1)
+            ],
+            result
+        );
+    }
+
+    #[test]
+    fn intersect_disjoint_triples() {
+        let a = vec![IdTriple::new(1, 1, 1), IdTriple::new(2, 2, 2)];
+        let b = vec![IdTriple::new(1, 1, 2), IdTriple::new(3, 3, 3)];
+
+        assert_eq!(0, intersect_triples(a, b).count());
+        assert_eq!(
+            0,
+            intersect_triples(Vec::new(), vec![IdTriple::new(1, 1, 1)]).count()
+        );
+    }
+
+    #[tokio::test]
+    async fn symmetric_difference_of_separate_layers() {
+        let files_a = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files_a.clone());
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.add_value_triple(ValueTriple::new_node("pig", "likes", "mud"));
+        builder.commit().await.unwrap();
+        let a = BaseLayer::load_from_files([1, 2, 3, 4, 5], &files_a)
+            .await
+            .unwrap();
+
+        let files_b = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([5, 4, 3, 2, 1], files_b.clone());
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.add_value_triple(ValueTriple::new_node("duck", "likes", "cow"));
+        builder.add_value_triple(ValueTriple::new_node("pig", "likes", "mud"));
+        builder.commit().await.unwrap();
+        let b = BaseLayer::load_from_files([5, 4, 3, 2, 1], &files_b)
+            .await
+            .unwrap();
+
+        let difference: Vec<_> = triples_symmetric_difference(&a, &b).collect();
+
+        assert_eq!(
+            vec![
+                (Side::OnlyA, ValueTriple::new_node("cow", "likes", "duck")),
+                (Side::OnlyB, ValueTriple::new_node("duck", "likes", "cow")),
+            ],
+            difference
+        );
+        assert_eq!(0, triples_symmetric_difference(&a, &a).count());
+
+        let (added, removed) = resolved_triple_diff(&a, &b);
+        assert_eq!(vec![ValueTriple::new_node("cow", "likes", "duck")], added);
+        assert_eq!(vec![ValueTriple::new_node("duck", "likes", "cow")], removed);
+    }
+
+    #[test]
+    fn intersect_triples_with_duplicates_in_one_input() {
+        let a = vec![
+            IdTriple::new(1, 1, 1),
+            IdTriple::new(1, 1, 1),
+            IdTriple::new(2, 1, 1),
+            IdTriple::new(2, 1, 1),
+        ];
+        let b = vec![IdTriple::new(1, 1, 1), IdTriple::new(2, 1, 1)];
+
+        let result: Vec<_> = intersect_triples(a, b).collect();
+        assert_eq!(vec![IdTriple::new(1, 1, 1), IdTriple::new(2, 1, 1)], result);
+    }
+
+    #[test]
+    fn subject_merge_join_aligns_groups() {
+        let a = vec![
+            IdTriple::new(1, 1, 1),
+            IdTriple::new(1, 2, 3),
+            IdTriple::new(3, 1, 1),
+            IdTriple::new(5, 1, 2),
+        ];
+        let b = vec![
+            IdTriple::new(1, 3, 4),
+            IdTriple::new(2, 1, 1),
+            IdTriple::new(5, 1, 2),
+            IdTriple::new(5, 2, 2),
+        ];
+
+        let result: Vec<_> = subject_merge_join(a.into_iter(), b.into_iter()).collect();
+        assert_eq!(
+            vec![
+                (
+                    1,
+                    vec![IdTriple::new(1, 1, 1), IdTriple::new(1, 2, 3)],
+                    vec![IdTriple::new(1, 3, 4)]
+                ),
+                (2, vec![], vec![IdTriple::new(2, 1, 1)]),
+                (3, vec![IdTriple::new(3, 1, 1)], vec![]),
+                (
+                    5,
+                    vec![IdTriple::new(5, 1, 2)],
+                    vec![IdTriple::new(5, 1, 2), IdTriple::new(5, 2, 2)]
+                ),
+            ],
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn provenance_of_readded_triple() {
+        let files = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.commit().await.unwrap();
+
+        let base: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let files = child_layer_files();
+        let mut builder =
+            SimpleLayerBuilder::from_parent([5, 4, 3, 2, 1], base.clone(), files.clone());
+        builder.remove_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.commit().await.unwrap();
+
+        let child1: Arc<InternalLayer> = Arc::new(
+            ChildLayer::load_from_files([5, 4, 3, 2, 1], base.clone(), &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let files = child_layer_files();
+        let mut builder =
+            SimpleLayerBuilder::from_parent([6, 6, 6, 6, 6], child1.clone(), files.clone());
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.commit().await.unwrap();
+
+        let child2: Arc<InternalLayer> = Arc::new(
+            ChildLayer::load_from_files([6, 6, 6, 6, 6], child1.clone(), &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let moo = child2
+            .value_triple_to_id(&ValueTriple::new_string_value("cow", "says", "moo"))
+            .unwrap();
+        let likes = child2
+            .value_triple_to_id(&ValueTriple::new_node("cow", "likes", "duck"))
+            .unwrap();
+
+        assert_eq!(Some([6, 6, 6, 6, 6]), child2.provenance_of(moo));
+        assert_eq!(None, child1.provenance_of(moo));
+        assert_eq!(Some([1, 2, 3, 4, 5]), base.provenance_of(moo));
+        assert_eq!(Some([1, 2, 3, 4, 5]), child2.provenance_of(likes));
+        assert_eq!(None, child2.provenance_of(IdTriple::new(1, 1, 1000)));
+    }
+
+    #[tokio::test]
+    async fn stack_depth_of_layer_chain() {
+        let files = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.commit().await.unwrap();
+
+        let base: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let mut layer = base.clone();
+        for i in 0..3 {
+            let name = [6, 6, 6, 6, i];
+            let files = child_layer_files();
+            let builder = SimpleLayerBuilder::from_parent(name, layer.clone(), files.clone());
+            builder.commit().await.unwrap();
+
+            layer = Arc::new(
+                ChildLayer::load_from_files(name, layer, &files)
+                    .await
+                    .unwrap()
+                    .into(),
+            );
+        }
+
+        assert_eq!(4, layer.stack_depth());
+        assert_eq!(1, base.stack_depth());
+    }
+
+    #[tokio::test]
+    async fn stack_has_removals_of_layer_chain() {
+        let files = base_layer_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "pig"));
+        builder.commit().await.unwrap();
+
+        let base: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let name = [6, 6, 6, 6, 1];
+        let files = child_layer_files();
+        let mut builder = SimpleLayerBuilder::from_parent(name, base.clone(), files.clone());
+        builder.add_value_triple(ValueTriple::new_node("pig", "likes", "duck"));
+        builder.commit().await.unwrap();
+        let child1: Arc<InternalLayer> = Arc::new(
+            ChildLayer::load_from_files(name, base.clone(), &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let name = [6, 6, 6, 6, 2];
+        let files = child_layer_files();
+        let mut builder = SimpleLayerBuilder::from_parent(name, child1.clone(), files.clone());
+        builder.remove_value_triple(ValueTriple::new_node("cow", "likes", "pig"));
+        builder.commit().await.unwrap();
+        let child2: Arc<InternalLayer> = Arc::new(
+            ChildLayer::load_from_files(name, child1.clone(), &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        assert!(!base.stack_has_removals());
+        assert!(!child1.stack_has_removals());
+        assert!(child2.stack_has_removals());
+    }
 }
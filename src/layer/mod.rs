@@ -3,12 +3,17 @@
 //! Databases in terminus-store are stacks of layers. The first layer
 //! in such a stack is a base layer, which contains an intial data
 //! set. On top of that, each layer stores additions and removals.
+#[cfg(feature = "arrow")]
+mod arrow_export;
 pub mod builder;
+pub mod dictionary;
 pub mod id_map;
 mod internal;
 mod layer;
 mod simple_builder;
 
+#[cfg(feature = "arrow")]
+pub use arrow_export::*;
 pub use id_map::*;
 pub use internal::*;
 pub use layer::*;
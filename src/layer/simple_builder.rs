@@ -9,15 +9,18 @@
 //! any format (numerical, string, or a mixture), store them in
 //! memory, then does the required sorting and id conversion on
 //! commit.
+use super::builder::FloatPolicy;
 use super::internal::*;
 use super::layer::*;
 use crate::storage::*;
+use bytes::Bytes;
 use std::collections::HashMap;
 use std::io;
 use std::pin::Pin;
 use std::sync::Arc;
 
 use futures::future::Future;
+use tdb_succinct::{TdbDataType, TypedDictEntry};
 
 use bitvec::prelude::*;
 
@@ -38,6 +41,10 @@ pub trait LayerBuilder: Send + Sync {
     fn remove_value_triple(&mut self, triple: ValueTriple);
     /// Remove an id triple
     fn remove_id_triple(&mut self, triple: IdTriple);
+    /// Set a metadata blob to store alongside the layer
+    ///
+    /// Builders that can't store metadata ignore it.
+    fn set_metadata(&mut self, _metadata: Bytes) {}
     /// Commit the layer to storage
     fn commit(self) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send>>;
     /// Commit a boxed layer to storage
@@ -63,6 +70,9 @@ pub struct SimpleLayerBuilder<F: 'static + FileLoad + FileStore + Clone> {
     node_count: usize,
     pred_count: usize,
     val_count: usize,
+
+    metadata: Option<Bytes>,
+    float_policy: FloatPolicy,
 }
 
 impl<F: 'static + FileLoad + FileStore + Clone> SimpleLayerBuilder<F> {
@@ -82,6 +92,9 @@ impl<F: 'static + FileLoad + FileStore + Clone> SimpleLayerBuilder<F> {
             node_count: 0,
             pred_count: 0,
             val_count: 0,
+
+            metadata: None,
+            float_policy: FloatPolicy::default(),
         }
     }
 
@@ -103,6 +116,32 @@ impl<F: 'static + FileLoad + FileStore + Clone> SimpleLayerBuilder<F> {
             node_count: 0,
             pred_count: 0,
             val_count: 0,
+
+            metadata: None,
+            float_policy: FloatPolicy::default(),
+        }
+    }
+
+    /// Set the policy for handling non-finite float values.
+    ///
+    /// With a strict policy, committing a layer that contains a NaN or
+    /// infinite float value fails with an `InvalidInput` error.
+    pub fn set_float_policy(&mut self, policy: FloatPolicy) {
+        self.float_policy = policy;
+    }
+
+    /// Remove all triples with the given predicate from the parent layer.
+    ///
+    /// This only stages removals for triples that exist in the parent.
+    /// Triples added in this builder are unaffected, and for a base
+    /// layer builder this does nothing.
+    pub fn remove_all_with_predicate(&mut self, predicate: &str) {
+        let parent = match self.parent.as_ref() {
+            Some(parent) => parent,
+            None => return,
+        };
+        if let Some(predicate_id) = parent.predicate_id(predicate) {
+            self.id_removals.extend(parent.triples_p(predicate_id));
         }
     }
 
@@ -215,6 +254,10 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuil
         self.id_removals.push(triple);
     }
 
+    fn set_metadata(&mut self, metadata: Bytes) {
+        self.metadata = Some(metadata);
+    }
+
     fn commit(self) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send>> {
         let SimpleLayerBuilder {
             name: _,
@@ -230,6 +273,9 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuil
             node_count,
             pred_count,
             val_count,
+
+            metadata,
+            float_policy,
         } = self;
         let parent_node_value_offset = parent
             .as_ref()
@@ -391,9 +437,15 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuil
 
                     builder.add_nodes(nodes.into_iter().map(|x| x.0));
                     builder.add_predicates(predicates.into_iter().map(|x| x.0));
-                    builder.add_values(values.into_iter().map(|x| x.0));
+                    builder.set_float_policy(float_policy);
+                    for (value, _) in values {
+                        builder.try_add_value(value)?;
+                    }
 
                     let mut builder = builder.into_phase2().await?;
+                    if let Some(metadata) = metadata {
+                        builder.set_metadata(metadata);
+                    }
 
                     builder.add_id_triples(id_additions).await?;
                     builder.remove_id_triples(id_removals).await?;
@@ -407,9 +459,15 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuil
 
                     builder.add_nodes(nodes.into_iter().map(|x| x.0));
                     builder.add_predicates(predicates.into_iter().map(|x| x.0));
-                    builder.add_values(values.into_iter().map(|x| x.0));
+                    builder.set_float_policy(float_policy);
+                    for (value, _) in values {
+                        builder.try_add_value(value)?;
+                    }
 
                     let mut builder = builder.into_phase2().await?;
+                    if let Some(metadata) = metadata {
+                        builder.set_metadata(metadata);
+                    }
 
                     builder.add_id_triples(id_additions).await?;
 
@@ -425,11 +483,88 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerBuilder for SimpleLayerBuil
     }
 }
 
+/// The object of a triple inserted into a `StringGraphBuilder`.
+///
+/// Each variant other than `Node` is stored as a value of the
+/// corresponding datatype. Strings and the fixed-size numeric
+/// datatypes have their own variant. Values of any other datatype
+/// can be inserted as an already encoded entry with `Value`, for
+/// example one made with `TdbDataType::make_entry`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedValue {
+    Node(String),
+    String(String),
+    UInt32(u32),
+    Int32(i32),
+    UInt64(u64),
+    Int64(i64),
+    Float32(f32),
+    Float64(f64),
+    Value(TypedDictEntry),
+}
+
+impl TypedValue {
+    fn into_object(self) -> ObjectType {
+        match self {
+            TypedValue::Node(node) => ObjectType::Node(node),
+            TypedValue::String(s) => ObjectType::Value(String::make_entry(&s)),
+            TypedValue::UInt32(n) => ObjectType::Value(u32::make_entry(&n)),
+            TypedValue::Int32(n) => ObjectType::Value(i32::make_entry(&n)),
+            TypedValue::UInt64(n) => ObjectType::Value(u64::make_entry(&n)),
+            TypedValue::Int64(n) => ObjectType::Value(i64::make_entry(&n)),
+            TypedValue::Float32(n) => ObjectType::Value(f32::make_entry(&n)),
+            TypedValue::Float64(n) => ObjectType::Value(f64::make_entry(&n)),
+            TypedValue::Value(entry) => ObjectType::Value(entry),
+        }
+    }
+}
+
+/// A builder for base layers that takes triples as strings and typed values.
+///
+/// Inserted triples are staged in memory, with their subjects,
+/// predicates and objects interned as they come in. Nothing is
+/// written until `finalize`, which builds the dictionaries and
+/// triples, writes the layer and loads it.
+pub struct StringGraphBuilder<F: 'static + FileLoad + FileStore + Clone> {
+    name: [u32; 5],
+    files: BaseLayerFiles<F>,
+    builder: SimpleLayerBuilder<F>,
+}
+
+impl<F: 'static + FileLoad + FileStore + Clone> StringGraphBuilder<F> {
+    /// Construct a builder for a base layer with the given name, written to the given files.
+    pub fn new(name: [u32; 5], files: BaseLayerFiles<F>) -> Self {
+        let builder = SimpleLayerBuilder::new(name, files.clone());
+
+        Self {
+            name,
+            files,
+            builder,
+        }
+    }
+
+    /// Insert a triple.
+    pub fn insert(&mut self, subject: &str, predicate: &str, object: TypedValue) {
+        self.builder.add_value_triple(ValueTriple {
+            subject: subject.to_string(),
+            predicate: predicate.to_string(),
+            object: object.into_object(),
+        });
+    }
+
+    /// Write the base layer to its files and load it.
+    pub async fn finalize(self) -> io::Result<InternalLayer> {
+        self.builder.commit().await?;
+
+        BaseLayer::load_from_files(self.name, &self.files).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::storage::memory::*;
-    use tdb_succinct::TdbDataType;
+    use tdb_succinct::{Datatype, TypedDictEntry};
 
     fn new_base_files() -> BaseLayerFiles<MemoryBackedStore> {
         // TODO inline
@@ -465,6 +600,28 @@ mod tests {
         assert!(layer.value_triple_exists(&ValueTriple::new_string_value("duck", "says", "quack")));
     }
 
+    #[tokio::test]
+    async fn metadata_survives_commit_and_load() {
+        let base_layer = example_base_layer().await;
+        assert_eq!(None, base_layer.metadata());
+
+        let name = [0, 0, 0, 0, 1];
+        let files = new_child_files();
+        let mut builder = SimpleLayerBuilder::from_parent(name, base_layer.clone(), files.clone());
+        builder.add_value_triple(ValueTriple::new_string_value("horse", "says", "neigh"));
+        builder.set_metadata(Bytes::from_static(b"{\"author\":\"matthijs\"}"));
+        builder.commit().await.unwrap();
+
+        let child = ChildLayer::load_from_files(name, base_layer, &files)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some(Bytes::from_static(b"{\"author\":\"matthijs\"}")),
+            child.metadata()
+        );
+    }
+
     #[tokio::test]
     async fn simple_child_layer_construction() {
         let base_layer = example_base_layer().await;
@@ -499,6 +656,44 @@ mod tests {
             .value_triple_exists(&ValueTriple::new_string_value("duck", "says", "quack")));
     }
 
+    #[tokio::test]
+    async fn remove_all_triples_with_predicate() {
+        let files = new_base_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.add_value_triple(ValueTriple::new_string_value("pig", "says", "oink"));
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "pig"));
+        builder.add_value_triple(ValueTriple::new_node("pig", "likes", "cow"));
+        builder.commit().await.unwrap();
+        let base_layer: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([1, 2, 3, 4, 5], &files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let name = [0, 0, 0, 0, 0];
+        let files = new_child_files();
+        let mut builder = SimpleLayerBuilder::from_parent(name, base_layer.clone(), files.clone());
+        builder.remove_all_with_predicate("likes");
+        builder.remove_all_with_predicate("nonexistent");
+        builder.commit().await.unwrap();
+
+        let child_layer = ChildLayer::load_from_files(name, base_layer, &files)
+            .await
+            .unwrap();
+
+        let likes = child_layer.predicate_id("likes").unwrap();
+        assert_eq!(0, child_layer.triples_p(likes).count());
+        assert!(
+            child_layer.value_triple_exists(&ValueTriple::new_string_value("cow", "says", "moo"))
+        );
+        assert!(
+            child_layer.value_triple_exists(&ValueTriple::new_string_value("pig", "says", "oink"))
+        );
+        assert_eq!(2, child_layer.triple_count());
+    }
+
     #[tokio::test]
     async fn multi_level_layers() {
         let base_layer = example_base_layer().await;
@@ -806,4 +1001,71 @@ mod tests {
             child_layer.value_triple_exists(&ValueTriple::new_string_value("cow", "says", "moo"))
         );
     }
+
+    #[tokio::test]
+    async fn string_graph_builder_with_typed_values() {
+        let mut builder = StringGraphBuilder::new([1, 2, 3, 4, 5], new_base_files());
+        builder.insert("cow", "likes", TypedValue::Node("duck".to_string()));
+        builder.insert("cow", "says", TypedValue::String("moo".to_string()));
+        builder.insert("cow", "legs", TypedValue::UInt32(4));
+        builder.insert("cow", "weight", TypedValue::Float64(612.5));
+        builder.insert("duck", "balance", TypedValue::Int64(-3));
+        builder.insert(
+            "duck",
+            "feathers",
+            TypedValue::Value(u64::make_entry(&1200)),
+        );
+
+        let layer = builder.finalize().await.unwrap();
+
+        assert_eq!(6, layer.triple_addition_count());
+        assert!(layer.value_triple_exists(&ValueTriple::new_node("cow", "likes", "duck")));
+        assert!(layer
+            .id_object_is_node(layer.object_node_id("duck").unwrap())
+            .unwrap());
+
+        let datatype_of = |value: TypedDictEntry| {
+            layer
+                .object_value_id(&value)
+                .and_then(|id| layer.object_datatype(id))
+        };
+        assert_eq!(
+            Some(Datatype::String),
+            datatype_of(String::make_entry(&"moo"))
+        );
+        assert_eq!(Some(Datatype::UInt32), datatype_of(u32::make_entry(&4)));
+        assert_eq!(
+            Some(Datatype::Float64),
+            datatype_of(f64::make_entry(&612.5))
+        );
+        assert_eq!(Some(Datatype::Int64), datatype_of(i64::make_entry(&-3)));
+        assert_eq!(Some(Datatype::UInt64), datatype_of(u64::make_entry(&1200)));
+    }
+
+    #[tokio::test]
+    async fn strict_float_policy_fails_commit() {
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], new_base_files());
+        builder.set_float_policy(FloatPolicy::Strict);
+        builder.add_value_triple(ValueTriple::new_value(
+            "cow",
+            "weight",
+            f64::make_entry(&f64::NAN),
+        ));
+
+        let err = builder.commit().await.unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+
+        let base_layer = example_base_layer().await;
+        let mut builder =
+            SimpleLayerBuilder::from_parent([0, 0, 0, 0, 1], base_layer, new_child_files());
+        builder.set_float_policy(FloatPolicy::Strict);
+        builder.add_value_triple(ValueTriple::new_value(
+            "pig",
+            "weight",
+            f32::make_entry(&f32::INFINITY),
+        ));
+
+        let err = builder.commit().await.unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
 }
@@ -36,7 +36,8 @@ use tdb_succinct::{
 use super::{
     consts::{LayerFileEnum, FILENAME_ENUM_MAP},
     locking::{ExclusiveLockedFile, LockedFile},
-    name_to_string, string_to_name, FileLoad, FileStore, PersistentLayerStore, SyncableFile,
+    name_to_string, string_to_name, FileLoad, FileStore, PersistentLayerStore, StorageError,
+    SyncableFile,
 };
 
 #[async_trait]
@@ -1465,6 +1466,40 @@ impl<M: ArchiveMetadataBackend + Unpin + 'static, D: ArchiveBackend + 'static> P
             .await
     }
 
+    async fn rename_directory(&self, from: [u32; 5], to: [u32; 5]) -> io::Result<()> {
+        if self.metadata_backend.layer_exists(to).await? {
+            return Err(StorageError::AlreadyExists.into());
+        }
+
+        let mut guard = self.construction.write().unwrap();
+        if guard.contains_key(&to) {
+            return Err(StorageError::AlreadyExists.into());
+        }
+
+        // only layers under construction can be renamed, archived layers are immutable
+        let files = guard.remove(&from).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "tried to rename a layer which is not under construction",
+            )
+        })?;
+        guard.insert(to, files);
+
+        Ok(())
+    }
+
+    async fn delete_directory(&self, name: [u32; 5]) -> io::Result<()> {
+        let mut guard = self.construction.write().unwrap();
+        if guard.remove(&name).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "tried to delete a layer which is not under construction",
+            ));
+        }
+
+        Ok(())
+    }
+
     async fn finalize(&self, directory: [u32; 5]) -> io::Result<()> {
         let files = {
             let mut guard = self.construction.write().unwrap();
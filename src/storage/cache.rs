@@ -1,11 +1,14 @@
+use super::file::BaseLayerFiles;
 use super::layer::*;
+use super::memory::MemoryBackedStore;
+use super::stats::LayerStats;
 use crate::layer::*;
 use async_trait::async_trait;
 use std::collections::HashMap;
 use std::io;
 use std::path::Path;
 use std::sync::{Arc, RwLock, Weak};
-use tdb_succinct::{StringDict, TypedDict};
+use tdb_succinct::{MonotonicLogArray, StringDict, TypedDict};
 
 pub trait LayerCache: 'static + Send + Sync {
     fn get_layer_from_cache(&self, name: [u32; 5]) -> Option<Arc<InternalLayer>>;
@@ -131,6 +134,14 @@ impl LayerStore for CachedLayerStore {
         self.inner.finalize_layer(name).await
     }
 
+    async fn layer_stats(&self, name: [u32; 5]) -> io::Result<Option<LayerStats>> {
+        self.inner.layer_stats(name).await
+    }
+
+    async fn child_layer_subjects(&self, name: [u32; 5]) -> io::Result<Option<MonotonicLogArray>> {
+        self.inner.child_layer_subjects(name).await
+    }
+
     async fn get_layer_parent_name(&self, name: [u32; 5]) -> io::Result<Option<[u32; 5]>> {
         // is layer in cache? if so, we can use the cached version
         if let Some(layer) = self.cache.get_layer_from_cache(name) {
@@ -240,6 +251,13 @@ impl LayerStore for CachedLayerStore {
         self.inner.create_base_layer().await
     }
 
+    async fn create_content_addressed_base_layer(
+        &self,
+        files: &BaseLayerFiles<MemoryBackedStore>,
+    ) -> io::Result<[u32; 5]> {
+        self.inner.create_content_addressed_base_layer(files).await
+    }
+
     async fn create_child_layer(&self, parent: [u32; 5]) -> io::Result<Box<dyn LayerBuilder>> {
         self.inner
             .create_child_layer_with_cache(parent, self.cache.clone())
@@ -90,6 +90,9 @@ pub struct Filenames {
 
     pub parent: &'static str,
     pub rollup: &'static str,
+    pub stats: &'static str,
+    pub metadata: &'static str,
+    pub shared_dictionaries: &'static str,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, FromPrimitive)]
@@ -156,6 +159,9 @@ pub enum LayerFileEnum {
 
     Parent,
     Rollup,
+    Stats,
+    Metadata,
+    SharedDictionaries,
 }
 
 pub const FILENAMES: Filenames = Filenames {
@@ -254,6 +260,9 @@ pub const FILENAMES: Filenames = Filenames {
 
     parent: "parent.hex",
     rollup: "rollup.hex",
+    stats: "stats.bin",
+    metadata: "metadata.bin",
+    shared_dictionaries: "shared_dictionaries.bin",
 };
 
 lazy_static! {
@@ -502,6 +511,9 @@ lazy_static! {
         ),
         ("parent.hex", LayerFileEnum::Parent),
         ("rollup.hex", LayerFileEnum::Rollup),
+        ("stats.bin", LayerFileEnum::Stats),
+        ("metadata.bin", LayerFileEnum::Metadata),
+        ("shared_dictionaries.bin", LayerFileEnum::SharedDictionaries),
     ]);
 }
 
@@ -516,7 +528,7 @@ pub const SHARED_REQUIRED_FILES: [&'static str; 8] = [
     FILENAMES.value_dictionary_offsets,
 ];
 
-pub const SHARED_OPTIONAL_FILES: [&'static str; 7] = [
+pub const SHARED_OPTIONAL_FILES: [&'static str; 9] = [
     FILENAMES.node_value_idmap_bits,
     FILENAMES.node_value_idmap_bit_index_blocks,
     FILENAMES.node_value_idmap_bit_index_sblocks,
@@ -524,6 +536,8 @@ pub const SHARED_OPTIONAL_FILES: [&'static str; 7] = [
     FILENAMES.predicate_idmap_bit_index_blocks,
     FILENAMES.predicate_idmap_bit_index_sblocks,
     FILENAMES.rollup,
+    FILENAMES.stats,
+    FILENAMES.metadata,
 ];
 
 pub const BASE_LAYER_REQUIRED_FILES: [&'static str; 15] = [
@@ -581,9 +595,10 @@ pub const CHILD_LAYER_REQUIRED_FILES: [&'static str; 31] = [
     FILENAMES.neg_predicate_wavelet_tree_bit_index_sblocks,
 ];
 
-pub const CHILD_LAYER_OPTIONAL_FILES: [&'static str; 4] = [
+pub const CHILD_LAYER_OPTIONAL_FILES: [&'static str; 5] = [
     FILENAMES.pos_subjects,
     FILENAMES.pos_objects,
     FILENAMES.neg_subjects,
     FILENAMES.neg_objects,
+    FILENAMES.shared_dictionaries,
 ];
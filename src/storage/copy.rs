@@ -1,6 +1,10 @@
 use std::io;
 
-use super::{BaseLayerFiles, ChildLayerFiles, FileLoad, FileStore, IdMapFiles};
+use super::consts::{FILENAMES, FILENAME_ENUM_MAP};
+use super::error::StorageError;
+use super::{
+    BaseLayerFiles, ChildLayerFiles, FileLoad, FileStore, IdMapFiles, PersistentLayerStore,
+};
 
 use tdb_succinct::storage::copy_file;
 
@@ -49,6 +53,9 @@ impl<F1: 'static + FileLoad + FileStore> BaseLayerFiles<F1> {
         self.predicate_wavelet_tree_files
             .copy_from(&from.predicate_wavelet_tree_files)
             .await?;
+        if from.metadata_file.exists().await? {
+            copy_file(&from.metadata_file, &self.metadata_file).await?;
+        }
 
         Ok(())
     }
@@ -99,7 +106,87 @@ impl<F1: 'static + FileLoad + FileStore> ChildLayerFiles<F1> {
         self.neg_predicate_wavelet_tree_files
             .copy_from(&from.neg_predicate_wavelet_tree_files)
             .await?;
+        if from.metadata_file.exists().await? {
+            copy_file(&from.metadata_file, &self.metadata_file).await?;
+        }
 
         Ok(())
     }
 }
+
+/// Copy the files of a layer from one store to another, without loading the layer.
+///
+/// Files are copied byte for byte. The rollup file is not copied, as
+/// the rollup it refers to may not exist in the target store. A
+/// child layer can only be loaded from the target store once its
+/// parent has been copied as well.
+pub async fn copy_layer<S1: PersistentLayerStore, S2: PersistentLayerStore>(
+    from: &S1,
+    to: &S2,
+    name: [u32; 5],
+) -> io::Result<()> {
+    if !from.directory_exists(name).await? {
+        return Err(StorageError::LayerNotFound.into());
+    }
+    if to.directory_exists(name).await? {
+        return Err(StorageError::AlreadyExists.into());
+    }
+
+    to.create_named_directory(name).await?;
+    for filename in FILENAME_ENUM_MAP.keys() {
+        if *filename == FILENAMES.rollup || !from.file_exists(name, filename).await? {
+            continue;
+        }
+
+        let from_file = from.get_file(name, filename).await?;
+        let to_file = to.get_file(name, filename).await?;
+        copy_file(&from_file, &to_file).await?;
+    }
+    to.finalize(name).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::*;
+    use crate::storage::memory::MemoryLayerStore;
+    use crate::storage::LayerStore;
+
+    #[tokio::test]
+    async fn copy_layer_stack_between_memory_stores() {
+        let from = MemoryLayerStore::new();
+        let mut builder = from.create_base_layer().await.unwrap();
+        let base_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.commit_boxed().await.unwrap();
+
+        let mut builder = from.create_child_layer(base_name).await.unwrap();
+        let child_name = builder.name();
+        builder.remove_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_string_value("duck", "says", "quack"));
+        builder.commit_boxed().await.unwrap();
+
+        let to = MemoryLayerStore::new();
+        copy_layer(&from, &to, base_name).await.unwrap();
+        copy_layer(&from, &to, child_name).await.unwrap();
+
+        let original = from.get_layer(child_name).await.unwrap().unwrap();
+        let copy = to.get_layer(child_name).await.unwrap().unwrap();
+
+        assert_eq!(Some(base_name), copy.parent_name());
+        let expected: Vec<_> = original
+            .triples()
+            .map(|t| original.id_triple_to_string(&t).unwrap())
+            .collect();
+        let actual: Vec<_> = copy
+            .triples()
+            .map(|t| copy.id_triple_to_string(&t).unwrap())
+            .collect();
+        assert_eq!(expected, actual);
+
+        assert!(copy_layer(&from, &to, base_name).await.is_err());
+    }
+}
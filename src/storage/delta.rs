@@ -221,6 +221,7 @@ async fn dictionary_rollup_upto<S: LayerStore, F: 'static + FileLoad + FileStore
 pub async fn dictionary_rollup<F: 'static + FileLoad + FileStore>(
     layer: &InternalLayer,
     files: &BaseLayerFiles<F>,
+    build_idmaps: bool,
 ) -> io::Result<()> {
     let node_dicts = layer
         .immediate_layers()
@@ -239,7 +240,11 @@ pub async fn dictionary_rollup<F: 'static + FileLoad + FileStore>(
     merge_string_dictionaries(predicate_dicts, files.predicate_dictionary_files.clone()).await?;
     merge_typed_dictionaries(value_dicts, files.value_dictionary_files.clone()).await?;
 
-    memory_construct_idmaps(layer, files.id_map_files.clone()).await
+    if build_idmaps {
+        memory_construct_idmaps(layer, files.id_map_files.clone()).await
+    } else {
+        Ok(())
+    }
 }
 
 async fn memory_dictionary_rollup_upto<F: 'static + FileLoad + FileStore>(
@@ -271,7 +276,22 @@ pub async fn delta_rollup<F: 'static + FileLoad + FileStore>(
     layer: &InternalLayer,
     files: BaseLayerFiles<F>,
 ) -> io::Result<()> {
-    dictionary_rollup(layer, &files).await?;
+    delta_rollup_with_idmaps(layer, files, true).await
+}
+
+/// Roll up the given layer into a base layer, optionally skipping idmap construction.
+///
+/// When `build_idmaps` is false, no idmap files are written, and the
+/// resulting layer loads with an identity idmap. This saves work for
+/// layers that are only ever queried by id, but string lookups will
+/// only be correct if the merged dictionaries happen to be in the
+/// same order as the original ids.
+pub async fn delta_rollup_with_idmaps<F: 'static + FileLoad + FileStore>(
+    layer: &InternalLayer,
+    files: BaseLayerFiles<F>,
+    build_idmaps: bool,
+) -> io::Result<()> {
+    dictionary_rollup(layer, &files, build_idmaps).await?;
 
     let counts = layer.all_counts();
 
@@ -398,6 +418,8 @@ pub async fn delta_rollup_upto<S: LayerStore, F: 'static + FileLoad + FileStore>
     let disk_changes = store.layer_changes_upto(bound, upto).await?;
     let memory_changes =
         InternalTripleStackIterator::from_layer_stack(layer, bound).expect("upto not found");
+    // The stack iterator collapses removals and re-additions of the same
+    // triple across layers, so only the net changes end up in the rollup.
     let changes = InternalTripleStackIterator::merge(vec![disk_changes, memory_changes]);
     let additions = changes
         .clone()
@@ -507,6 +529,31 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn rollup_has_same_fingerprint() {
+        let store = MemoryLayerStore::new();
+        let (base_layer, _, layer) = build_three_layers(&store).await.unwrap();
+
+        let delta_files = base_layer_memory_files();
+        delta_rollup(&layer, delta_files.clone()).await.unwrap();
+
+        let delta_layer: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([0, 0, 0, 0, 4], &delta_files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        assert_eq!(
+            layer.triples_fingerprint(),
+            delta_layer.triples_fingerprint()
+        );
+        assert_ne!(
+            base_layer.triples_fingerprint(),
+            delta_layer.triples_fingerprint()
+        );
+    }
+
     #[tokio::test]
     async fn rollup_two_of_three_layers() {
         let store = MemoryLayerStore::new();
@@ -606,6 +653,98 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn rollup_without_idmaps() {
+        let store = MemoryLayerStore::new();
+        let mut builder = store.create_base_layer().await.unwrap();
+        let base_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("a", "p", "b"));
+        builder.commit_boxed().await.unwrap();
+
+        let mut builder = store.create_child_layer(base_name).await.unwrap();
+        let child_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("c", "q", "d"));
+        builder.add_value_triple(ValueTriple::new_node("b", "p", "c"));
+        builder.commit_boxed().await.unwrap();
+        let layer = store.get_layer(child_name).await.unwrap().unwrap();
+
+        let delta_files = base_layer_memory_files();
+        delta_rollup_with_idmaps(&layer, delta_files.clone(), false)
+            .await
+            .unwrap();
+
+        let delta_layer: Arc<InternalLayer> = Arc::new(
+            BaseLayer::load_from_files([0, 0, 0, 0, 4], &delta_files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        assert!(delta_layer.node_value_id_map().id_wtree.is_none());
+        assert!(delta_layer.predicate_id_map().id_wtree.is_none());
+
+        let expected: Vec<_> = layer.triples().collect();
+        let actual: Vec<_> = delta_layer.triples().collect();
+        assert_eq!(expected, actual);
+
+        // dictionaries were sorted to begin with, so string lookups still work
+        for t in expected {
+            let value_triple = layer.id_triple_to_string(&t).unwrap();
+            assert!(delta_layer.value_triple_exists(&value_triple));
+        }
+    }
+
+    #[tokio::test]
+    async fn rollup_collapses_removal_and_readdition() {
+        let store = MemoryLayerStore::new();
+        let mut builder = store.create_base_layer().await.unwrap();
+        let base_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.commit_boxed().await.unwrap();
+        let base_layer = store.get_layer(base_name).await.unwrap().unwrap();
+
+        let mut builder = store.create_child_layer(base_name).await.unwrap();
+        let child1_name = builder.name();
+        builder.remove_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.commit_boxed().await.unwrap();
+
+        let mut builder = store.create_child_layer(child1_name).await.unwrap();
+        let child2_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.commit_boxed().await.unwrap();
+        let child_layer = store.get_layer(child2_name).await.unwrap().unwrap();
+
+        let delta_files = child_layer_memory_files();
+        delta_rollup_upto(&store, &child_layer, base_name, delta_files.clone())
+            .await
+            .unwrap();
+
+        let delta_layer: Arc<InternalLayer> = Arc::new(
+            ChildLayer::load_from_files([0, 0, 0, 0, 4], base_layer, &delta_files)
+                .await
+                .unwrap()
+                .into(),
+        );
+
+        let triple = delta_layer
+            .value_triple_to_id(&ValueTriple::new_node("cow", "likes", "duck"))
+            .unwrap();
+        assert!(delta_layer.triple_exists(triple.subject, triple.predicate, triple.object));
+        assert!(!delta_layer.internal_triple_addition_exists(
+            triple.subject,
+            triple.predicate,
+            triple.object
+        ));
+        assert!(!delta_layer.internal_triple_removal_exists(
+            triple.subject,
+            triple.predicate,
+            triple.object
+        ));
+        assert_eq!(1, delta_layer.internal_triple_layer_addition_count());
+        assert_eq!(0, delta_layer.internal_triple_layer_removal_count());
+    }
+
     async fn create_layer_stack<S: LayerStore>(store: &S) -> Vec<[u32; 5]> {
         let mut builder = store.create_base_layer().await.unwrap();
         let base_name = builder.name();
@@ -1,12 +1,15 @@
 //! Directory-based implementation of storage traits.
 
+use bytes::Bytes;
 use locking::*;
 use std::collections::HashMap;
 use std::io::{self, SeekFrom};
 use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
 use tokio::fs;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
 use tokio::sync::RwLock;
 
 use async_trait::async_trait;
@@ -17,6 +20,148 @@ use super::*;
 
 const PREFIX_DIR_SIZE: usize = 3;
 
+/// A file on disk that is replaced atomically on write.
+///
+/// Writes go to a temporary file next to the target path, which is
+/// only renamed into place on `sync_all`. Readers therefore never see
+/// a partially written file, even if the writer is interrupted.
+#[derive(Clone, Debug)]
+pub struct AtomicFileBackedStore {
+    path: PathBuf,
+}
+
+impl AtomicFileBackedStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> AtomicFileBackedStore {
+        AtomicFileBackedStore { path: path.into() }
+    }
+
+    /// Returns a fresh temporary path next to the target path.
+    ///
+    /// Every writer gets its own temporary file, so concurrent writers
+    /// of the same file don't write into each other's temporary file.
+    fn temp_path(&self) -> PathBuf {
+        let mut file_name = self
+            .path
+            .file_name()
+            .map(|n| n.to_os_string())
+            .unwrap_or_default();
+        file_name.push(format!(".{:016x}.tmp", rand::random::<u64>()));
+
+        self.path.with_file_name(file_name)
+    }
+
+    fn as_file_backed_store(&self) -> FileBackedStore {
+        FileBackedStore::new(self.path.clone())
+    }
+}
+
+pub struct AtomicFileBackedStoreWriter {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: BufWriter<fs::File>,
+    done: bool,
+}
+
+impl Drop for AtomicFileBackedStoreWriter {
+    fn drop(&mut self) {
+        if !self.done {
+            // the write was never completed, so throw away what was written so far
+            let temp_path = std::mem::take(&mut self.temp_path);
+            match tokio::runtime::Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn(async move {
+                        let _ = fs::remove_file(temp_path).await;
+                    });
+                }
+                Err(_) => {
+                    // outside of a runtime, blocking is not a problem
+                    let _ = std::fs::remove_file(temp_path);
+                }
+            }
+        }
+    }
+}
+
+impl AsyncWrite for AtomicFileBackedStoreWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.file).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.file).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.file).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl SyncableFile for AtomicFileBackedStoreWriter {
+    async fn sync_all(mut self) -> io::Result<()> {
+        self.file.flush().await?;
+        self.file.get_ref().sync_all().await?;
+        fs::rename(&self.temp_path, &self.path).await?;
+        self.done = true;
+
+        if cfg!(unix) {
+            // make the rename itself durable
+            if let Some(parent) = self.path.parent() {
+                sync_directory(parent.to_path_buf()).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FileStore for AtomicFileBackedStore {
+    type Write = AtomicFileBackedStoreWriter;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        let temp_path = self.temp_path();
+        let mut options = fs::OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(true);
+        let file = options.open(&temp_path).await?;
+
+        Ok(AtomicFileBackedStoreWriter {
+            path: self.path.clone(),
+            temp_path,
+            file: BufWriter::new(file),
+            done: false,
+        })
+    }
+}
+
+#[async_trait]
+impl FileLoad for AtomicFileBackedStore {
+    type Read = <FileBackedStore as FileLoad>::Read;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.as_file_backed_store().exists().await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        self.as_file_backed_store().size().await
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        self.as_file_backed_store().open_read_from(offset).await
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        self.as_file_backed_store().map().await
+    }
+}
+
 #[derive(Clone)]
 pub struct DirectoryLayerStore {
     path: PathBuf,
@@ -30,7 +175,7 @@ impl DirectoryLayerStore {
 
 #[async_trait]
 impl PersistentLayerStore for DirectoryLayerStore {
-    type File = FileBackedStore;
+    type File = AtomicFileBackedStore;
     async fn directories(&self) -> io::Result<Vec<[u32; 5]>> {
         let mut stream = fs::read_dir(&self.path).await?;
         let mut result = Vec::new();
@@ -79,7 +224,7 @@ impl PersistentLayerStore for DirectoryLayerStore {
         p.push(&dir_name[0..PREFIX_DIR_SIZE]);
         p.push(dir_name);
         p.push(name);
-        Ok(FileBackedStore::new(p))
+        Ok(AtomicFileBackedStore::new(p))
     }
 
     async fn file_exists(&self, directory: [u32; 5], file: &str) -> io::Result<bool> {
@@ -94,6 +239,48 @@ impl PersistentLayerStore for DirectoryLayerStore {
             Err(_) => Ok(false),
         }
     }
+    async fn rename_directory(&self, from: [u32; 5], to: [u32; 5]) -> io::Result<()> {
+        let mut from_path = self.path.clone();
+        let from_name = name_to_string(from);
+        from_path.push(&from_name[0..PREFIX_DIR_SIZE]);
+        from_path.push(from_name);
+
+        let mut to_path = self.path.clone();
+        let to_name = name_to_string(to);
+        to_path.push(&to_name[0..PREFIX_DIR_SIZE]);
+        fs::create_dir_all(&to_path).await?;
+        to_path.push(to_name);
+
+        if self.directory_exists(to).await? {
+            return Err(StorageError::AlreadyExists.into());
+        }
+
+        if let Err(e) = fs::rename(from_path, &to_path).await {
+            // someone else may have moved a directory into place in the meantime
+            if self.directory_exists(to).await? {
+                return Err(StorageError::AlreadyExists.into());
+            }
+
+            return Err(e);
+        }
+
+        if cfg!(unix) {
+            to_path.pop();
+            sync_directory(to_path).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn delete_directory(&self, name: [u32; 5]) -> io::Result<()> {
+        let mut p = self.path.clone();
+        let name = name_to_string(name);
+        p.push(&name[0..PREFIX_DIR_SIZE]);
+        p.push(name);
+
+        fs::remove_dir_all(p).await
+    }
+
     async fn finalize(&self, directory: [u32; 5]) -> io::Result<()> {
         if cfg!(unix) {
             // ensure the underlying directory record is properly synchronized
@@ -102,18 +289,39 @@ impl PersistentLayerStore for DirectoryLayerStore {
             directory_path.push(&dir_name[0..PREFIX_DIR_SIZE]);
             directory_path.push(dir_name);
 
-            let mut options = tokio::fs::OpenOptions::new();
-            options.create(false);
-            options.read(true);
-            options.write(false);
-            let dir_fd = options.open(directory_path).await?;
-            dir_fd.sync_all().await?;
+            sync_directory(directory_path).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&self) -> io::Result<()> {
+        if cfg!(unix) {
+            // layer files and layer directories are synchronized as
+            // they are written and finalized, but the prefix
+            // directories that contain the layer directories are not.
+            let mut stream = fs::read_dir(&self.path).await?;
+            while let Some(direntry) = stream.next_entry().await? {
+                if direntry.file_type().await?.is_dir() {
+                    sync_directory(direntry.path()).await?;
+                }
+            }
+            sync_directory(self.path.clone()).await?;
         }
 
         Ok(())
     }
 }
 
+async fn sync_directory(path: PathBuf) -> io::Result<()> {
+    let mut options = tokio::fs::OpenOptions::new();
+    options.create(false);
+    options.read(true);
+    options.write(false);
+    let dir_fd = options.open(path).await?;
+    dir_fd.sync_all().await
+}
+
 #[derive(Clone)]
 pub struct DirectoryLabelStore {
     path: PathBuf,
@@ -129,13 +337,10 @@ fn get_label_from_data(name: String, data: &[u8]) -> io::Result<Label> {
     let s = String::from_utf8_lossy(&data);
     let lines: Vec<&str> = s.lines().collect();
     if lines.len() != 2 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!(
-                "expected label file to have two lines. contents were ({:?})",
-                lines
-            ),
-        ));
+        return Err(StorageError::Corrupt.with_message(format!(
+            "expected label file to have two lines. contents were ({:?})",
+            lines
+        )));
     }
 
     let version_str = &lines[0];
@@ -143,13 +348,10 @@ fn get_label_from_data(name: String, data: &[u8]) -> io::Result<Label> {
 
     let version = u64::from_str_radix(version_str, 10);
     if version.is_err() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!(
-                "expected first line of label file to be a number but it was {}",
-                version_str
-            ),
-        ));
+        return Err(StorageError::Corrupt.with_message(format!(
+            "expected first line of label file to be a number but it was {}",
+            version_str
+        )));
     }
 
     if layer_str.is_empty() {
@@ -224,10 +426,7 @@ impl LabelStore for DirectoryLabelStore {
         p.push(format!("{}.label", label));
         let contents = "0\n\n".to_string().into_bytes();
         match fs::metadata(&p).await {
-            Ok(_) => Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "database already exists",
-            )),
+            Ok(_) => Err(StorageError::AlreadyExists.into()),
             Err(e) => match e.kind() {
                 io::ErrorKind::NotFound => {
                     let mut file = ExclusiveLockedFile::create_and_open(p).await?;
@@ -279,7 +478,7 @@ impl LabelStore for DirectoryLabelStore {
             file.sync_all().await?;
             Ok(Some(new_label))
         } else {
-            Ok(None)
+            Err(StorageError::VersionConflict.into())
         }
     }
 
@@ -368,10 +567,7 @@ impl LabelStore for CachedDirectoryLabelStore {
     async fn create_label(&self, label: &str) -> io::Result<Label> {
         let mut labels = self.labels.write().await;
         if labels.contains_key(label) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "database already exists",
-            ));
+            return Err(StorageError::AlreadyExists.into());
         }
 
         let mut p = self.path.clone();
@@ -435,7 +631,7 @@ impl LabelStore for CachedDirectoryLabelStore {
                 labels.insert(label.name.clone(), new_label.clone());
                 Ok(Some(new_label))
             } else {
-                Ok(None)
+                Err(StorageError::VersionConflict.into())
             }
         } else {
             Err(io::Error::new(io::ErrorKind::NotFound, "label not found"))
@@ -517,6 +713,87 @@ mod tests {
         assert_eq!(contents, map.as_ref());
     }
 
+    #[tokio::test]
+    async fn write_and_sync_atomic_file_backed() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("foo");
+        let file = AtomicFileBackedStore::new(file_path);
+
+        let mut w = file.open_write().await.unwrap();
+        w.write_all(&[1, 2, 3]).await.unwrap();
+        w.flush().await.unwrap();
+        assert!(!file.exists().await.unwrap());
+
+        w.sync_all().await.unwrap();
+        let map = file.map().await.unwrap();
+
+        assert_eq!(&vec![1, 2, 3][..], &map.as_ref()[..]);
+    }
+
+    #[tokio::test]
+    async fn dropped_atomic_writer_leaves_no_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("foo");
+        let file = AtomicFileBackedStore::new(file_path);
+
+        let mut w = file.open_write().await.unwrap();
+        w.write_all(&[1, 2, 3]).await.unwrap();
+        w.flush().await.unwrap();
+        let temp_path = w.temp_path.clone();
+        assert!(temp_path.exists());
+        std::mem::drop(w);
+
+        // the temporary file is removed in the background
+        for _ in 0..100 {
+            if !temp_path.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        assert!(!file.exists().await.unwrap());
+        assert!(!temp_path.exists());
+    }
+
+    #[tokio::test]
+    async fn concurrent_atomic_writers_use_separate_temp_files() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("foo");
+        let file = AtomicFileBackedStore::new(file_path);
+
+        let mut w1 = file.open_write().await.unwrap();
+        let mut w2 = file.open_write().await.unwrap();
+        assert_ne!(w1.temp_path, w2.temp_path);
+
+        w1.write_all(&[1, 2, 3]).await.unwrap();
+        w2.write_all(&[4, 5, 6, 7]).await.unwrap();
+        w2.sync_all().await.unwrap();
+        w1.sync_all().await.unwrap();
+
+        let map = file.map().await.unwrap();
+        assert_eq!(&vec![1, 2, 3][..], &map.as_ref()[..]);
+    }
+
+    #[tokio::test]
+    async fn dropped_atomic_writer_leaves_file_unchanged() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("foo");
+        let file = AtomicFileBackedStore::new(file_path);
+
+        let mut w = file.open_write().await.unwrap();
+        w.write_all(&[1, 2, 3]).await.unwrap();
+        w.sync_all().await.unwrap();
+
+        let mut w = file.open_write().await.unwrap();
+        w.write_all(&[4, 5, 6, 7]).await.unwrap();
+        w.flush().await.unwrap();
+        std::mem::drop(w);
+
+        let map = file.map().await.unwrap();
+
+        assert_eq!(&vec![1, 2, 3][..], &map.as_ref()[..]);
+    }
+
     #[tokio::test]
     async fn create_layers_from_directory_store() {
         let dir = tempdir().unwrap();
@@ -597,7 +874,7 @@ mod tests {
             let stored1 = store.create_label("foo").await?;
 
             let stored2 = store.set_label(&stored1, [6, 7, 8, 9, 10]).await?;
-            let stored3 = store.set_label(&stored1, [10, 9, 8, 7, 6]).await?;
+            let stored3 = store.set_label(&stored1, [10, 9, 8, 7, 6]).await;
 
             Ok::<_, io::Error>((stored2, stored3))
         }
@@ -605,7 +882,10 @@ mod tests {
         .unwrap();
 
         assert!(stored2.is_some());
-        assert!(stored3.is_none());
+        assert_eq!(
+            Some(StorageError::VersionConflict),
+            StorageError::from_io_error(&stored3.err().unwrap())
+        );
     }
 
     #[tokio::test]
@@ -619,7 +899,27 @@ mod tests {
         assert!(result.is_err());
 
         let error = result.err().unwrap();
-        assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+        assert_eq!(io::ErrorKind::AlreadyExists, error.kind());
+        assert_eq!(
+            Some(StorageError::AlreadyExists),
+            StorageError::from_io_error(&error)
+        );
+    }
+
+    #[tokio::test]
+    async fn directory_corrupt_label_errors() {
+        let dir = tempdir().unwrap();
+        let store = DirectoryLabelStore::new(dir.path());
+        fs::write(dir.path().join("foo.label"), b"not a version\n\n")
+            .await
+            .unwrap();
+
+        let error = store.get_label("foo").await.err().unwrap();
+        assert_eq!(
+            Some(StorageError::Corrupt),
+            StorageError::from_io_error(&error)
+        );
+        assert!(error.to_string().contains("not a version"));
     }
 
     #[tokio::test]
@@ -628,6 +928,18 @@ mod tests {
         assert!(!file.exists().await.unwrap());
     }
 
+    #[tokio::test]
+    async fn flush_directory_store() {
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path());
+
+        let mut builder = store.create_base_layer().await.unwrap();
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.commit_boxed().await.unwrap();
+
+        store.flush().await.unwrap();
+    }
+
     #[tokio::test]
     async fn rollup_and_retrieve_base() {
         let dir = tempdir().unwrap();
@@ -0,0 +1,108 @@
+//! Errors that storage backends report in a way callers can match on.
+use std::io;
+
+use thiserror::Error;
+
+/// A storage failure that callers may want to handle programmatically.
+///
+/// Storage methods return a `StorageResult`, which is an
+/// `io::Result`. A `StorageError` converts into an `io::Error` that
+/// carries it, so it can be recovered with
+/// `StorageError::from_io_error`.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageError {
+    #[error("layer not found")]
+    LayerNotFound,
+    #[error("file not found")]
+    FileNotFound,
+    #[error("stored data is corrupt")]
+    Corrupt,
+    #[error("label was changed concurrently")]
+    VersionConflict,
+    #[error("already exists")]
+    AlreadyExists,
+}
+
+/// The result type of storage operations.
+pub type StorageResult<T> = Result<T, io::Error>;
+
+/// A `StorageError` with a message describing the specific failure.
+#[derive(Error, Debug)]
+#[error("{message}")]
+struct DescribedStorageError {
+    error: StorageError,
+    message: String,
+}
+
+impl StorageError {
+    /// Returns the `StorageError` carried by the given `io::Error`, if any.
+    pub fn from_io_error(err: &io::Error) -> Option<StorageError> {
+        let inner = err.get_ref()?;
+        inner.downcast_ref::<StorageError>().copied().or_else(|| {
+            inner
+                .downcast_ref::<DescribedStorageError>()
+                .map(|described| described.error)
+        })
+    }
+
+    /// Convert into an `io::Error` that reports the given message instead of the generic one.
+    pub(crate) fn with_message<M: Into<String>>(self, message: M) -> io::Error {
+        io::Error::new(
+            self.kind(),
+            DescribedStorageError {
+                error: self,
+                message: message.into(),
+            },
+        )
+    }
+
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            StorageError::LayerNotFound | StorageError::FileNotFound => io::ErrorKind::NotFound,
+            StorageError::Corrupt => io::ErrorKind::InvalidData,
+            StorageError::VersionConflict => io::ErrorKind::Other,
+            StorageError::AlreadyExists => io::ErrorKind::AlreadyExists,
+        }
+    }
+}
+
+impl From<StorageError> for io::Error {
+    fn from(err: StorageError) -> Self {
+        io::Error::new(err.kind(), err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_error_survives_io_error_conversion() {
+        let err: io::Error = StorageError::Corrupt.into();
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!(
+            Some(StorageError::Corrupt),
+            StorageError::from_io_error(&err)
+        );
+    }
+
+    #[test]
+    fn described_storage_error_keeps_message() {
+        let err = StorageError::Corrupt.with_message("bad label file");
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!("bad label file", err.to_string());
+        assert_eq!(
+            Some(StorageError::Corrupt),
+            StorageError::from_io_error(&err)
+        );
+    }
+
+    #[test]
+    fn plain_io_error_has_no_storage_error() {
+        let err = io::Error::new(io::ErrorKind::NotFound, "something else");
+
+        assert_eq!(None, StorageError::from_io_error(&err));
+    }
+}
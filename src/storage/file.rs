@@ -7,6 +7,7 @@ pub use tdb_succinct::storage::{
     AdjacencyListFiles, AdjacencyListMaps, BitIndexFiles, BitIndexMaps, DictionaryFiles,
     DictionaryMaps, FileLoad, FileStore, SyncableFile, TypedDictionaryFiles, TypedDictionaryMaps,
 };
+use tokio::io::AsyncWriteExt;
 
 #[derive(Clone)]
 pub struct IdMapMaps {
@@ -30,6 +31,17 @@ impl<F: 'static + FileLoad + FileStore> IdMapFiles<F> {
             predicate_idmap_maps,
         })
     }
+
+    /// Map the id maps, treating an id map with any of its files missing as absent.
+    pub async fn map_all_lenient(&self) -> io::Result<IdMapMaps> {
+        let node_value_idmap_maps = map_bitindex_if_complete(&self.node_value_idmap_files).await?;
+        let predicate_idmap_maps = map_bitindex_if_complete(&self.predicate_idmap_files).await?;
+
+        Ok(IdMapMaps {
+            node_value_idmap_maps,
+            predicate_idmap_maps,
+        })
+    }
 }
 
 /// The files required for storing a layer
@@ -61,6 +73,13 @@ impl<F: 'static + FileLoad + FileStore + Clone> LayerFiles<F> {
         }
     }
 
+    pub fn metadata_file(&self) -> &F {
+        match self {
+            Self::Base(b) => &b.metadata_file,
+            Self::Child(c) => &c.metadata_file,
+        }
+    }
+
     pub fn into_base(self) -> BaseLayerFiles<F> {
         match self {
             Self::Base(b) => b,
@@ -93,6 +112,8 @@ pub struct BaseLayerFiles<F: 'static + FileLoad + FileStore> {
     pub o_ps_adjacency_list_files: AdjacencyListFiles<F>,
 
     pub predicate_wavelet_tree_files: BitIndexFiles<F>,
+
+    pub metadata_file: F,
 }
 
 #[derive(Clone)]
@@ -112,6 +133,8 @@ pub struct BaseLayerMaps {
     pub o_ps_adjacency_list_maps: AdjacencyListMaps,
 
     pub predicate_wavelet_tree_maps: BitIndexMaps,
+
+    pub metadata_map: Option<Bytes>,
 }
 
 impl<F: FileLoad + FileStore> BaseLayerFiles<F> {
@@ -131,6 +154,8 @@ impl<F: FileLoad + FileStore> BaseLayerFiles<F> {
 
         let predicate_wavelet_tree_maps = self.predicate_wavelet_tree_files.map_all().await?;
 
+        let metadata_map = self.metadata_file.map_if_exists().await?;
+
         Ok(BaseLayerMaps {
             node_dictionary_maps,
             predicate_dictionary_maps,
@@ -146,6 +171,8 @@ impl<F: FileLoad + FileStore> BaseLayerFiles<F> {
             o_ps_adjacency_list_maps,
 
             predicate_wavelet_tree_maps,
+
+            metadata_map,
         })
     }
 }
@@ -172,8 +199,18 @@ pub struct ChildLayerFiles<F: 'static + FileLoad + FileStore + Clone + Send + Sy
 
     pub pos_predicate_wavelet_tree_files: BitIndexFiles<F>,
     pub neg_predicate_wavelet_tree_files: BitIndexFiles<F>,
+
+    pub metadata_file: F,
+    /// Marks a child layer that was built without dictionaries of its own.
+    pub shared_dictionaries_file: F,
 }
 
+/// The maps of a child layer.
+///
+/// Child layers that were built without dictionaries of their own
+/// only refer to nodes, predicates and values already known to their
+/// parent. For such layers the dictionary maps are left empty, and the
+/// `own_*_dictionary_maps` accessors return `None`.
 #[derive(Clone)]
 pub struct ChildLayerMaps {
     pub node_dictionary_maps: DictionaryMaps,
@@ -196,13 +233,57 @@ pub struct ChildLayerMaps {
 
     pub pos_predicate_wavelet_tree_maps: BitIndexMaps,
     pub neg_predicate_wavelet_tree_maps: BitIndexMaps,
+
+    pub metadata_map: Option<Bytes>,
+}
+
+impl ChildLayerMaps {
+    /// Returns the node dictionary maps, or `None` if this layer has no node dictionary of its own.
+    pub fn own_node_dictionary_maps(&self) -> Option<&DictionaryMaps> {
+        dictionary_maps_if_present(&self.node_dictionary_maps)
+    }
+
+    /// Returns the predicate dictionary maps, or `None` if this layer has no predicate dictionary of its own.
+    pub fn own_predicate_dictionary_maps(&self) -> Option<&DictionaryMaps> {
+        dictionary_maps_if_present(&self.predicate_dictionary_maps)
+    }
+
+    /// Returns the value dictionary maps, or `None` if this layer has no value dictionary of its own.
+    pub fn own_value_dictionary_maps(&self) -> Option<&TypedDictionaryMaps> {
+        // a written typed dictionary always has a types present
+        // logarray, which is never empty.
+        if self.value_dictionary_maps.types_present_map.is_empty() {
+            None
+        } else {
+            Some(&self.value_dictionary_maps)
+        }
+    }
+}
+
+fn dictionary_maps_if_present(maps: &DictionaryMaps) -> Option<&DictionaryMaps> {
+    // a written dictionary always has an offsets logarray, which is
+    // never empty.
+    if maps.offsets_map.is_empty() {
+        None
+    } else {
+        Some(maps)
+    }
 }
 
 impl<F: FileLoad + FileStore + Clone> ChildLayerFiles<F> {
     pub async fn map_all(&self) -> io::Result<ChildLayerMaps> {
-        let node_dictionary_maps = self.node_dictionary_files.map_all().await?;
-        let predicate_dictionary_maps = self.predicate_dictionary_files.map_all().await?;
-        let value_dictionary_maps = self.value_dictionary_files.map_all().await?;
+        // only a layer explicitly marked as sharing its parent's
+        // dictionaries may lack dictionary files. For any other layer,
+        // a missing dictionary file is an error.
+        let shared_dictionaries = self.shared_dictionaries_file.exists().await?;
+        let node_dictionary_maps =
+            map_dictionary_unless_shared(&self.node_dictionary_files, shared_dictionaries).await?;
+        let predicate_dictionary_maps =
+            map_dictionary_unless_shared(&self.predicate_dictionary_files, shared_dictionaries)
+                .await?;
+        let value_dictionary_maps =
+            map_typed_dictionary_unless_shared(&self.value_dictionary_files, shared_dictionaries)
+                .await?;
 
         let id_map_maps = self.id_map_files.map_all().await?;
 
@@ -224,6 +305,8 @@ impl<F: FileLoad + FileStore + Clone> ChildLayerFiles<F> {
         let neg_predicate_wavelet_tree_maps =
             self.neg_predicate_wavelet_tree_files.map_all().await?;
 
+        let metadata_map = self.metadata_file.map_if_exists().await?;
+
         Ok(ChildLayerMaps {
             node_dictionary_maps,
             predicate_dictionary_maps,
@@ -245,6 +328,64 @@ impl<F: FileLoad + FileStore + Clone> ChildLayerFiles<F> {
 
             pos_predicate_wavelet_tree_maps,
             neg_predicate_wavelet_tree_maps,
+
+            metadata_map,
+        })
+    }
+}
+
+async fn map_bitindex_if_complete<F: FileLoad + FileStore>(
+    files: &BitIndexFiles<F>,
+) -> io::Result<Option<BitIndexMaps>> {
+    if files.bits_file.exists().await?
+        && files.blocks_file.exists().await?
+        && files.sblocks_file.exists().await?
+    {
+        Ok(Some(files.map_all().await?))
+    } else {
+        Ok(None)
+    }
+}
+
+async fn map_dictionary_unless_shared<F: FileLoad + FileStore>(
+    files: &DictionaryFiles<F>,
+    shared: bool,
+) -> io::Result<DictionaryMaps> {
+    if shared {
+        Ok(DictionaryMaps {
+            blocks_map: Bytes::new(),
+            offsets_map: Bytes::new(),
+        })
+    } else {
+        files.map_all().await
+    }
+}
+
+async fn map_typed_dictionary_unless_shared<F: FileLoad + FileStore>(
+    files: &TypedDictionaryFiles<F>,
+    shared: bool,
+) -> io::Result<TypedDictionaryMaps> {
+    if shared {
+        Ok(TypedDictionaryMaps {
+            types_present_map: Bytes::new(),
+            type_offsets_map: Bytes::new(),
+            blocks_map: Bytes::new(),
+            offsets_map: Bytes::new(),
         })
+    } else {
+        files.map_all().await
     }
 }
+
+/// Writes an empty file, whose presence marks a property of a layer.
+pub(crate) async fn write_marker_file<F: FileStore>(file: &F) -> io::Result<()> {
+    write_metadata_file(file, &[]).await
+}
+
+/// Writes the metadata blob of a layer to its metadata file.
+pub(crate) async fn write_metadata_file<F: FileStore>(file: &F, metadata: &[u8]) -> io::Result<()> {
+    let mut writer = file.open_write().await?;
+    writer.write_all(metadata).await?;
+    writer.flush().await?;
+    writer.sync_all().await
+}
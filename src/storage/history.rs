@@ -0,0 +1,122 @@
+//! A label store wrapper that keeps track of label history.
+use std::collections::HashMap;
+use std::io;
+
+use async_trait::async_trait;
+
+use super::label::*;
+
+/// A label store that records every update of a label.
+///
+/// Label stores only keep the latest version of a label. This wrapper
+/// additionally keeps an in-memory log of each successful update, so
+/// that the sequence of layers a label pointed at can be
+/// reconstructed. The log of a label is discarded when the label is
+/// deleted.
+#[derive(Clone)]
+pub struct HistoryLabelStore<S> {
+    inner: S,
+    history: futures_locks::RwLock<HashMap<String, Vec<(u64, Option<[u32; 5]>)>>>,
+}
+
+impl<S: LabelStore> HistoryLabelStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            history: Default::default(),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns the version and layer of each recorded update of the given label, oldest first.
+    pub async fn label_history(&self, name: &str) -> Vec<(u64, Option<[u32; 5]>)> {
+        let history = self.history.read().await;
+        history.get(name).cloned().unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl<S: LabelStore> LabelStore for HistoryLabelStore<S> {
+    async fn labels(&self) -> io::Result<Vec<Label>> {
+        self.inner.labels().await
+    }
+
+    async fn create_label(&self, name: &str) -> io::Result<Label> {
+        self.inner.create_label(name).await
+    }
+
+    async fn get_label(&self, name: &str) -> io::Result<Option<Label>> {
+        self.inner.get_label(name).await
+    }
+
+    async fn set_label_option(
+        &self,
+        label: &Label,
+        layer: Option<[u32; 5]>,
+    ) -> io::Result<Option<Label>> {
+        let mut history = self.history.write().await;
+        let result = self.inner.set_label_option(label, layer).await?;
+        if let Some(new_label) = &result {
+            history
+                .entry(new_label.name.clone())
+                .or_default()
+                .push((new_label.version, new_label.layer));
+        }
+
+        Ok(result)
+    }
+
+    async fn delete_label(&self, name: &str) -> io::Result<bool> {
+        let mut history = self.history.write().await;
+        let deleted = self.inner.delete_label(name).await?;
+        if deleted {
+            history.remove(name);
+        }
+
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::*;
+
+    #[tokio::test]
+    async fn record_label_history() {
+        let store = HistoryLabelStore::new(MemoryLabelStore::new());
+        let label = store.create_label("foo").await.unwrap();
+        assert!(store.label_history("foo").await.is_empty());
+
+        let label = store
+            .set_label(&label, [1, 2, 3, 4, 5])
+            .await
+            .unwrap()
+            .unwrap();
+        let label = store
+            .set_label(&label, [6, 7, 8, 9, 10])
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(store
+            .set_label(&label.with_updated_layer(None), [0, 0, 0, 0, 0])
+            .await
+            .is_err());
+        store.clear_label(&label).await.unwrap().unwrap();
+
+        assert_eq!(
+            vec![
+                (1, Some([1, 2, 3, 4, 5])),
+                (2, Some([6, 7, 8, 9, 10])),
+                (3, None)
+            ],
+            store.label_history("foo").await
+        );
+
+        assert!(store.delete_label("foo").await.unwrap());
+        assert!(store.label_history("foo").await.is_empty());
+    }
+}
@@ -1,7 +1,7 @@
-use std::io;
-
 use async_trait::async_trait;
 
+use super::error::StorageResult;
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Label {
     pub name: String,
@@ -36,21 +36,30 @@ impl Label {
 
 #[async_trait]
 pub trait LabelStore: Send + Sync {
-    async fn labels(&self) -> io::Result<Vec<Label>>;
-    async fn create_label(&self, name: &str) -> io::Result<Label>;
-    async fn get_label(&self, name: &str) -> io::Result<Option<Label>>;
+    async fn labels(&self) -> StorageResult<Vec<Label>>;
+    /// Create a new empty label.
+    ///
+    /// Fails with `StorageError::AlreadyExists`, of kind
+    /// `io::ErrorKind::AlreadyExists`, if the label already exists.
+    async fn create_label(&self, name: &str) -> StorageResult<Label>;
+    async fn get_label(&self, name: &str) -> StorageResult<Option<Label>>;
+    /// Point the label at the given layer, returning the updated label.
+    ///
+    /// The update only happens if the stored label is still the given
+    /// one. If the label was changed since it was read, this fails
+    /// with `StorageError::VersionConflict`.
     async fn set_label_option(
         &self,
         label: &Label,
         layer: Option<[u32; 5]>,
-    ) -> io::Result<Option<Label>>;
-    async fn delete_label(&self, name: &str) -> io::Result<bool>;
+    ) -> StorageResult<Option<Label>>;
+    async fn delete_label(&self, name: &str) -> StorageResult<bool>;
 
-    async fn set_label(&self, label: &Label, layer: [u32; 5]) -> io::Result<Option<Label>> {
+    async fn set_label(&self, label: &Label, layer: [u32; 5]) -> StorageResult<Option<Label>> {
         self.set_label_option(label, Some(layer)).await
     }
 
-    async fn clear_label(&self, label: &Label) -> io::Result<Option<Label>> {
+    async fn clear_label(&self, label: &Label) -> StorageResult<Option<Label>> {
         self.set_label_option(label, None).await
     }
 }
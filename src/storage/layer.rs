@@ -1,17 +1,22 @@
 use super::cache::*;
 use super::consts::FILENAMES;
 use super::delta::*;
+use super::error::{StorageError, StorageResult};
 use super::file::*;
+use super::memory::MemoryBackedStore;
 use super::pack::Packable;
+use super::stats::LayerStats;
+use crate::chrono_log;
 use crate::layer::base_merge::merge_base_layers;
 use crate::layer::builder::DictionarySetFileBuilder;
+use crate::layer::dictionary::{empty_string_dict, empty_typed_dict};
 use crate::layer::BaseLayerFileBuilder;
 use crate::layer::ChildLayerFileBuilderPhase2;
 use crate::layer::TripleChange;
 use crate::layer::{
     layer_triple_exists, BaseLayer, ChildLayer, IdMap, IdTriple, InternalLayer,
     InternalLayerTripleObjectIterator, InternalLayerTriplePredicateIterator,
-    InternalLayerTripleSubjectIterator, InternalTripleStackIterator, LayerBuilder,
+    InternalLayerTripleSubjectIterator, InternalTripleStackIterator, LayerBuilder, MappedRegions,
     OptInternalLayerTriplePredicateIterator, OptInternalLayerTripleSubjectIterator, RollupLayer,
     SimpleLayerBuilder,
 };
@@ -21,9 +26,13 @@ use tdb_succinct::dict_file_get_count;
 use tdb_succinct::logarray::logarray_file_get_length_and_width;
 use tdb_succinct::StringDict;
 use tdb_succinct::TypedDict;
-use tdb_succinct::{util, AdjacencyList, BitIndex, LogArray, MonotonicLogArray, WaveletTree};
+use tdb_succinct::{
+    util, AdjacencyList, BitIndex, LogArray, LogArrayBufBuilder, MonotonicLogArray, WaveletTree,
+};
 
 use bitvec::prelude::*;
+use bytes::{Bytes, BytesMut};
+use itertools::Itertools;
 use std::convert::TryInto;
 use std::io;
 use std::path::Path;
@@ -85,8 +94,23 @@ pub trait LayerStore: 'static + Packable + Send + Sync {
         Ok(())
     }
 
+    /// Returns the stats that were stored for a layer when it was finalized.
+    ///
+    /// This only reads a small file rather than loading the layer.
+    /// Returns None if the layer does not exist or if it has no
+    /// stored stats, which is the case for layers that were never
+    /// finalized through `finalize_layer`.
+    async fn layer_stats(&self, name: [u32; 5]) -> io::Result<Option<LayerStats>>;
+
     async fn get_layer_parent_name(&self, name: [u32; 5]) -> io::Result<Option<[u32; 5]>>;
 
+    /// Returns the subjects that a child layer adds or removes triples for, in ascending order.
+    ///
+    /// Only the subject files of the layer are mapped, so this is much
+    /// cheaper than loading the layer. Returns None if the layer does
+    /// not exist or is a base layer.
+    async fn child_layer_subjects(&self, name: [u32; 5]) -> io::Result<Option<MonotonicLogArray>>;
+
     async fn get_node_dictionary(&self, name: [u32; 5]) -> io::Result<Option<StringDict>>;
 
     async fn get_predicate_dictionary(&self, name: [u32; 5]) -> io::Result<Option<StringDict>>;
@@ -104,6 +128,23 @@ pub trait LayerStore: 'static + Packable + Send + Sync {
     async fn get_predicate_idmap(&self, name: [u32; 5]) -> io::Result<Option<IdMap>>;
 
     async fn create_base_layer(&self) -> io::Result<Box<dyn LayerBuilder>>;
+    /// Store an already built base layer under a name derived from its contents.
+    ///
+    /// The layer is written under a temporary name and then moved into
+    /// place, so the derived name never refers to a partially written
+    /// layer. If a layer with that name already exists, including one
+    /// stored concurrently, nothing is kept and its name is returned.
+    ///
+    /// Stores that can't move layers fail with `io::ErrorKind::Unsupported`.
+    async fn create_content_addressed_base_layer(
+        &self,
+        _files: &BaseLayerFiles<MemoryBackedStore>,
+    ) -> io::Result<[u32; 5]> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "content-addressed layers are not supported by this store",
+        ))
+    }
     async fn create_child_layer_with_cache(
         &self,
         parent: [u32; 5],
@@ -218,6 +259,125 @@ pub trait LayerStore: 'static + Packable + Send + Sync {
             .await
     }
 
+    /// Roll up the given layer if its stack is deeper than `max_depth`.
+    ///
+    /// The layer is rolled up onto the bottom layer of its stack,
+    /// which leaves a stack of two layers. A `max_depth` below 2 is
+    /// therefore treated as 2. Returns the name of the rollup layer,
+    /// or None if the stack was not too deep.
+    async fn rollup_if_deeper_than(
+        &self,
+        layer: Arc<InternalLayer>,
+        max_depth: usize,
+    ) -> io::Result<Option<[u32; 5]>> {
+        if layer.stack_depth() <= max_depth.max(2) {
+            return Ok(None);
+        }
+
+        let mut bottom = &*layer;
+        while let Some(parent) = bottom.immediate_parent() {
+            bottom = parent;
+        }
+        let bottom_name = bottom.name();
+
+        let rollup = self.imprecise_rollup_upto(layer, bottom_name).await?;
+
+        Ok(Some(rollup))
+    }
+
+    /// Rewrite a layer whose stack contains no removals as a base layer.
+    ///
+    /// A child layer that only adds triples on top of its ancestors
+    /// can be materialized as a single base layer without losing
+    /// anything but history. The new base layer is registered as the
+    /// rollup of the given layer, so subsequent retrievals of the
+    /// layer will query the base layer instead.
+    ///
+    /// Returns the name of the base layer, or None if the stack
+    /// contains removals and the layer was left untouched.
+    async fn promote_to_base(&self, name: [u32; 5]) -> io::Result<Option<[u32; 5]>> {
+        let layer = self
+            .get_layer(name)
+            .await?
+            .ok_or(StorageError::LayerNotFound)?;
+
+        if layer.parent_name().is_none() {
+            // already a base layer, nothing to promote
+            return Ok(Some(name));
+        }
+
+        if layer.triple_removal_count() != 0 {
+            return Ok(None);
+        }
+
+        let base = self.perform_rollup(layer).await?;
+        self.register_rollup(name, base).await?;
+
+        Ok(Some(base))
+    }
+
+    /// Collapse replayed layers in the stack of the given layer.
+    ///
+    /// Replaying a log can produce a child layer with the exact same
+    /// change set as its parent. As the builders leave out additions
+    /// the parent already has and removals it lacks, such a layer
+    /// ends up without any local changes. A layer is left out of the
+    /// stack if its local changes are either identical to those of the
+    /// layer below it or empty, while the layer below it did record
+    /// changes. Layers with metadata are never left out, so an
+    /// intentionally empty layer that carries metadata is kept. Every
+    /// layer above the first one left out is rebuilt on top of its new
+    /// parent.
+    ///
+    /// Returns the name of the new top layer, which is the given
+    /// layer itself if nothing was collapsed.
+    async fn dedup_chain(&self, top: [u32; 5]) -> io::Result<[u32; 5]> {
+        let names = self.retrieve_layer_stack_names(top).await?;
+        let mut layers = Vec::with_capacity(names.len());
+        for name in names {
+            layers.push(
+                self.get_layer(name)
+                    .await?
+                    .ok_or(StorageError::LayerNotFound)?,
+            );
+        }
+
+        let mut current = layers[0].name();
+        let mut previous = &layers[0];
+        let mut rebuilding = false;
+        for layer in &layers[1..] {
+            let is_replay = previous.local_changes().next().is_some()
+                && (layer.local_changes().next().is_none()
+                    || layer.local_changes().eq(previous.local_changes()));
+            if is_replay && layer.metadata().is_none() {
+                rebuilding = true;
+                continue;
+            }
+            previous = layer;
+
+            if !rebuilding {
+                current = layer.name();
+                continue;
+            }
+
+            let mut builder = self.create_child_layer(current).await?;
+            for (change, triple) in layer.local_changes_strings() {
+                match change {
+                    TripleChange::Addition => builder.add_value_triple(triple),
+                    TripleChange::Removal => builder.remove_value_triple(triple),
+                }
+            }
+            if let Some(metadata) = layer.metadata() {
+                builder.set_metadata(metadata);
+            }
+
+            current = builder.name();
+            builder.commit_boxed().await?;
+        }
+
+        Ok(current)
+    }
+
     async fn squash(&self, layer: Arc<InternalLayer>) -> io::Result<[u32; 5]>;
     async fn squash_upto(&self, layer: Arc<InternalLayer>, upto: [u32; 5]) -> io::Result<[u32; 5]>;
 
@@ -355,30 +515,79 @@ pub trait LayerStore: 'static + Packable + Send + Sync {
 #[async_trait]
 pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
     type File: FileLoad + FileStore + Clone;
-    async fn directories(&self) -> io::Result<Vec<[u32; 5]>>;
-    async fn create_named_directory(&self, id: [u32; 5]) -> io::Result<[u32; 5]>;
-    async fn create_directory(&self) -> io::Result<[u32; 5]> {
+    async fn directories(&self) -> StorageResult<Vec<[u32; 5]>>;
+    async fn create_named_directory(&self, id: [u32; 5]) -> StorageResult<[u32; 5]>;
+    async fn create_directory(&self) -> StorageResult<[u32; 5]> {
         let name = rand::random();
         self.create_named_directory(name).await
     }
 
-    async fn directory_exists(&self, name: [u32; 5]) -> io::Result<bool>;
-    async fn get_file(&self, directory: [u32; 5], name: &str) -> io::Result<Self::File>;
-    async fn file_exists(&self, directory: [u32; 5], file: &str) -> io::Result<bool>;
+    async fn directory_exists(&self, name: [u32; 5]) -> StorageResult<bool>;
+    async fn get_file(&self, directory: [u32; 5], name: &str) -> StorageResult<Self::File>;
+    async fn file_exists(&self, directory: [u32; 5], file: &str) -> StorageResult<bool>;
 
-    async fn finalize(&self, _directory: [u32; 5]) -> io::Result<()> {
+    /// Move a directory to a new name.
+    ///
+    /// The move is atomic: either the complete directory appears under
+    /// the new name or nothing changes. If the new name is already
+    /// taken, this fails with an `AlreadyExists` error. Stores that
+    /// can't move directories fail with `io::ErrorKind::Unsupported`.
+    async fn rename_directory(&self, _from: [u32; 5], _to: [u32; 5]) -> StorageResult<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "renaming directories is not supported by this store",
+        ))
+    }
+
+    /// Remove a directory and all files in it.
+    ///
+    /// Stores that can't remove directories fail with
+    /// `io::ErrorKind::Unsupported`.
+    async fn delete_directory(&self, _name: [u32; 5]) -> StorageResult<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "deleting directories is not supported by this store",
+        ))
+    }
+
+    async fn finalize(&self, _directory: [u32; 5]) -> StorageResult<()> {
         Ok(())
     }
 
-    async fn layer_has_rollup(&self, name: [u32; 5]) -> io::Result<bool> {
+    /// Returns whether the files of this store are memory mapped.
+    ///
+    /// Layers loaded from a store that keeps its files on the heap
+    /// have nothing to prefault.
+    fn files_are_mapped(&self) -> bool {
+        true
+    }
+
+    /// Ensure that all layers written so far are durable.
+    ///
+    /// This is meant to be called after committing layers and before
+    /// pointing a label at them.
+    async fn flush(&self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    async fn layer_has_rollup(&self, name: [u32; 5]) -> StorageResult<bool> {
         self.file_exists(name, FILENAMES.rollup).await
     }
 
-    async fn layer_has_parent(&self, name: [u32; 5]) -> io::Result<bool> {
+    /// Returns whether the given layer was built without dictionaries of its own.
+    ///
+    /// Such a layer only refers to entries of its parent's
+    /// dictionaries. Any other layer missing a dictionary file is
+    /// damaged.
+    async fn layer_has_shared_dictionaries(&self, name: [u32; 5]) -> StorageResult<bool> {
+        self.file_exists(name, FILENAMES.shared_dictionaries).await
+    }
+
+    async fn layer_has_parent(&self, name: [u32; 5]) -> StorageResult<bool> {
         self.file_exists(name, FILENAMES.parent).await
     }
 
-    async fn layer_parent(&self, name: [u32; 5]) -> io::Result<Option<[u32; 5]>> {
+    async fn layer_parent(&self, name: [u32; 5]) -> StorageResult<Option<[u32; 5]>> {
         if self.directory_exists(name).await? {
             if self.layer_has_parent(name).await? {
                 let parent = self.read_parent_file(name).await?;
@@ -387,14 +596,11 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 Ok(None)
             }
         } else {
-            Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                "parent layer not found",
-            ))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
-    async fn base_layer_files(&self, name: [u32; 5]) -> io::Result<BaseLayerFiles<Self::File>> {
+    async fn base_layer_files(&self, name: [u32; 5]) -> StorageResult<BaseLayerFiles<Self::File>> {
         let filenames = vec![
             FILENAMES.node_dictionary_blocks,
             FILENAMES.node_dictionary_offsets,
@@ -427,6 +633,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
             FILENAMES.base_predicate_wavelet_tree_bits,
             FILENAMES.base_predicate_wavelet_tree_bit_index_blocks,
             FILENAMES.base_predicate_wavelet_tree_bit_index_sblocks,
+            FILENAMES.metadata,
         ];
 
         let mut files = Vec::with_capacity(filenames.len());
@@ -496,10 +703,15 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 blocks_file: files[29].clone(),
                 sblocks_file: files[30].clone(),
             },
+
+            metadata_file: files[31].clone(),
         })
     }
 
-    async fn child_layer_files(&self, name: [u32; 5]) -> io::Result<ChildLayerFiles<Self::File>> {
+    async fn child_layer_files(
+        &self,
+        name: [u32; 5],
+    ) -> StorageResult<ChildLayerFiles<Self::File>> {
         let filenames = vec![
             FILENAMES.node_dictionary_blocks,
             FILENAMES.node_dictionary_offsets,
@@ -549,6 +761,8 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
             FILENAMES.neg_predicate_wavelet_tree_bits,
             FILENAMES.neg_predicate_wavelet_tree_bit_index_blocks,
             FILENAMES.neg_predicate_wavelet_tree_bit_index_sblocks,
+            FILENAMES.metadata,
+            FILENAMES.shared_dictionaries,
         ];
 
         let mut files = Vec::with_capacity(filenames.len());
@@ -648,10 +862,17 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 blocks_file: files[46].clone(),
                 sblocks_file: files[47].clone(),
             },
+
+            metadata_file: files[48].clone(),
+            shared_dictionaries_file: files[49].clone(),
         })
     }
 
-    async fn write_parent_file(&self, dir_name: [u32; 5], parent_name: [u32; 5]) -> io::Result<()> {
+    async fn write_parent_file(
+        &self,
+        dir_name: [u32; 5],
+        parent_name: [u32; 5],
+    ) -> StorageResult<()> {
         let parent_string = name_to_string(parent_name);
 
         let file = self.get_file(dir_name, FILENAMES.parent).await?;
@@ -664,7 +885,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
         Ok(())
     }
 
-    async fn read_parent_file(&self, dir_name: [u32; 5]) -> io::Result<[u32; 5]> {
+    async fn read_parent_file(&self, dir_name: [u32; 5]) -> StorageResult<[u32; 5]> {
         let file = self.get_file(dir_name, FILENAMES.parent).await?;
         let mut reader = file.open_read().await?;
 
@@ -675,7 +896,11 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
     }
 
     // TODO this should check if the rollup is better than what is there
-    async fn write_rollup_file(&self, dir_name: [u32; 5], rollup_name: [u32; 5]) -> io::Result<()> {
+    async fn write_rollup_file(
+        &self,
+        dir_name: [u32; 5],
+        rollup_name: [u32; 5],
+    ) -> StorageResult<()> {
         let rollup_string = name_to_string(rollup_name);
 
         let file = self.get_file(dir_name, FILENAMES.rollup).await?;
@@ -689,7 +914,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
         Ok(())
     }
 
-    async fn read_rollup_file(&self, dir_name: [u32; 5]) -> io::Result<[u32; 5]> {
+    async fn read_rollup_file(&self, dir_name: [u32; 5]) -> StorageResult<[u32; 5]> {
         let file = self.get_file(dir_name, FILENAMES.rollup).await?;
         let mut reader = file.open_read().await?;
 
@@ -714,18 +939,34 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
         string_to_name(layer_str)
     }
 
+    async fn write_stats_file(&self, dir_name: [u32; 5], stats: &LayerStats) -> StorageResult<()> {
+        let file = self.get_file(dir_name, FILENAMES.stats).await?;
+        let mut writer = file.open_write().await?;
+
+        writer.write_all(&stats.to_bytes()).await?;
+        writer.flush().await?;
+        writer.sync_all().await?;
+
+        Ok(())
+    }
+
+    async fn read_stats_file(&self, dir_name: [u32; 5]) -> StorageResult<LayerStats> {
+        let file = self.get_file(dir_name, FILENAMES.stats).await?;
+        let mut reader = file.open_read().await?;
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+
+        LayerStats::from_bytes(&data)
+    }
+
     async fn create_child_layer_files_with_cache(
         &self,
         parent: [u32; 5],
         cache: Arc<dyn LayerCache>,
-    ) -> io::Result<([u32; 5], Arc<InternalLayer>, ChildLayerFiles<Self::File>)> {
+    ) -> StorageResult<([u32; 5], Arc<InternalLayer>, ChildLayerFiles<Self::File>)> {
         let parent_layer = match self.get_layer_with_cache(parent, cache).await? {
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::NotFound,
-                    "parent layer not found",
-                ))
-            }
+            None => return Err(StorageError::LayerNotFound.into()),
             Some(parent_layer) => Ok::<_, io::Error>(parent_layer),
         }?;
 
@@ -739,7 +980,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
     async fn node_dictionary_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<DictionaryFiles<Self::File>> {
+    ) -> StorageResult<DictionaryFiles<Self::File>> {
         // does layer exist?
         if self.directory_exists(layer).await? {
             let offsets_file = self
@@ -754,14 +995,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 offsets_file,
             })
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn predicate_dictionary_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<DictionaryFiles<Self::File>> {
+    ) -> StorageResult<DictionaryFiles<Self::File>> {
         // does layer exist?
         if self.directory_exists(layer).await? {
             let offsets_file = self
@@ -776,14 +1017,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 offsets_file,
             })
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn value_dictionary_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<TypedDictionaryFiles<Self::File>> {
+    ) -> StorageResult<TypedDictionaryFiles<Self::File>> {
         // does layer exist?
         if self.directory_exists(layer).await? {
             let types_present_file = self
@@ -806,14 +1047,32 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 offsets_file,
             })
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
+        }
+    }
+
+    /// Returns the types present and type offsets buffers of the value dictionary of the given layer.
+    ///
+    /// These are the logarrays a `TypedDict` is parsed from, exactly
+    /// as they are stored. Returns None if the layer has no value
+    /// dictionary of its own.
+    async fn value_dictionary_type_bytes(
+        &self,
+        layer: [u32; 5],
+    ) -> StorageResult<Option<(Bytes, Bytes)>> {
+        if self.layer_has_shared_dictionaries(layer).await? {
+            return Ok(None);
         }
+        let files = self.value_dictionary_files(layer).await?;
+        let maps = files.map_all().await?;
+
+        Ok(Some((maps.types_present_map, maps.type_offsets_map)))
     }
 
     async fn node_value_idmap_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<BitIndexFiles<Self::File>> {
+    ) -> StorageResult<BitIndexFiles<Self::File>> {
         // does layer exist?
         if self.directory_exists(layer).await? {
             let bits_file = self
@@ -832,14 +1091,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 sblocks_file,
             })
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn predicate_idmap_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<BitIndexFiles<Self::File>> {
+    ) -> StorageResult<BitIndexFiles<Self::File>> {
         // does layer exist?
         if self.directory_exists(layer).await? {
             let bits_file = self.get_file(layer, FILENAMES.predicate_idmap_bits).await?;
@@ -856,14 +1115,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 sblocks_file,
             })
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn triple_addition_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<(
+    ) -> StorageResult<(
         Self::File,
         AdjacencyListFiles<Self::File>,
         AdjacencyListFiles<Self::File>,
@@ -962,14 +1221,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
 
             Ok((subjects_file, s_p_aj_files, sp_o_aj_files))
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn triple_removal_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<
+    ) -> StorageResult<
         Option<(
             Self::File,
             AdjacencyListFiles<Self::File>,
@@ -1043,14 +1302,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 Ok(None)
             }
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn predicate_wavelet_addition_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<BitIndexFiles<Self::File>> {
+    ) -> StorageResult<BitIndexFiles<Self::File>> {
         // does layer exist?
         if self.directory_exists(layer).await? {
             let (wavelet_bits_file, wavelet_bit_index_blocks_file, wavelet_bit_index_sblocks_file);
@@ -1094,14 +1353,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
             };
             Ok(bitindex_files)
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn predicate_wavelet_removal_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<Option<BitIndexFiles<Self::File>>> {
+    ) -> StorageResult<Option<BitIndexFiles<Self::File>>> {
         // does layer exist?
         if self.directory_exists(layer).await? {
             if self.layer_has_parent(layer).await? {
@@ -1129,14 +1388,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 Ok(None)
             }
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn triple_addition_files_by_object(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<(
+    ) -> StorageResult<(
         Self::File,
         Self::File,
         AdjacencyListFiles<Self::File>,
@@ -1239,14 +1498,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
 
             Ok((subjects_file, objects_file, o_ps_aj_files, s_p_aj_files))
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn triple_removal_files_by_object(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<
+    ) -> StorageResult<
         Option<(
             Self::File,
             Self::File,
@@ -1315,14 +1574,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 Ok(None)
             }
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn triple_layer_addition_count_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<(Self::File, Self::File, BitIndexFiles<Self::File>)> {
+    ) -> StorageResult<(Self::File, Self::File, BitIndexFiles<Self::File>)> {
         // does layer exist?
         if self.directory_exists(layer).await? {
             let (s_p_nums_file, sp_o_bits_file);
@@ -1347,14 +1606,14 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
             let predicate_wavelet_files = self.predicate_wavelet_addition_files(layer).await?;
             Ok((s_p_nums_file, sp_o_bits_file, predicate_wavelet_files))
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 
     async fn triple_layer_removal_count_files(
         &self,
         layer: [u32; 5],
-    ) -> io::Result<Option<(Self::File, Self::File, BitIndexFiles<Self::File>)>> {
+    ) -> StorageResult<Option<(Self::File, Self::File, BitIndexFiles<Self::File>)>> {
         // does layer exist?
         if self.directory_exists(layer).await? {
             if self.layer_has_parent(layer).await? {
@@ -1379,7 +1638,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
                 Ok(None)
             }
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 }
@@ -1412,6 +1671,34 @@ pub fn string_to_name(string: &str) -> Result<[u32; 5], std::io::Error> {
     Ok([n1, n2, n3, n4, n5])
 }
 
+/// Derive a layer name from the contents of the given base layer files.
+///
+/// The name is the first 20 bytes of a BLAKE3 hash over every file,
+/// each prefixed with its length, so identical layers get identical names.
+pub async fn base_layer_content_name<F: 'static + FileLoad + FileStore>(
+    files: &BaseLayerFiles<F>,
+) -> io::Result<[u32; 5]> {
+    let maps = files.map_all().await?;
+    let mut hasher = blake3::Hasher::new();
+    for region in MappedRegions::from_base_maps(&maps).regions() {
+        hasher.update(&(region.len() as u64).to_be_bytes());
+        hasher.update(region);
+    }
+    if let Some(metadata) = &maps.metadata_map {
+        // layers that only differ in their metadata should not share a name
+        hasher.update(&(metadata.len() as u64).to_be_bytes());
+        hasher.update(metadata);
+    }
+    let hash = hasher.finalize();
+
+    let mut name = [0; 5];
+    for (ix, chunk) in hash.as_bytes()[..20].chunks(4).enumerate() {
+        name[ix] = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+
+    Ok(name)
+}
+
 pub fn bytes_to_name(bytes: &[u8]) -> Result<[u32; 5], std::io::Error> {
     if bytes.len() != 40 {
         Err(io::Error::new(io::ErrorKind::Other, "bytes not len 40"))
@@ -1521,14 +1808,20 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
             match rollup {
                 None => {
                     let files = self.base_layer_files(base_id).await?;
-                    let base_layer = BaseLayer::load_from_files(base_id, &files).await?;
+                    let mut base_layer = BaseLayer::load_from_files(base_id, &files).await?;
+                    if !self.files_are_mapped() {
+                        base_layer.forget_mapped_regions();
+                    }
 
-                    layer = Arc::new(base_layer.into());
+                    layer = Arc::new(base_layer);
                 }
                 Some((rollup_id, original_parent_id_option)) => {
                     let files = self.base_layer_files(rollup_id).await?;
-                    let base_layer: Arc<InternalLayer> =
-                        Arc::new(BaseLayer::load_from_files(rollup_id, &files).await?.into());
+                    let mut base_layer = BaseLayer::load_from_files(rollup_id, &files).await?;
+                    if !self.files_are_mapped() {
+                        base_layer.forget_mapped_regions();
+                    }
+                    let base_layer = Arc::new(base_layer);
                     cache.cache_layer(base_layer.clone());
 
                     layer = Arc::new(
@@ -1554,20 +1847,24 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
             match rollup {
                 None => {
                     let files = self.child_layer_files(layer_id).await?;
-                    let child_layer =
+                    let mut child_layer =
                         ChildLayer::load_from_files(layer_id, ancestor, &files).await?;
-                    layer = Arc::new(child_layer.into());
+                    if !self.files_are_mapped() {
+                        child_layer.forget_mapped_regions();
+                    }
+                    layer = Arc::new(child_layer);
                 }
                 Some((rollup_id, original_parent_id_option)) => {
                     let original_parent_id = original_parent_id_option
                         .expect("child rollup layer should always have original parent id");
 
                     let files = self.child_layer_files(rollup_id).await?;
-                    let child_layer: Arc<InternalLayer> = Arc::new(
-                        ChildLayer::load_from_files(rollup_id, ancestor, &files)
-                            .await?
-                            .into(),
-                    );
+                    let mut child_layer =
+                        ChildLayer::load_from_files(rollup_id, ancestor, &files).await?;
+                    if !self.files_are_mapped() {
+                        child_layer.forget_mapped_regions();
+                    }
+                    let child_layer = Arc::new(child_layer);
                     cache.cache_layer(child_layer.clone());
 
                     layer = Arc::new(
@@ -1587,15 +1884,69 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
     }
 
     async fn finalize_layer(&self, name: [u32; 5]) -> io::Result<()> {
+        // stats are only an optimization, so failing to collect them
+        // should not fail the commit.
+        if let Ok(stats) = layer_stats_from_files(self, name).await {
+            if self.write_stats_file(name, &stats).await.is_err() {
+                chrono_log!("could not write stats for layer {}", name_to_string(name));
+            }
+        }
+
         self.finalize(name).await
     }
 
+    async fn layer_stats(&self, name: [u32; 5]) -> io::Result<Option<LayerStats>> {
+        if self.directory_exists(name).await? && self.file_exists(name, FILENAMES.stats).await? {
+            Ok(Some(self.read_stats_file(name).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
     async fn get_layer_parent_name(&self, name: [u32; 5]) -> io::Result<Option<[u32; 5]>> {
         self.layer_parent(name).await
     }
 
+    async fn child_layer_subjects(&self, name: [u32; 5]) -> io::Result<Option<MonotonicLogArray>> {
+        if !self.directory_exists(name).await? || !self.layer_has_parent(name).await? {
+            return Ok(None);
+        }
+
+        let mut subject_arrays = Vec::with_capacity(2);
+        for filename in [FILENAMES.pos_subjects, FILENAMES.neg_subjects] {
+            let map = self.get_file(name, filename).await?.map().await?;
+            subject_arrays.push(LogArray::parse(map).map_err(|_| StorageError::Corrupt)?);
+        }
+        let neg_subjects = subject_arrays.pop().unwrap();
+        let pos_subjects = subject_arrays.pop().unwrap();
+        if neg_subjects.is_empty() {
+            return Ok(Some(pos_subjects.into()));
+        }
+
+        // both subject lists are sorted, so their union can be written out in one pass
+        let subjects: Vec<u64> = pos_subjects
+            .iter()
+            .merge(neg_subjects.iter())
+            .dedup()
+            .collect();
+        let width = util::calculate_width(*subjects.last().unwrap());
+        let mut buf = BytesMut::new();
+        let mut builder = LogArrayBufBuilder::new(&mut buf, width);
+        builder.reserve(subjects.len());
+        for subject in subjects {
+            builder.push(subject);
+        }
+        builder.finalize();
+        let subjects = LogArray::parse(buf.freeze()).map_err(|_| StorageError::Corrupt)?;
+
+        Ok(Some(subjects.into()))
+    }
+
     async fn get_node_dictionary(&self, name: [u32; 5]) -> io::Result<Option<StringDict>> {
         if self.directory_exists(name).await? {
+            if self.layer_has_shared_dictionaries(name).await? {
+                return Ok(Some(empty_string_dict()));
+            }
             let files = self.node_dictionary_files(name).await?;
             let maps = files.map_all().await?;
 
@@ -1607,6 +1958,9 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
 
     async fn get_predicate_dictionary(&self, name: [u32; 5]) -> io::Result<Option<StringDict>> {
         if self.directory_exists(name).await? {
+            if self.layer_has_shared_dictionaries(name).await? {
+                return Ok(Some(empty_string_dict()));
+            }
             let files = self.predicate_dictionary_files(name).await?;
             let maps = files.map_all().await?;
 
@@ -1618,6 +1972,9 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
 
     async fn get_value_dictionary(&self, name: [u32; 5]) -> io::Result<Option<TypedDict>> {
         if self.directory_exists(name).await? {
+            if self.layer_has_shared_dictionaries(name).await? {
+                return Ok(Some(empty_typed_dict()));
+            }
             let files = self.value_dictionary_files(name).await?;
             let maps = files.map_all().await?;
 
@@ -1634,6 +1991,9 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
 
     async fn get_node_count(&self, name: [u32; 5]) -> io::Result<Option<u64>> {
         if self.directory_exists(name).await? {
+            if self.layer_has_shared_dictionaries(name).await? {
+                return Ok(Some(0));
+            }
             let file = self.node_dictionary_files(name).await?.blocks_file;
             Ok(Some(dict_file_get_count(file).await?))
         } else {
@@ -1643,6 +2003,9 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
 
     async fn get_predicate_count(&self, name: [u32; 5]) -> io::Result<Option<u64>> {
         if self.directory_exists(name).await? {
+            if self.layer_has_shared_dictionaries(name).await? {
+                return Ok(Some(0));
+            }
             let file = self.predicate_dictionary_files(name).await?.blocks_file;
             Ok(Some(dict_file_get_count(file).await?))
         } else {
@@ -1652,6 +2015,9 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
 
     async fn get_value_count(&self, name: [u32; 5]) -> io::Result<Option<u64>> {
         if self.directory_exists(name).await? {
+            if self.layer_has_shared_dictionaries(name).await? {
+                return Ok(Some(0));
+            }
             let file = self.value_dictionary_files(name).await?.blocks_file;
             Ok(Some(dict_file_get_count(file).await?))
         } else {
@@ -1708,6 +2074,45 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
         Ok(Box::new(SimpleLayerBuilder::new(dir_name, files)) as Box<dyn LayerBuilder>)
     }
 
+    async fn create_content_addressed_base_layer(
+        &self,
+        files: &BaseLayerFiles<MemoryBackedStore>,
+    ) -> io::Result<[u32; 5]> {
+        let name = base_layer_content_name(files).await?;
+        if self.directory_exists(name).await? {
+            return Ok(name);
+        }
+
+        // write the layer under a temporary name first, so that the
+        // content-addressed name only ever refers to a complete layer.
+        let temp_name = self.create_directory().await?;
+        let copied = async {
+            let output_files = self.base_layer_files(temp_name).await?;
+            output_files.copy_from(files).await
+        }
+        .await;
+        if let Err(e) = copied {
+            let _ = self.delete_directory(temp_name).await;
+            return Err(e);
+        }
+
+        match self.rename_directory(temp_name, name).await {
+            Ok(()) => {
+                self.finalize(name).await?;
+                Ok(name)
+            }
+            Err(e) => {
+                let _ = self.delete_directory(temp_name).await;
+                if e.kind() == io::ErrorKind::AlreadyExists {
+                    // a concurrent writer stored the same layer first
+                    Ok(name)
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
     async fn create_child_layer_with_cache(
         &self,
         parent: [u32; 5],
@@ -1744,6 +2149,14 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
         let dir_name = self.create_directory().await?;
         let files = self.base_layer_files(dir_name).await?;
         delta_rollup(&layer, files).await?;
+        if let Some(metadata) = layer.metadata() {
+            // a rollup stands in for the original layer, so it carries its metadata
+            write_metadata_file(
+                &self.get_file(dir_name, FILENAMES.metadata).await?,
+                &metadata,
+            )
+            .await?;
+        }
         self.finalize(dir_name).await?;
 
         Ok(dir_name)
@@ -1780,6 +2193,13 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
             .create_child_layer_files_with_cache(upto, cache)
             .await?;
         delta_rollup_upto(self, &layer, upto, child_layer_files).await?;
+        if let Some(metadata) = layer.metadata() {
+            write_metadata_file(
+                &self.get_file(layer_dir, FILENAMES.metadata).await?,
+                &metadata,
+            )
+            .await?;
+        }
         self.finalize(layer_dir).await?;
         Ok(layer_dir)
     }
@@ -1815,6 +2235,13 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
             .create_child_layer_files_with_cache(upto, cache)
             .await?;
         imprecise_delta_rollup_upto(self, &layer, upto, child_layer_files).await?;
+        if let Some(metadata) = layer.metadata() {
+            write_metadata_file(
+                &self.get_file(layer_dir, FILENAMES.metadata).await?,
+                &metadata,
+            )
+            .await?;
+        }
         self.finalize(layer_dir).await?;
         Ok(layer_dir)
     }
@@ -2515,10 +2942,7 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
                 }
                 result.push(parent);
             } else {
-                return Err(io::Error::new(
-                    io::ErrorKind::NotFound,
-                    "parent layer not found while retrieving names of layer stack",
-                ));
+                return Err(StorageError::LayerNotFound.into());
             }
         }
 
@@ -2527,6 +2951,42 @@ impl<F: 'static + FileLoad + FileStore + Clone, T: 'static + PersistentLayerStor
     }
 }
 
+/// Computes the stats of a layer from the counts stored in its files.
+///
+/// This walks down the stack until it finds an ancestor with stored
+/// stats, so no layer has to be loaded.
+async fn layer_stats_from_files<S: PersistentLayerStore>(
+    store: &S,
+    name: [u32; 5],
+) -> io::Result<LayerStats> {
+    let mut stats = LayerStats::default();
+    let mut removal_count = 0;
+    let mut current = Some(name);
+    while let Some(layer) = current {
+        if layer != name {
+            if let Some(stored) = store.layer_stats(layer).await? {
+                stats.node_count += stored.node_count;
+                stats.predicate_count += stored.predicate_count;
+                stats.value_count += stored.value_count;
+                stats.triple_count += stored.triple_count;
+                break;
+            }
+        }
+
+        stats.node_count += store.get_node_count(layer).await?.unwrap_or(0);
+        stats.predicate_count += store.get_predicate_count(layer).await?.unwrap_or(0);
+        stats.value_count += store.get_value_count(layer).await?.unwrap_or(0);
+        stats.triple_count += store.triple_layer_addition_count(layer).await? as u64;
+        removal_count += store.triple_layer_removal_count(layer).await? as u64;
+
+        current = store.layer_parent(layer).await?;
+    }
+
+    stats.triple_count = stats.triple_count.saturating_sub(removal_count);
+
+    Ok(stats)
+}
+
 pub(crate) async fn file_triple_exists<F: FileLoad + FileStore>(
     subjects_file: F,
     s_p_adjacency_list_files: AdjacencyListFiles<F>,
@@ -2761,6 +3221,187 @@ mod tests {
         Ok((name, layer_opt, add_contents, remove_contents))
     }
 
+    async fn promote_additions_only_stack<S: LayerStore>(store: &S) -> io::Result<()> {
+        let (base_name, _base_layer, _) = example_base_layer(store, false).await?;
+        let mut builder = store.create_child_layer(base_name).await?;
+        let name = builder.name();
+        for t in CHILD_ADDITION_TRIPLES.iter() {
+            builder.add_value_triple(t.clone());
+        }
+        builder.commit_boxed().await?;
+        let layer = store.get_layer(name).await?.unwrap();
+
+        let promoted = store.promote_to_base(name).await?.unwrap();
+        assert_ne!(name, promoted);
+        let promoted_layer = store.get_layer(promoted).await?.unwrap();
+        assert_eq!(None, promoted_layer.parent_name());
+
+        let expected: Vec<_> = layer
+            .triples()
+            .map(|t| layer.id_triple_to_string(&t).unwrap())
+            .collect();
+        let actual: Vec<_> = promoted_layer
+            .triples()
+            .map(|t| promoted_layer.id_triple_to_string(&t).unwrap())
+            .collect();
+        assert_eq!(expected, actual);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_promote_additions_only_stack() {
+        let store = MemoryLayerStore::new();
+        promote_additions_only_stack(&store).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn directory_promote_additions_only_stack() {
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path());
+        promote_additions_only_stack(&store).await.unwrap();
+    }
+
+    async fn layer_stats_match_loaded_layer<S: LayerStore>(store: &S) -> io::Result<()> {
+        let (name, _layer, _, _) = example_child_layer(store, true).await?;
+        assert_eq!(None, store.layer_stats(name).await?);
+
+        store.finalize_layer(name).await?;
+        let stats = store.layer_stats(name).await?.unwrap();
+
+        let layer = store.get_layer(name).await?.unwrap();
+        let counts = layer.all_counts();
+        assert_eq!(counts.node_count as u64, stats.node_count);
+        assert_eq!(counts.predicate_count as u64, stats.predicate_count);
+        assert_eq!(counts.value_count as u64, stats.value_count);
+        assert_eq!(layer.triple_count() as u64, stats.triple_count);
+        assert_eq!(10, stats.triple_count);
+
+        assert_eq!(None, store.layer_stats([1, 2, 3, 4, 5]).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_layer_stats_match_loaded_layer() {
+        let store = MemoryLayerStore::new();
+        layer_stats_match_loaded_layer(&store).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn directory_layer_stats_match_loaded_layer() {
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path());
+        layer_stats_match_loaded_layer(&store).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stack_with_removals_is_not_promoted() {
+        let store = MemoryLayerStore::new();
+        let (name, _layer, _, _) = example_child_layer(&store, false).await.unwrap();
+
+        assert_eq!(None, store.promote_to_base(name).await.unwrap());
+    }
+
+    async fn build_memory_base_layer() -> BaseLayerFiles<MemoryBackedStore> {
+        let files = crate::storage::memory::base_layer_memory_files();
+        let mut builder = SimpleLayerBuilder::new([0, 0, 0, 0, 0], files.clone());
+        for t in BASE_TRIPLES.iter() {
+            builder.add_value_triple(t.clone());
+        }
+        builder.commit().await.unwrap();
+
+        files
+    }
+
+    #[tokio::test]
+    async fn content_addressed_base_layer_is_stored_once() {
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path());
+
+        let name1 = store
+            .create_content_addressed_base_layer(&build_memory_base_layer().await)
+            .await
+            .unwrap();
+        let name2 = store
+            .create_content_addressed_base_layer(&build_memory_base_layer().await)
+            .await
+            .unwrap();
+
+        assert_eq!(name1, name2);
+        assert!(store.directory_exists(name1).await.unwrap());
+
+        let layer = store.get_layer(name1).await.unwrap().unwrap();
+        for t in BASE_TRIPLES.iter() {
+            assert!(layer.value_triple_exists(t));
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_content_addressed_base_layers_are_stored_once() {
+        let store = MemoryLayerStore::new();
+        let files1 = build_memory_base_layer().await;
+        let files2 = build_memory_base_layer().await;
+
+        let (name1, name2) = tokio::join!(
+            store.create_content_addressed_base_layer(&files1),
+            store.create_content_addressed_base_layer(&files2)
+        );
+        let name1 = name1.unwrap();
+        let name2 = name2.unwrap();
+
+        assert_eq!(name1, name2);
+        // the temporary layer of the writer that lost has been removed
+        assert_eq!(vec![name1], store.layers().await.unwrap());
+
+        let layer = store.get_layer(name1).await.unwrap().unwrap();
+        for t in BASE_TRIPLES.iter() {
+            assert!(layer.value_triple_exists(t));
+        }
+    }
+
+    #[tokio::test]
+    async fn existing_directory_is_not_renamed_over() {
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path());
+
+        let from = store.create_directory().await.unwrap();
+        let to = store.create_directory().await.unwrap();
+        let error = store.rename_directory(from, to).await.unwrap_err();
+
+        assert_eq!(io::ErrorKind::AlreadyExists, error.kind());
+        assert!(store.directory_exists(from).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn content_addressed_base_layer_keeps_metadata() {
+        let dir = tempdir().unwrap();
+        let store = DirectoryLayerStore::new(dir.path());
+
+        let files = crate::storage::memory::base_layer_memory_files();
+        let mut builder = SimpleLayerBuilder::new([0, 0, 0, 0, 0], files.clone());
+        for t in BASE_TRIPLES.iter() {
+            builder.add_value_triple(t.clone());
+        }
+        builder.set_metadata(Bytes::from_static(b"some metadata"));
+        builder.commit().await.unwrap();
+
+        let with_metadata = store
+            .create_content_addressed_base_layer(&files)
+            .await
+            .unwrap();
+        let without_metadata = store
+            .create_content_addressed_base_layer(&build_memory_base_layer().await)
+            .await
+            .unwrap();
+        assert_ne!(with_metadata, without_metadata);
+
+        let layer = store.get_layer(with_metadata).await.unwrap().unwrap();
+        assert_eq!(Some(Bytes::from_static(b"some metadata")), layer.metadata());
+        let layer = store.get_layer(without_metadata).await.unwrap().unwrap();
+        assert_eq!(None, layer.metadata());
+    }
+
     async fn base_layer_counts<S: LayerStore>(store: &S, invalidate: bool) -> io::Result<()> {
         let (name, _layer, _) = example_base_layer(store, invalidate).await?;
         assert_eq!(11, store.triple_layer_addition_count(name).await?);
@@ -2,15 +2,92 @@
 
 use std::collections::HashMap;
 use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
 use async_trait::async_trait;
+use tokio::io::AsyncWrite;
 
+use super::error::StorageError;
 use super::file::*;
 use super::label::*;
 use super::layer::*;
 
 pub use tdb_succinct::storage::memory::*;
 
+/// A writer for a memory backed file that fails when writing more than a maximum amount of bytes.
+pub struct BoundedMemoryBackedStoreWriter {
+    inner: <MemoryBackedStore as FileStore>::Write,
+    written: usize,
+    max_size: usize,
+}
+
+impl AsyncWrite for BoundedMemoryBackedStoreWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        if self.written + buf.len() > self.max_size {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Other,
+                "write exceeds maximum size of memory backed file",
+            )));
+        }
+
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(count)) = result {
+            self.written += count;
+        }
+
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl SyncableFile for BoundedMemoryBackedStoreWriter {
+    async fn sync_all(self) -> io::Result<()> {
+        self.inner.sync_all().await
+    }
+}
+
+/// Extra ways of writing to memory backed files.
+#[async_trait]
+pub trait MemoryBackedStoreExt {
+    /// Open a writer that returns an error once more than `max_size` bytes are written to it.
+    ///
+    /// Nothing is stored in the file unless the writer is synced.
+    async fn open_write_bounded(
+        &self,
+        max_size: usize,
+    ) -> io::Result<BoundedMemoryBackedStoreWriter>;
+}
+
+#[async_trait]
+impl MemoryBackedStoreExt for MemoryBackedStore {
+    async fn open_write_bounded(
+        &self,
+        max_size: usize,
+    ) -> io::Result<BoundedMemoryBackedStoreWriter> {
+        Ok(BoundedMemoryBackedStoreWriter {
+            inner: self.open_write().await?,
+            written: 0,
+            max_size,
+        })
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct MemoryLayerStore {
     layers: futures_locks::RwLock<HashMap<[u32; 5], HashMap<String, MemoryBackedStore>>>,
@@ -26,6 +103,10 @@ impl MemoryLayerStore {
 impl PersistentLayerStore for MemoryLayerStore {
     type File = MemoryBackedStore;
 
+    fn files_are_mapped(&self) -> bool {
+        false
+    }
+
     async fn directories(&self) -> io::Result<Vec<[u32; 5]>> {
         let guard = self.layers.read().await;
         Ok(guard.keys().cloned().collect())
@@ -43,6 +124,25 @@ impl PersistentLayerStore for MemoryLayerStore {
         Ok(guard.contains_key(&name))
     }
 
+    async fn rename_directory(&self, from: [u32; 5], to: [u32; 5]) -> io::Result<()> {
+        let mut guard = self.layers.write().await;
+        if guard.contains_key(&to) {
+            return Err(StorageError::AlreadyExists.into());
+        }
+
+        let files = guard.remove(&from).ok_or(StorageError::LayerNotFound)?;
+        guard.insert(to, files);
+
+        Ok(())
+    }
+
+    async fn delete_directory(&self, name: [u32; 5]) -> io::Result<()> {
+        let mut guard = self.layers.write().await;
+        guard.remove(&name);
+
+        Ok(())
+    }
+
     async fn file_exists(&self, directory: [u32; 5], file: &str) -> io::Result<bool> {
         let guard = self.layers.read().await;
         if let Some(files) = guard.get(&directory) {
@@ -71,7 +171,7 @@ impl PersistentLayerStore for MemoryLayerStore {
                 Ok(result)
             }
         } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "layer not found"))
+            Err(StorageError::LayerNotFound.into())
         }
     }
 }
@@ -99,10 +199,7 @@ impl LabelStore for MemoryLabelStore {
 
         let mut labels = self.labels.write().await;
         if labels.get(&label.name).is_some() {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "label already exists",
-            ))
+            Err(StorageError::AlreadyExists.into())
         } else {
             labels.insert(label.name.clone(), label.clone());
             Ok(label)
@@ -131,7 +228,7 @@ impl LabelStore for MemoryLabelStore {
             )),
             Some(old_label) => {
                 if old_label.version + 1 != new_label.version {
-                    Ok(None)
+                    Err(StorageError::VersionConflict.into())
                 } else {
                     labels.insert(new_label.name.clone(), new_label.clone());
 
@@ -211,6 +308,8 @@ pub fn base_layer_memory_files() -> BaseLayerFiles<MemoryBackedStore> {
             blocks_file: MemoryBackedStore::new(),
             sblocks_file: MemoryBackedStore::new(),
         },
+
+        metadata_file: MemoryBackedStore::new(),
     }
 }
 
@@ -308,13 +407,20 @@ pub fn child_layer_memory_files() -> ChildLayerFiles<MemoryBackedStore> {
             blocks_file: MemoryBackedStore::new(),
             sblocks_file: MemoryBackedStore::new(),
         },
+
+        metadata_file: MemoryBackedStore::new(),
+        shared_dictionaries_file: MemoryBackedStore::new(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::layer::dictionary::TypedDictExt;
     use crate::layer::*;
+    use crate::storage::consts::FILENAMES;
+    use bytes::Bytes;
+    use tdb_succinct::{LogArray, MonotonicLogArray, TdbDataType};
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
     #[tokio::test]
@@ -347,6 +453,21 @@ mod tests {
         assert_eq!(vec![1, 2, 3], map.as_ref());
     }
 
+    #[tokio::test]
+    async fn write_past_bound_memory_backed() {
+        let file = MemoryBackedStore::new();
+
+        let mut w = file.open_write_bounded(4).await.unwrap();
+        w.write_all(&[1, 2, 3]).await.unwrap();
+        let error = w.write_all(&[4, 5]).await.unwrap_err();
+        assert_eq!(io::ErrorKind::Other, error.kind());
+
+        w.write_all(&[4]).await.unwrap();
+        w.sync_all().await.unwrap();
+
+        assert_eq!(vec![1, 2, 3, 4], file.map().await.unwrap().as_ref());
+    }
+
     #[tokio::test]
     async fn create_layers_from_memory_store() {
         let store = MemoryLayerStore::new();
@@ -373,6 +494,16 @@ mod tests {
         assert!(!layer.value_triple_exists(&ValueTriple::new_string_value("duck", "says", "quack")));
     }
 
+    #[tokio::test]
+    async fn flush_memory_store() {
+        let store = MemoryLayerStore::new();
+        let mut builder = store.create_base_layer().await.unwrap();
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.commit_boxed().await.unwrap();
+
+        store.flush().await.unwrap();
+    }
+
     #[tokio::test]
     async fn memory_create_and_retrieve_equal_label() {
         let store = MemoryLabelStore::new();
@@ -380,6 +511,188 @@ mod tests {
         assert_eq!(foo, store.get_label("foo").await.unwrap().unwrap());
     }
 
+    #[tokio::test]
+    async fn memory_create_label_twice_errors() {
+        let store = MemoryLabelStore::new();
+        store.create_label("foo").await.unwrap();
+
+        let error = store.create_label("foo").await.err().unwrap();
+        assert_eq!(
+            Some(StorageError::AlreadyExists),
+            StorageError::from_io_error(&error)
+        );
+    }
+
+    #[tokio::test]
+    async fn value_dictionary_type_bytes_round_trip() {
+        let store = MemoryLayerStore::new();
+        let mut builder = store.create_base_layer().await.unwrap();
+        let name = builder.name();
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.add_value_triple(ValueTriple::new_value("cow", "legs", u32::make_entry(&4)));
+        builder.add_value_triple(ValueTriple::new_value(
+            "cow",
+            "weight",
+            i64::make_entry(&612),
+        ));
+        builder.commit_boxed().await.unwrap();
+
+        let (types_present, type_offsets) = store
+            .value_dictionary_type_bytes(name)
+            .await
+            .unwrap()
+            .unwrap();
+        let types_present: MonotonicLogArray = LogArray::parse(types_present).unwrap().into();
+        let type_offsets: MonotonicLogArray = LogArray::parse(type_offsets).unwrap().into();
+
+        let dict = store.get_value_dictionary(name).await.unwrap().unwrap();
+        let expected: Vec<u64> = dict
+            .types_present()
+            .into_iter()
+            .map(|datatype| datatype as u64)
+            .collect();
+        let actual: Vec<u64> = (0..types_present.len())
+            .map(|i| types_present.entry(i))
+            .collect();
+        assert_eq!(expected, actual);
+        assert_eq!(expected.len() - 1, type_offsets.len());
+    }
+
+    #[tokio::test]
+    async fn child_layer_subjects_are_the_changed_subjects() {
+        let store = MemoryLayerStore::new();
+        let mut builder = store.create_base_layer().await.unwrap();
+        let base_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.add_value_triple(ValueTriple::new_string_value("pig", "says", "oink"));
+        builder.add_value_triple(ValueTriple::new_string_value("duck", "says", "quack"));
+        builder.commit_boxed().await.unwrap();
+
+        let mut builder = store.create_child_layer(base_name).await.unwrap();
+        let child_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_string_value("horse", "says", "neigh"));
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "horse"));
+        builder.remove_value_triple(ValueTriple::new_string_value("pig", "says", "oink"));
+        builder.commit_boxed().await.unwrap();
+
+        let child = store.get_layer(child_name).await.unwrap().unwrap();
+        let mut expected = vec![
+            child.subject_id("cow").unwrap(),
+            child.subject_id("horse").unwrap(),
+            child.subject_id("pig").unwrap(),
+        ];
+        expected.sort();
+
+        let subjects = store
+            .child_layer_subjects(child_name)
+            .await
+            .unwrap()
+            .unwrap();
+        let subjects: Vec<u64> = (0..subjects.len()).map(|i| subjects.entry(i)).collect();
+        assert_eq!(expected, subjects);
+
+        assert!(store
+            .child_layer_subjects(base_name)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn dedup_chain_collapses_replayed_children() {
+        let store = MemoryLayerStore::new();
+        let mut builder = store.create_base_layer().await.unwrap();
+        let base_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_node("duck", "hates", "cow"));
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+        builder.commit_boxed().await.unwrap();
+
+        // replay the same change set twice
+        let mut parent_name = base_name;
+        for _ in 0..2 {
+            let mut builder = store.create_child_layer(parent_name).await.unwrap();
+            parent_name = builder.name();
+            builder.remove_value_triple(ValueTriple::new_node("duck", "hates", "cow"));
+            builder.add_value_triple(ValueTriple::new_node("duck", "likes", "cow"));
+            builder.commit_boxed().await.unwrap();
+        }
+        let replayed = store.get_layer(parent_name).await.unwrap().unwrap();
+        assert_eq!(0, replayed.local_changes().count());
+
+        let mut builder = store.create_child_layer(parent_name).await.unwrap();
+        let top_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("pig", "likes", "cow"));
+        builder.commit_boxed().await.unwrap();
+
+        let deduped_name = store.dedup_chain(top_name).await.unwrap();
+        assert_ne!(top_name, deduped_name);
+
+        let top = store.get_layer(top_name).await.unwrap().unwrap();
+        let deduped = store.get_layer(deduped_name).await.unwrap().unwrap();
+        assert_eq!(
+            store
+                .retrieve_layer_stack_names(top_name)
+                .await
+                .unwrap()
+                .len()
+                - 1,
+            store
+                .retrieve_layer_stack_names(deduped_name)
+                .await
+                .unwrap()
+                .len()
+        );
+
+        let triples = |layer: &InternalLayer| -> Vec<_> {
+            layer
+                .triples()
+                .map(|t| layer.id_triple_to_string(&t).unwrap())
+                .collect()
+        };
+        assert_eq!(triples(&top), triples(&deduped));
+
+        assert_eq!(deduped_name, store.dedup_chain(deduped_name).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn dedup_chain_keeps_empty_child_with_metadata() {
+        let store = MemoryLayerStore::new();
+        let mut builder = store.create_base_layer().await.unwrap();
+        let base_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.commit_boxed().await.unwrap();
+
+        let mut builder = store.create_child_layer(base_name).await.unwrap();
+        let child_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("duck", "likes", "cow"));
+        builder.commit_boxed().await.unwrap();
+
+        let mut builder = store.create_child_layer(child_name).await.unwrap();
+        let top_name = builder.name();
+        builder.set_metadata(Bytes::from_static(b"checkpoint"));
+        builder.commit_boxed().await.unwrap();
+
+        assert_eq!(top_name, store.dedup_chain(top_name).await.unwrap());
+        let top = store.get_layer(top_name).await.unwrap().unwrap();
+        assert_eq!(Some(Bytes::from_static(b"checkpoint")), top.metadata());
+    }
+
+    #[tokio::test]
+    async fn memory_file_of_missing_layer_errors() {
+        let store = MemoryLayerStore::new();
+
+        let error = store
+            .get_file([1, 2, 3, 4, 5], FILENAMES.parent)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            Some(StorageError::LayerNotFound),
+            StorageError::from_io_error(&error)
+        );
+    }
+
     #[tokio::test]
     async fn memory_update_label_succeeds() {
         let store = MemoryLabelStore::new();
@@ -408,11 +721,11 @@ mod tests {
             .await
             .unwrap()
             .is_some());
-        assert!(store
-            .set_label(&foo, [1, 1, 1, 1, 1])
-            .await
-            .unwrap()
-            .is_none());
+        let error = store.set_label(&foo, [1, 1, 1, 1, 1]).await.err().unwrap();
+        assert_eq!(
+            Some(StorageError::VersionConflict),
+            StorageError::from_io_error(&error)
+        );
     }
 
     #[tokio::test]
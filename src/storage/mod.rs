@@ -32,13 +32,22 @@ mod layer;
 pub mod archive;
 mod copy;
 pub mod delta;
+mod error;
+mod history;
 mod locking;
 pub mod memory;
 pub mod pack;
+mod read_ahead;
+mod stats;
 
 pub use cache::*;
+pub use copy::*;
 pub use delta::*;
+pub use error::*;
 pub use file::*;
+pub use history::*;
 pub use label::*;
 pub use layer::*;
 pub use pack::*;
+pub use read_ahead::*;
+pub use stats::*;
@@ -5,11 +5,18 @@ use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
+use bytes::Bytes;
 
 use super::cache::*;
 use super::consts::*;
+use super::error::StorageError;
 use super::file::*;
 use super::layer::*;
+use crate::layer::{
+    IdTriple, InternalLayerTripleSubjectIterator, InternalTripleSubjectIterator,
+    OptInternalLayerTripleSubjectIterator,
+};
+use tdb_succinct::{AdjacencyList, LogArray, MonotonicLogArray};
 
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
@@ -157,10 +164,7 @@ async fn tar_append_file<S: PersistentLayerStore, W: io::Write>(
 
         Ok(())
     } else {
-        Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "file does not exist",
-        ))
+        Err(StorageError::FileNotFound.into())
     }
 }
 
@@ -208,8 +212,12 @@ async fn tar_append_layer<W: io::Write, S: PersistentLayerStore>(
             .unwrap()
     });
 
-    for f in &SHARED_REQUIRED_FILES {
-        tar_append_file(store, tar, layer, &path, f, mtime).await?;
+    let is_child = store.file_exists(layer, FILENAMES.parent).await?;
+    // child layers may share their parent's dictionaries, in which case they have none of their own
+    if !store.layer_has_shared_dictionaries(layer).await? {
+        for f in &SHARED_REQUIRED_FILES {
+            tar_append_file(store, tar, layer, &path, f, mtime).await?;
+        }
     }
     for f in &SHARED_OPTIONAL_FILES {
         if f == &FILENAMES.rollup {
@@ -218,7 +226,7 @@ async fn tar_append_layer<W: io::Write, S: PersistentLayerStore>(
         }
         tar_append_file_if_exists(store, tar, layer, &path, f, mtime).await?;
     }
-    if store.file_exists(layer, FILENAMES.parent).await? {
+    if is_child {
         // this is a child layer
         for f in &CHILD_LAYER_REQUIRED_FILES {
             tar_append_file(store, tar, layer, &path, f, mtime).await?;
@@ -304,6 +312,223 @@ pub fn pack_layer_parents<R: io::Read>(
     Ok(result_map)
 }
 
+/// The files `PackReader` keeps of each layer, which are all it needs to iterate triples.
+const PACK_READER_FILES: [&str; 28] = [
+    FILENAMES.parent,
+    FILENAMES.base_subjects,
+    FILENAMES.base_s_p_adjacency_list_nums,
+    FILENAMES.base_s_p_adjacency_list_bits,
+    FILENAMES.base_s_p_adjacency_list_bit_index_blocks,
+    FILENAMES.base_s_p_adjacency_list_bit_index_sblocks,
+    FILENAMES.base_sp_o_adjacency_list_nums,
+    FILENAMES.base_sp_o_adjacency_list_bits,
+    FILENAMES.base_sp_o_adjacency_list_bit_index_blocks,
+    FILENAMES.base_sp_o_adjacency_list_bit_index_sblocks,
+    FILENAMES.pos_subjects,
+    FILENAMES.pos_s_p_adjacency_list_nums,
+    FILENAMES.pos_s_p_adjacency_list_bits,
+    FILENAMES.pos_s_p_adjacency_list_bit_index_blocks,
+    FILENAMES.pos_s_p_adjacency_list_bit_index_sblocks,
+    FILENAMES.pos_sp_o_adjacency_list_nums,
+    FILENAMES.pos_sp_o_adjacency_list_bits,
+    FILENAMES.pos_sp_o_adjacency_list_bit_index_blocks,
+    FILENAMES.pos_sp_o_adjacency_list_bit_index_sblocks,
+    FILENAMES.neg_subjects,
+    FILENAMES.neg_s_p_adjacency_list_nums,
+    FILENAMES.neg_s_p_adjacency_list_bits,
+    FILENAMES.neg_s_p_adjacency_list_bit_index_blocks,
+    FILENAMES.neg_s_p_adjacency_list_bit_index_sblocks,
+    FILENAMES.neg_sp_o_adjacency_list_nums,
+    FILENAMES.neg_sp_o_adjacency_list_bits,
+    FILENAMES.neg_sp_o_adjacency_list_bit_index_blocks,
+    FILENAMES.neg_sp_o_adjacency_list_bit_index_sblocks,
+];
+
+/// Read-only access to the layers in a pack, without importing them into a store.
+pub struct PackReader {
+    layers: HashMap<[u32; 5], HashMap<String, Bytes>>,
+}
+
+impl PackReader {
+    /// Read the triple index files of all layers in the given pack into memory.
+    ///
+    /// Dictionaries and every other file that isn't needed to iterate
+    /// triples are skipped without being decompressed into memory.
+    pub fn new(pack: &[u8]) -> io::Result<Self> {
+        let tar = GzDecoder::new(io::Cursor::new(pack));
+        let mut archive = Archive::new(tar);
+
+        let mut layers: HashMap<[u32; 5], HashMap<String, Bytes>> = HashMap::new();
+        for e in archive.entries()? {
+            let mut entry = e?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let path = entry.path()?.into_owned();
+            let (layer_id, file_name) = match (
+                path.iter().next().and_then(|p| p.to_str()),
+                path.file_name().and_then(|f| f.to_str()),
+            ) {
+                (Some(layer_id), Some(file_name)) => (string_to_name(layer_id)?, file_name),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "unexpected path in pack",
+                    ))
+                }
+            };
+
+            let files = layers.entry(layer_id).or_default();
+            if !PACK_READER_FILES.contains(&file_name) {
+                continue;
+            }
+
+            let mut content = Vec::with_capacity(entry.header().size()? as usize);
+            entry.read_to_end(&mut content)?;
+            files.insert(file_name.to_owned(), Bytes::from(content));
+        }
+
+        Ok(Self { layers })
+    }
+
+    /// Returns the names of all layers in the pack.
+    pub fn layer_names(&self) -> Vec<[u32; 5]> {
+        self.layers.keys().cloned().collect()
+    }
+
+    /// Iterate over the triples of the given layer, ordered by subject, predicate and object.
+    ///
+    /// Only the subject indexes of the layer and its ancestors are
+    /// parsed. All ancestors of the layer have to be in the pack. If
+    /// the parents of the layers in the pack form a cycle, this fails
+    /// with `io::ErrorKind::InvalidData`.
+    pub fn layer_triples(&self, name: [u32; 5]) -> io::Result<impl Iterator<Item = IdTriple>> {
+        let mut positives = Vec::new();
+        let mut negatives = Vec::new();
+
+        let mut visited = HashSet::new();
+        let mut current = Some(name);
+        while let Some(name) = current {
+            if !visited.insert(name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "cycle in the layer parents of the pack",
+                ));
+            }
+
+            let files = self.layers.get(&name).ok_or(StorageError::LayerNotFound)?;
+
+            match files.get(FILENAMES.parent) {
+                Some(parent) => {
+                    positives.push(pack_subject_iterator(
+                        files,
+                        FILENAMES.pos_subjects,
+                        [
+                            FILENAMES.pos_s_p_adjacency_list_nums,
+                            FILENAMES.pos_s_p_adjacency_list_bits,
+                            FILENAMES.pos_s_p_adjacency_list_bit_index_blocks,
+                            FILENAMES.pos_s_p_adjacency_list_bit_index_sblocks,
+                        ],
+                        [
+                            FILENAMES.pos_sp_o_adjacency_list_nums,
+                            FILENAMES.pos_sp_o_adjacency_list_bits,
+                            FILENAMES.pos_sp_o_adjacency_list_bit_index_blocks,
+                            FILENAMES.pos_sp_o_adjacency_list_bit_index_sblocks,
+                        ],
+                    )?);
+                    negatives.push(pack_subject_iterator(
+                        files,
+                        FILENAMES.neg_subjects,
+                        [
+                            FILENAMES.neg_s_p_adjacency_list_nums,
+                            FILENAMES.neg_s_p_adjacency_list_bits,
+                            FILENAMES.neg_s_p_adjacency_list_bit_index_blocks,
+                            FILENAMES.neg_s_p_adjacency_list_bit_index_sblocks,
+                        ],
+                        [
+                            FILENAMES.neg_sp_o_adjacency_list_nums,
+                            FILENAMES.neg_sp_o_adjacency_list_bits,
+                            FILENAMES.neg_sp_o_adjacency_list_bit_index_blocks,
+                            FILENAMES.neg_sp_o_adjacency_list_bit_index_sblocks,
+                        ],
+                    )?);
+
+                    let parent = std::str::from_utf8(parent)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    current = Some(string_to_name(parent)?);
+                }
+                None => {
+                    positives.push(pack_subject_iterator(
+                        files,
+                        FILENAMES.base_subjects,
+                        [
+                            FILENAMES.base_s_p_adjacency_list_nums,
+                            FILENAMES.base_s_p_adjacency_list_bits,
+                            FILENAMES.base_s_p_adjacency_list_bit_index_blocks,
+                            FILENAMES.base_s_p_adjacency_list_bit_index_sblocks,
+                        ],
+                        [
+                            FILENAMES.base_sp_o_adjacency_list_nums,
+                            FILENAMES.base_sp_o_adjacency_list_bits,
+                            FILENAMES.base_sp_o_adjacency_list_bit_index_blocks,
+                            FILENAMES.base_sp_o_adjacency_list_bit_index_sblocks,
+                        ],
+                    )?);
+                    negatives.push(OptInternalLayerTripleSubjectIterator(None));
+
+                    current = None;
+                }
+            }
+        }
+
+        Ok(InternalTripleSubjectIterator::from_parts(
+            positives, negatives,
+        ))
+    }
+}
+
+fn pack_file(files: &HashMap<String, Bytes>, file_name: &str) -> io::Result<Bytes> {
+    files
+        .get(file_name)
+        .cloned()
+        .ok_or_else(|| StorageError::FileNotFound.into())
+}
+
+fn pack_adjacency_list(
+    files: &HashMap<String, Bytes>,
+    [nums, bits, blocks, sblocks]: [&str; 4],
+) -> io::Result<AdjacencyList> {
+    Ok(AdjacencyList::parse(
+        pack_file(files, nums)?,
+        pack_file(files, bits)?,
+        pack_file(files, blocks)?,
+        pack_file(files, sblocks)?,
+    ))
+}
+
+fn pack_subject_iterator(
+    files: &HashMap<String, Bytes>,
+    subjects_file: &str,
+    s_p_files: [&str; 4],
+    sp_o_files: [&str; 4],
+) -> io::Result<OptInternalLayerTripleSubjectIterator> {
+    let subjects = match files.get(subjects_file) {
+        Some(map) => Some(MonotonicLogArray::from_logarray(
+            LogArray::parse(map.clone()).map_err(|_| StorageError::Corrupt)?,
+        )),
+        None => None,
+    };
+
+    Ok(OptInternalLayerTripleSubjectIterator(Some(
+        InternalLayerTripleSubjectIterator::new(
+            subjects,
+            pack_adjacency_list(files, s_p_files)?,
+            pack_adjacency_list(files, sp_o_files)?,
+        ),
+    )))
+}
+
 #[async_trait]
 impl Packable for CachedLayerStore {
     async fn export_layers(
@@ -327,6 +552,7 @@ mod tests {
     use super::*;
     use crate::layer::*;
     use crate::storage::directory::*;
+    use crate::storage::memory::*;
     use std::sync::Arc;
     use tempfile::tempdir;
 
@@ -380,4 +606,118 @@ mod tests {
             triples
         );
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn iterate_layer_triples_from_pack() {
+        let dir = tempdir().unwrap();
+        let store = Arc::new(DirectoryLayerStore::new(dir.path()));
+
+        let mut builder = store.create_base_layer().await.unwrap();
+        let base_name = builder.name();
+
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.add_value_triple(ValueTriple::new_node("duck", "hates", "cow"));
+        builder.add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"));
+
+        builder.commit_boxed().await.unwrap();
+
+        let mut builder = store.create_child_layer(base_name).await.unwrap();
+        let child_name = builder.name();
+
+        builder.remove_value_triple(ValueTriple::new_node("duck", "hates", "cow"));
+        builder.add_value_triple(ValueTriple::new_node("duck", "likes", "cow"));
+
+        builder.commit_boxed().await.unwrap();
+
+        let export = store
+            .export_layers(Box::new(vec![base_name, child_name].into_iter()))
+            .await
+            .unwrap();
+        let reader = PackReader::new(&export).unwrap();
+
+        let base_layer = store.get_layer(base_name).await.unwrap().unwrap();
+        let child_layer = store.get_layer(child_name).await.unwrap().unwrap();
+        assert_eq!(
+            base_layer.triples().collect::<Vec<_>>(),
+            reader.layer_triples(base_name).unwrap().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            child_layer.triples().collect::<Vec<_>>(),
+            reader
+                .layer_triples(child_name)
+                .unwrap()
+                .collect::<Vec<_>>()
+        );
+
+        let export = store
+            .export_layers(Box::new(vec![child_name].into_iter()))
+            .await
+            .unwrap();
+        let reader = PackReader::new(&export).unwrap();
+        assert_eq!(vec![child_name], reader.layer_names());
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            reader.layer_triples(child_name).err().unwrap().kind()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pack_reader_keeps_only_triple_index_files() {
+        let store = MemoryLayerStore::new();
+        let mut builder = store.create_base_layer().await.unwrap();
+        let base_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.commit_boxed().await.unwrap();
+
+        let export = store
+            .export_layers(Box::new(vec![base_name].into_iter()))
+            .await
+            .unwrap();
+        let reader = PackReader::new(&export).unwrap();
+
+        let files = &reader.layers[&base_name];
+        assert!(files.contains_key(FILENAMES.base_subjects));
+        assert!(!files.contains_key(FILENAMES.node_dictionary_blocks));
+        assert!(files
+            .keys()
+            .all(|file| PACK_READER_FILES.contains(&file.as_str())));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pack_reader_detects_parent_cycle() {
+        let store = MemoryLayerStore::new();
+        let mut builder = store.create_base_layer().await.unwrap();
+        let base_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("cow", "likes", "duck"));
+        builder.commit_boxed().await.unwrap();
+
+        let mut builder = store.create_child_layer(base_name).await.unwrap();
+        let child1_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("duck", "likes", "cow"));
+        builder.commit_boxed().await.unwrap();
+
+        let mut builder = store.create_child_layer(child1_name).await.unwrap();
+        let child2_name = builder.name();
+        builder.add_value_triple(ValueTriple::new_node("pig", "likes", "cow"));
+        builder.commit_boxed().await.unwrap();
+
+        let export = store
+            .export_layers(Box::new(
+                vec![base_name, child1_name, child2_name].into_iter(),
+            ))
+            .await
+            .unwrap();
+        let mut reader = PackReader::new(&export).unwrap();
+
+        // point the first child at the second one, closing a cycle
+        reader.layers.get_mut(&child1_name).unwrap().insert(
+            FILENAMES.parent.to_owned(),
+            Bytes::from(name_to_string(child2_name)),
+        );
+
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            reader.layer_triples(child2_name).err().unwrap().kind()
+        );
+    }
 }
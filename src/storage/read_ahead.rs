@@ -0,0 +1,197 @@
+//! Buffered reading for files that are streamed from start to end.
+use std::io;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::Stream;
+use tokio::io::BufReader;
+
+use super::file::*;
+
+/// The amount of bytes read ahead when streaming the triples of a layer.
+pub const TRIPLE_STREAM_READ_AHEAD: usize = 1 << 16;
+
+/// A file whose readers read ahead in chunks of a fixed size.
+///
+/// Streaming a structure off a file backend otherwise results in a
+/// read on the underlying file for every small piece that is
+/// decoded. A capacity of 0 disables read-ahead.
+#[derive(Clone)]
+pub struct ReadAheadFile<F> {
+    inner: F,
+    capacity: usize,
+}
+
+impl<F> ReadAheadFile<F> {
+    pub fn new(inner: F, capacity: usize) -> Self {
+        Self { inner, capacity }
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<F: FileLoad> FileLoad for ReadAheadFile<F> {
+    type Read = BufReader<F::Read>;
+
+    async fn exists(&self) -> io::Result<bool> {
+        self.inner.exists().await
+    }
+
+    async fn size(&self) -> io::Result<usize> {
+        self.inner.size().await
+    }
+
+    async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+        let reader = self.inner.open_read_from(offset).await?;
+
+        Ok(BufReader::with_capacity(self.capacity, reader))
+    }
+
+    async fn map(&self) -> io::Result<Bytes> {
+        self.inner.map().await
+    }
+
+    async fn map_if_exists(&self) -> io::Result<Option<Bytes>> {
+        self.inner.map_if_exists().await
+    }
+}
+
+#[async_trait]
+impl<F: FileStore> FileStore for ReadAheadFile<F> {
+    type Write = F::Write;
+
+    async fn open_write(&self) -> io::Result<Self::Write> {
+        self.inner.open_write().await
+    }
+}
+
+/// Streams the pairs of an adjacency list, reading `read_ahead` bytes from each file at a time.
+pub async fn adjacency_list_stream_pairs_with_read_ahead<F: 'static + FileLoad + FileStore>(
+    bits_file: F,
+    nums_file: F,
+    read_ahead: usize,
+) -> io::Result<impl Stream<Item = io::Result<(u64, u64)>> + Unpin + Send> {
+    tdb_succinct::adjacency_list_stream_pairs(
+        ReadAheadFile::new(bits_file, read_ahead),
+        ReadAheadFile::new(nums_file, read_ahead),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::*;
+    use crate::storage::memory::*;
+    use futures::TryStreamExt;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    #[derive(Clone)]
+    struct CountingFile {
+        inner: MemoryBackedStore,
+        reads: Arc<AtomicUsize>,
+    }
+
+    struct CountingReader<R> {
+        inner: R,
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    #[async_trait]
+    impl FileLoad for CountingFile {
+        type Read = CountingReader<<MemoryBackedStore as FileLoad>::Read>;
+
+        async fn exists(&self) -> io::Result<bool> {
+            self.inner.exists().await
+        }
+
+        async fn size(&self) -> io::Result<usize> {
+            self.inner.size().await
+        }
+
+        async fn open_read_from(&self, offset: usize) -> io::Result<Self::Read> {
+            Ok(CountingReader {
+                inner: self.inner.open_read_from(offset).await?,
+                reads: self.reads.clone(),
+            })
+        }
+
+        async fn map(&self) -> io::Result<Bytes> {
+            self.inner.map().await
+        }
+    }
+
+    #[async_trait]
+    impl FileStore for CountingFile {
+        type Write = <MemoryBackedStore as FileStore>::Write;
+
+        async fn open_write(&self) -> io::Result<Self::Write> {
+            self.inner.open_write().await
+        }
+    }
+
+    async fn stream_counting_reads(
+        files: &AdjacencyListFiles<MemoryBackedStore>,
+        read_ahead: usize,
+    ) -> (Vec<(u64, u64)>, usize) {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let counting = |file: &MemoryBackedStore| CountingFile {
+            inner: file.clone(),
+            reads: reads.clone(),
+        };
+
+        let pairs = adjacency_list_stream_pairs_with_read_ahead(
+            counting(&files.bitindex_files.bits_file),
+            counting(&files.nums_file),
+            read_ahead,
+        )
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+
+        (pairs, reads.load(Ordering::Relaxed))
+    }
+
+    #[tokio::test]
+    async fn read_ahead_reduces_reads() {
+        let files = base_layer_memory_files();
+        let mut builder = SimpleLayerBuilder::new([1, 2, 3, 4, 5], files.clone());
+        for i in 0..50_000 {
+            builder.add_value_triple(ValueTriple::new_node(
+                &format!("s{}", i / 3),
+                "p",
+                &format!("o{}", i),
+            ));
+        }
+        builder.commit().await.unwrap();
+
+        let (unbuffered_pairs, unbuffered_reads) =
+            stream_counting_reads(&files.sp_o_adjacency_list_files, 0).await;
+        let (buffered_pairs, buffered_reads) =
+            stream_counting_reads(&files.sp_o_adjacency_list_files, 1 << 16).await;
+
+        assert_eq!(50_000, unbuffered_pairs.len());
+        assert_eq!(unbuffered_pairs, buffered_pairs);
+        assert!(buffered_reads < unbuffered_reads);
+    }
+}
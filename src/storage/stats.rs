@@ -0,0 +1,68 @@
+//! Summary counts for layers that can be read without loading the layer.
+use std::io;
+
+use crate::layer::Layer;
+
+/// The size of an encoded `LayerStats`.
+pub const LAYER_STATS_SIZE: usize = 32;
+
+/// Counts for a layer and all its ancestors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LayerStats {
+    pub node_count: u64,
+    pub predicate_count: u64,
+    pub value_count: u64,
+    pub triple_count: u64,
+}
+
+impl LayerStats {
+    /// Collect the stats of a loaded layer.
+    pub fn from_layer(layer: &dyn Layer) -> Self {
+        let counts = layer.all_counts();
+
+        Self {
+            node_count: counts.node_count as u64,
+            predicate_count: counts.predicate_count as u64,
+            value_count: counts.value_count as u64,
+            triple_count: layer.triple_count() as u64,
+        }
+    }
+
+    /// Encode the stats as four big-endian u64s.
+    pub fn to_bytes(&self) -> [u8; LAYER_STATS_SIZE] {
+        let mut result = [0; LAYER_STATS_SIZE];
+        result[0..8].copy_from_slice(&self.node_count.to_be_bytes());
+        result[8..16].copy_from_slice(&self.predicate_count.to_be_bytes());
+        result[16..24].copy_from_slice(&self.value_count.to_be_bytes());
+        result[24..32].copy_from_slice(&self.triple_count.to_be_bytes());
+
+        result
+    }
+
+    /// Decode stats as written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() != LAYER_STATS_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "expected stats file to be {} bytes but it was {}",
+                    LAYER_STATS_SIZE,
+                    bytes.len()
+                ),
+            ));
+        }
+
+        let read_u64 = |offset: usize| {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(&bytes[offset..offset + 8]);
+            u64::from_be_bytes(buf)
+        };
+
+        Ok(Self {
+            node_count: read_u64(0),
+            predicate_count: read_u64(8),
+            value_count: read_u64(16),
+            triple_count: read_u64(24),
+        })
+    }
+}
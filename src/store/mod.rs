@@ -6,12 +6,17 @@ pub mod sync;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 
-use crate::layer::{IdTriple, Layer, LayerBuilder, LayerCounts, ObjectType, ValueTriple};
+use crate::layer::{
+    IdTriple, Layer, LayerBuilder, LayerCounts, ObjectType, TripleChange, ValueTriple,
+};
 use crate::storage::archive::{ArchiveLayerStore, DirectoryArchiveBackend, LruArchiveBackend};
 use crate::storage::directory::{DirectoryLabelStore, DirectoryLayerStore};
 use crate::storage::memory::{MemoryLabelStore, MemoryLayerStore};
-use crate::storage::{CachedLayerStore, LabelStore, LayerStore, LockingHashMapLayerCache};
-use tdb_succinct::TypedDictEntry;
+use crate::storage::{
+    CachedLayerStore, Label, LabelStore, LayerStore, LockingHashMapLayerCache, StorageError,
+};
+use bytes::Bytes;
+use tdb_succinct::{Datatype, SizedDictEntry, TypedDict, TypedDictEntry};
 
 use std::io;
 
@@ -40,6 +45,7 @@ pub struct StoreLayerBuilder {
     builder: Arc<RwLock<Option<Box<dyn LayerBuilder>>>>,
     name: [u32; 5],
     store: Store,
+    max_depth: Option<usize>,
 }
 
 impl StoreLayerBuilder {
@@ -51,6 +57,7 @@ impl StoreLayerBuilder {
             name: builder.name(),
             builder: Arc::new(RwLock::new(Some(builder))),
             store,
+            max_depth: None,
         })
     }
 
@@ -60,6 +67,7 @@ impl StoreLayerBuilder {
             name: builder.name(),
             builder: Arc::new(RwLock::new(Some(builder))),
             store,
+            max_depth: None,
         }
     }
 
@@ -112,6 +120,11 @@ impl StoreLayerBuilder {
         self.with_builder(move |b| b.remove_id_triple(triple))
     }
 
+    /// Set a metadata blob to store alongside the layer.
+    pub fn set_metadata(&self, metadata: Bytes) -> Result<(), io::Error> {
+        self.with_builder(move |b| b.set_metadata(metadata))
+    }
+
     /// Returns true if this layer has been committed, and false otherwise.
     pub fn committed(&self) -> bool {
         self.builder
@@ -121,6 +134,10 @@ impl StoreLayerBuilder {
     }
 
     /// Commit the layer to storage without loading the resulting layer.
+    ///
+    /// If this builder was opened with `open_write_with_auto_rollup`
+    /// and the new layer's stack is too deep, the layer is loaded and
+    /// rolled up after it is stored.
     pub async fn commit_no_load(&self) -> io::Result<()> {
         let mut builder = None;
         {
@@ -143,7 +160,22 @@ impl StoreLayerBuilder {
             Some(builder) => {
                 let id = builder.name();
                 builder.commit_boxed().await?;
-                self.store.layer_store.finalize_layer(id).await
+                self.store.layer_store.finalize_layer(id).await?;
+
+                if let Some(max_depth) = self.max_depth {
+                    let layer = self
+                        .store
+                        .layer_store
+                        .get_layer(id)
+                        .await?
+                        .ok_or(StorageError::LayerNotFound)?;
+                    self.store
+                        .layer_store
+                        .rollup_if_deeper_than(layer, max_depth)
+                        .await?;
+                }
+
+                Ok(())
             }
         }
     }
@@ -258,6 +290,21 @@ impl StoreLayer {
         Ok(StoreLayerBuilder::wrap(layer, self.store.clone()))
     }
 
+    /// Create a layer builder based on this layer, which keeps the layer stack at most `max_depth` deep.
+    ///
+    /// When the committed layer's stack is deeper than `max_depth`,
+    /// it is rolled up onto the bottom layer of the stack. See
+    /// `LayerStore::rollup_if_deeper_than`.
+    pub async fn open_write_with_auto_rollup(
+        &self,
+        max_depth: usize,
+    ) -> io::Result<StoreLayerBuilder> {
+        let mut builder = self.open_write().await?;
+        builder.max_depth = Some(max_depth);
+
+        Ok(builder)
+    }
+
     /// Returns the parent of this layer, if any, or None if this layer has no parent.
     pub async fn parent(&self) -> io::Result<Option<StoreLayer>> {
         let parent_name = self.layer.parent_name();
@@ -664,6 +711,10 @@ impl Layer for StoreLayer {
         self.layer.triple_removal_count()
     }
 
+    fn stack_has_removals(&self) -> bool {
+        self.layer.stack_has_removals()
+    }
+
     fn all_counts(&self) -> LayerCounts {
         self.layer.all_counts()
     }
@@ -671,6 +722,55 @@ impl Layer for StoreLayer {
     fn single_triple_sp(&self, subject: u64, predicate: u64) -> Option<IdTriple> {
         self.layer.single_triple_sp(subject, predicate)
     }
+
+    fn local_changes(&self) -> Box<dyn Iterator<Item = (TripleChange, IdTriple)> + Send> {
+        self.layer.local_changes()
+    }
+
+    fn triples_sp_from(
+        &self,
+        subject: u64,
+        predicate: u64,
+        object_start: u64,
+    ) -> Box<dyn Iterator<Item = IdTriple> + Send> {
+        self.layer.triples_sp_from(subject, predicate, object_start)
+    }
+
+    fn provenance_of(&self, triple: IdTriple) -> Option<[u32; 5]> {
+        self.layer.provenance_of(triple)
+    }
+
+    fn stack_depth(&self) -> usize {
+        self.layer.stack_depth()
+    }
+
+    fn triples_object_order(&self) -> Box<dyn Iterator<Item = IdTriple> + Send> {
+        self.layer.triples_object_order()
+    }
+
+    fn datatypes_present(&self) -> Vec<Datatype> {
+        self.layer.datatypes_present()
+    }
+
+    fn value_objects(&self) -> Box<dyn Iterator<Item = (u64, Datatype, SizedDictEntry)> + Send> {
+        self.layer.value_objects()
+    }
+
+    fn predicates(&self) -> Box<dyn Iterator<Item = (u64, String)> + Send> {
+        self.layer.predicates()
+    }
+
+    fn value_dictionary_handle(&self) -> TypedDict {
+        self.layer.value_dictionary_handle()
+    }
+
+    fn metadata(&self) -> Option<Bytes> {
+        self.layer.metadata()
+    }
+
+    fn subject_position(&self, subject: u64) -> Option<usize> {
+        self.layer.subject_position(subject)
+    }
 }
 
 /// A named graph in terminus-store.
@@ -751,12 +851,7 @@ impl NamedGraph {
         };
 
         if set_is_ok {
-            Ok(self
-                .store
-                .label_store
-                .set_label(&label, layer_name)
-                .await?
-                .is_some())
+            label_was_updated(self.store.label_store.set_label(&label, layer_name).await)
         } else {
             Ok(false)
         }
@@ -776,13 +871,9 @@ impl NamedGraph {
             match label {
                 None => return Err(io::Error::new(io::ErrorKind::NotFound, "label not found")),
                 Some(label) => {
-                    if self
-                        .store
-                        .label_store
-                        .set_label(&label, layer_name)
-                        .await?
-                        .is_some()
-                    {
+                    if label_was_updated(
+                        self.store.label_store.set_label(&label, layer_name).await,
+                    )? {
                         return Ok(());
                     }
                 }
@@ -804,12 +895,7 @@ impl NamedGraph {
                 if label.version != version {
                     Ok(false)
                 } else {
-                    Ok(self
-                        .store
-                        .label_store
-                        .set_label(&label, layer_name)
-                        .await?
-                        .is_some())
+                    label_was_updated(self.store.label_store.set_label(&label, layer_name).await)
                 }
             }
         }
@@ -820,6 +906,20 @@ impl NamedGraph {
     }
 }
 
+/// Turns the result of a label update into whether the update happened.
+///
+/// A label that was changed concurrently is not an error here, it
+/// just means the update did not happen.
+fn label_was_updated(result: io::Result<Option<Label>>) -> io::Result<bool> {
+    match result {
+        Ok(label) => Ok(label.is_some()),
+        Err(e) if StorageError::from_io_error(&e) == Some(StorageError::VersionConflict) => {
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
 impl Store {
     /// Create a new store from the given label and layer store.
     pub fn new<Labels: 'static + LabelStore, Layers: 'static + LayerStore>(
@@ -1024,6 +1124,36 @@ mod tests {
         assert!(layer2.value_triple_exists(&ValueTriple::new_string_value("cow", "says", "moo")));
     }
 
+    #[tokio::test]
+    async fn auto_rollup_bounds_stack_depth() {
+        let store = open_memory_store();
+        let builder = store.create_base_layer().await.unwrap();
+        builder
+            .add_value_triple(ValueTriple::new_string_value("cow", "says", "moo"))
+            .unwrap();
+        let mut layer = builder.commit().await.unwrap();
+
+        for i in 0..6 {
+            let builder = layer.open_write_with_auto_rollup(3).await.unwrap();
+            builder
+                .add_value_triple(ValueTriple::new_node("cow", "likes", &format!("pig{}", i)))
+                .unwrap();
+            layer = builder.commit().await.unwrap();
+
+            assert!(layer.stack_depth() <= 3);
+        }
+
+        assert_eq!(7, layer.triple_count());
+        assert!(layer.value_triple_exists(&ValueTriple::new_string_value("cow", "says", "moo")));
+        for i in 0..6 {
+            assert!(layer.value_triple_exists(&ValueTriple::new_node(
+                "cow",
+                "likes",
+                &format!("pig{}", i)
+            )));
+        }
+    }
+
     #[tokio::test]
     async fn commit_builder_makes_builder_committed() {
         let store = open_memory_store();
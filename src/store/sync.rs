@@ -11,11 +11,14 @@ use tokio::runtime::Runtime;
 use std::io;
 use std::path::{Path, PathBuf};
 
-use crate::layer::{IdTriple, Layer, LayerBuilder, LayerCounts, ObjectType, ValueTriple};
+use crate::layer::{
+    IdTriple, Layer, LayerBuilder, LayerCounts, ObjectType, TripleChange, ValueTriple,
+};
 use crate::store::{
     open_directory_store, open_memory_store, NamedGraph, Store, StoreLayer, StoreLayerBuilder,
 };
-use tdb_succinct::TypedDictEntry;
+use bytes::Bytes;
+use tdb_succinct::{Datatype, SizedDictEntry, TypedDict, TypedDictEntry};
 
 use super::{open_archive_store, open_raw_archive_store};
 
@@ -83,6 +86,11 @@ impl SyncStoreLayerBuilder {
         self.inner.remove_id_triple(triple)
     }
 
+    /// Set a metadata blob to store alongside the layer.
+    pub fn set_metadata(&self, metadata: Bytes) -> Result<(), io::Error> {
+        self.inner.set_metadata(metadata)
+    }
+
     /// Returns a boolean result which is true if this builder has been committed, and false otherwise.
     pub fn committed(&self) -> bool {
         self.inner.committed()
@@ -452,6 +460,10 @@ impl Layer for SyncStoreLayer {
         self.inner.triple_removal_count()
     }
 
+    fn stack_has_removals(&self) -> bool {
+        self.inner.stack_has_removals()
+    }
+
     fn all_counts(&self) -> LayerCounts {
         self.inner.all_counts()
     }
@@ -459,6 +471,55 @@ impl Layer for SyncStoreLayer {
     fn single_triple_sp(&self, subject: u64, predicate: u64) -> Option<IdTriple> {
         self.inner.single_triple_sp(subject, predicate)
     }
+
+    fn local_changes(&self) -> Box<dyn Iterator<Item = (TripleChange, IdTriple)> + Send> {
+        self.inner.local_changes()
+    }
+
+    fn triples_sp_from(
+        &self,
+        subject: u64,
+        predicate: u64,
+        object_start: u64,
+    ) -> Box<dyn Iterator<Item = IdTriple> + Send> {
+        self.inner.triples_sp_from(subject, predicate, object_start)
+    }
+
+    fn provenance_of(&self, triple: IdTriple) -> Option<[u32; 5]> {
+        self.inner.provenance_of(triple)
+    }
+
+    fn stack_depth(&self) -> usize {
+        self.inner.stack_depth()
+    }
+
+    fn triples_object_order(&self) -> Box<dyn Iterator<Item = IdTriple> + Send> {
+        self.inner.triples_object_order()
+    }
+
+    fn datatypes_present(&self) -> Vec<Datatype> {
+        self.inner.datatypes_present()
+    }
+
+    fn value_objects(&self) -> Box<dyn Iterator<Item = (u64, Datatype, SizedDictEntry)> + Send> {
+        self.inner.value_objects()
+    }
+
+    fn predicates(&self) -> Box<dyn Iterator<Item = (u64, String)> + Send> {
+        self.inner.predicates()
+    }
+
+    fn value_dictionary_handle(&self) -> TypedDict {
+        self.inner.value_dictionary_handle()
+    }
+
+    fn metadata(&self) -> Option<Bytes> {
+        self.inner.metadata()
+    }
+
+    fn subject_position(&self, subject: u64) -> Option<usize> {
+        self.inner.subject_position(subject)
+    }
 }
 
 /// A named graph in terminus-store.